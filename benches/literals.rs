@@ -0,0 +1,20 @@
+use std::time::Instant;
+
+use rlox::interpreter::Interpreter;
+use rlox::parser::Parser;
+use rlox::scanner::Scanner;
+
+// Literal-heavy program evaluated repeatedly, literals are decoded once at parse time
+fn main() {
+    let source = format!("var x = {}1;", "1 + 2 * 3 - 4 / 2 + ".repeat(50));
+    let mut scanner = Scanner::new(source);
+    let statements = Parser::new(scanner.scan_tokens()).parse().unwrap();
+    let mut interpreter = Interpreter::new();
+
+    let iterations = 10_000;
+    let start = Instant::now();
+    for _ in 0..iterations {
+        interpreter.interpret(statements.clone()).unwrap();
+    }
+    println!("literals: {:?} per iteration", start.elapsed() / iterations);
+}
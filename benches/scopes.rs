@@ -0,0 +1,31 @@
+use std::time::Instant;
+
+use rlox::interpreter::Interpreter;
+use rlox::parser::Parser;
+use rlox::scanner::Scanner;
+
+const DEPTH: u32 = 100;
+
+// Nested blocks under a growing number of globals. Entering a block pushes a
+// scope instead of cloning the chain, so the cost per block should stay flat.
+fn main() {
+    for globals in [10, 1_000, 10_000] {
+        let declarations = (0..globals).map(|n| format!("var g{} = {};", n, n)).collect::<String>();
+        let blocks = format!("var x = g0; {}{}", "{ var y = 1; ".repeat(DEPTH as usize), " x = x + y; }".repeat(DEPTH as usize));
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(parse(&declarations)).unwrap();
+        let statements = parse(&blocks);
+
+        let iterations = 200;
+        let start = Instant::now();
+        for _ in 0..iterations {
+            interpreter.interpret(statements.clone()).unwrap();
+        }
+        println!("scopes: {:>6} globals, {:?} per block", globals, start.elapsed() / (iterations * DEPTH));
+    }
+}
+
+fn parse(source: &str) -> Vec<rlox::statements::Stmt> {
+    let mut scanner = Scanner::new(source.to_string());
+    Parser::new(scanner.scan_tokens()).parse().unwrap()
+}
@@ -0,0 +1,73 @@
+use crate::parser::Parser;
+use crate::resolver::resolve;
+use crate::scanner::Scanner;
+use crate::statements::Stmt;
+
+// Caches are only trusted by the exact version that wrote them, the AST has no stable format
+const FORMAT: &str = concat!("rlox-ast ", env!("CARGO_PKG_VERSION"));
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct AstCache {
+    format: String,
+    statements: Vec<Stmt>,
+}
+
+// Scans, parses and checks a program, then serializes its AST with a version header
+pub fn emit_ast(source: String) -> Result<String, String> {
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens();
+    if let Some(err) = scanner.errors().first() {
+        return Err(err.to_string());
+    }
+
+    let statements = Parser::new(tokens).parse()?;
+    let program = resolve(statements).map_err(|errors| {
+        errors.iter().map(|err| err.to_string()).collect::<Vec<String>>().join("\n")
+    })?;
+    let cache = AstCache { format: FORMAT.to_string(), statements: program.statements };
+    serde_json::to_string(&cache).map_err(|err| err.to_string())
+}
+
+pub fn load_ast(serialized: &str) -> Result<Vec<Stmt>, String> {
+    let cache: AstCache = serde_json::from_str(serialized).map_err(|err| format!("Invalid AST cache: {}", err))?;
+    if cache.format != FORMAT {
+        return Err(format!("Stale AST cache: written by '{}', expected '{}'.", cache.format, FORMAT));
+    }
+    Ok(cache.statements)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::rlox::{run_source, Options};
+
+    #[test]
+    fn test_round_trip() {
+        let source = "var a = 1, b = \"two\";\n{ var c = a + 0o7; print c; print b + nil; }\nprint a > 0 ? .5 : -1;\nprint \"hi\".upper();\nassert a == 1;\nprint;";
+
+        let direct = Rc::new(RefCell::new(Vec::new()));
+        run_source(String::from(source), &Options::default(), direct.clone()).unwrap();
+
+        let statements = load_ast(&emit_ast(String::from(source)).unwrap()).unwrap();
+        let cached = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = cached.clone();
+        interpreter.interpret(statements).unwrap();
+
+        assert_eq!(cached.borrow().clone(), direct.borrow().clone());
+        assert_eq!(String::from_utf8(cached.borrow().clone()).unwrap(), "8\ntwonil\n0.5\nHI\n\n");
+    }
+
+    #[test]
+    fn test_rejects_stale_or_broken_caches() {
+        let serialized = emit_ast(String::from("print 1;")).unwrap();
+        let stale = serialized.replace(FORMAT, "rlox-ast 0.0.0");
+        assert_eq!(load_ast(&stale), Err(format!("Stale AST cache: written by 'rlox-ast 0.0.0', expected '{}'.", FORMAT)));
+        assert!(load_ast("not json").unwrap_err().starts_with("Invalid AST cache"));
+        assert_eq!(emit_ast(String::from("print 1")), Err(String::from("Expect ';' after expression.")));
+    }
+}
@@ -0,0 +1,70 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::environment::Value;
+use crate::function::LoxFunction;
+use crate::token::Token;
+
+// A `class` declaration. Its methods close over the scope it was declared in, with
+// `super` bound in between when there is a superclass.
+pub struct LoxClass {
+    pub name: Token,
+    pub superclass: Option<Rc<LoxClass>>,
+    pub methods: HashMap<String, Rc<LoxFunction>>,
+}
+
+impl LoxClass {
+    // The nearest definition wins, so a subclass overrides its superclasses
+    pub fn find_method(&self, name: &str) -> Option<Rc<LoxFunction>> {
+        match self.methods.get(name) {
+            Some(method) => Some(method.clone()),
+            None => self.superclass.as_ref().and_then(|superclass| superclass.find_method(name)),
+        }
+    }
+
+    // Calling the class passes its arguments on to init
+    pub fn arity(&self) -> usize {
+        self.find_method("init").map_or(0, |init| init.arity())
+    }
+}
+
+// Compared by identity, each declaration makes a new class
+impl PartialEq for LoxClass {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for LoxClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<class {}>", self.name.lexeme)
+    }
+}
+
+pub struct LoxInstance {
+    pub class: Rc<LoxClass>,
+    // Created on first assignment, a field shadows a method of the same name
+    pub fields: RefCell<HashMap<String, Value>>,
+}
+
+impl LoxInstance {
+    pub fn new(class: Rc<LoxClass>) -> LoxInstance {
+        LoxInstance { class, fields: RefCell::new(HashMap::new()) }
+    }
+}
+
+// Two instances are only equal when they are the same object
+impl PartialEq for LoxInstance {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+// The fields can hold the instance itself, so they are left out
+impl fmt::Debug for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<{} instance>", self.class.name.lexeme)
+    }
+}
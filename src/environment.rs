@@ -1,30 +1,99 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 
+use crate::error::{RloxError, RuntimeErrorKind};
+use crate::class::{LoxClass, LoxInstance};
+use crate::function::LoxFunction;
+use crate::natives::NativeFunction;
+
+// Cloning an environment shares its scopes rather than copying them, which is how
+// a closure keeps seeing (and changing) the variables around its declaration
 #[derive(Debug, Clone)]
 pub struct Environment {
-    pub values: HashMap<String, Value>,
+    pub values: Rc<RefCell<HashMap<String, Value>>>,
     pub enclosing: Option<Box<Environment>>,
+    // Label for debug output, e.g. "global" or "block@line 3"
+    pub name: Option<String>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Environment {
     pub fn new() -> Environment {
         Environment {
-            values: HashMap::new(),
+            values: Rc::new(RefCell::new(HashMap::new())),
             enclosing: None,
+            name: None,
+        }
+    }
+
+    pub fn named(name: &str) -> Environment {
+        Environment {
+            name: Some(name.to_string()),
+            ..Environment::new()
+        }
+    }
+
+    // Dumps the scope chain from the innermost scope outwards, one scope per line
+    pub fn dump(&self) -> String {
+        let values = self.values.borrow();
+        let mut names = values.keys().collect::<Vec<&String>>();
+        names.sort();
+        let bindings = names
+            .iter()
+            .map(|name| format!("{} = {}", name, values[*name]))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let mut scope = format!("[{}]", self.name.as_deref().unwrap_or("<anonymous>"));
+        // An empty scope is just its name, without a trailing space
+        if !bindings.is_empty() {
+            scope.push_str(&format!(" {}", bindings));
+        }
+        match &self.enclosing {
+            Some(enclosing) => format!("{}\n{}", scope, enclosing.dump()),
+            None => scope,
+        }
+    }
+
+    // Enters `scope` as the new innermost scope by moving the current one behind it,
+    // so nothing is copied however many variables are already defined
+    pub fn push(&mut self, scope: Environment) {
+        let enclosing = std::mem::replace(self, scope);
+        self.enclosing = Some(Box::new(enclosing));
+    }
+
+    // Leaves the innermost scope, the outermost one is never popped
+    pub fn pop(&mut self) {
+        if let Some(enclosing) = self.enclosing.take() {
+            *self = *enclosing;
+        }
+    }
+
+    // The global scope at the bottom of the chain
+    pub fn outermost(&self) -> &Environment {
+        match &self.enclosing {
+            Some(enclosing) => enclosing.outermost(),
+            None => self,
         }
     }
 
     pub fn insert(&mut self, name: String, value: Value) {
-        self.values.insert(name, value);
+        self.values.borrow_mut().insert(name, value);
     }
 
-    pub fn get(&mut self, name: &String) -> Result<Value, String> {
-        match self.values.get(name) {
-            Some(value) => Ok(value.clone()),
+    pub fn get(&mut self, name: &String) -> Result<Value, RloxError> {
+        let value = self.values.borrow().get(name).cloned();
+        match value {
+            Some(value) => Ok(value),
             None => {
                 match &mut self.enclosing {
                     Some(enclosing) => enclosing.get(name),
-                    None => Err(format!("Undefined variable '{}'.", name)),
+                    None => Err(RloxError::runtime(RuntimeErrorKind::UndefinedVar, format!("Undefined variable '{}'.", name))),
                 }
             }
         }
@@ -34,17 +103,206 @@ impl Environment {
         self.insert(name, value);
     }
 
-    pub fn assign(&mut self, name: String, value: Value) -> Result<(), String> {
-        self.get(&name)?;
-        self.values.insert(name, value);
-        Ok(())
+    // Updates the variable in the scope that defined it
+    pub fn assign(&mut self, name: String, value: Value) -> Result<(), RloxError> {
+        if let Some(slot) = self.values.borrow_mut().get_mut(&name) {
+            *slot = value;
+            return Ok(());
+        }
+        match &mut self.enclosing {
+            Some(enclosing) => enclosing.assign(name, value),
+            None => Err(RloxError::runtime(RuntimeErrorKind::UndefinedVar, format!("Undefined variable '{}'.", name))),
+        }
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Value {
     Number(f64),
+    // Integer literals and exact integer arithmetic, mixing with a Number gives a Number
+    Int(i64),
     String(String),
     Boolean(bool),
+    // Only literals get serialized with the AST, and a native is never a literal
+    #[cfg_attr(feature = "serde", serde(skip))]
+    NativeFunction(Rc<NativeFunction>),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Function(Rc<LoxFunction>),
+    // Shared and mutable, an assigned copy sees changes made through the original.
+    // Built at runtime from literal expressions, so never part of a serialized AST.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    List(Rc<RefCell<Vec<Value>>>),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Map(Rc<RefCell<LoxMap>>),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Class(Rc<LoxClass>),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Instance(Rc<LoxInstance>),
     Nil,
-}
\ No newline at end of file
+}
+
+// String keys in insertion order, so printing a map and listing its keys is predictable
+#[derive(Debug, Clone, Default)]
+pub struct LoxMap {
+    entries: Vec<(String, Value)>,
+    positions: HashMap<String, usize>,
+}
+
+impl LoxMap {
+    pub fn new() -> LoxMap {
+        LoxMap::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.positions.get(key).map(|position| &self.entries[*position].1)
+    }
+
+    // A new key goes last, an existing one keeps its place
+    pub fn insert(&mut self, key: String, value: Value) {
+        match self.positions.get(&key) {
+            Some(position) => self.entries[*position].1 = value,
+            None => {
+                self.positions.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+}
+
+// Maps are equal with the same keys and values, whatever order they were added in
+impl PartialEq for LoxMap {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+// Int and Number compare by numeric value, so 1 == 1.0
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Int(int), Value::Number(number)) | (Value::Number(number), Value::Int(int)) => {
+                // i64::MAX as f64 rounds up to 2^63, which is already out of range
+                number.fract() == 0.0 && *number >= i64::MIN as f64 && *number < i64::MAX as f64 && *number as i64 == *int
+            }
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::NativeFunction(a), Value::NativeFunction(b)) => a == b,
+            (Value::Function(a), Value::Function(b)) => a == b,
+            (Value::Class(a), Value::Class(b)) => a == b,
+            (Value::Instance(a), Value::Instance(b)) => a == b,
+            // By contents, a collection is always equal to itself even when it contains itself
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b) || *a.borrow() == *b.borrow(),
+            (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b) || *a.borrow() == *b.borrow(),
+            (Value::Nil, Value::Nil) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Value {
+    pub fn list(values: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(values)))
+    }
+
+    // Lox truthiness: only nil and false are falsey
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Nil => false,
+            Value::Boolean(boolean) => *boolean,
+            _ => true,
+        }
+    }
+
+    // Either numeric variant as a float, for operations that don't care about exactness
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            Value::Int(int) => Some(*int as f64),
+            _ => None,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            // Int is how exact integers are stored, to a Lox program both are numbers
+            Value::Number(_) | Value::Int(_) => "number",
+            Value::String(_) => "string",
+            Value::Boolean(_) => "bool",
+            Value::NativeFunction(_) | Value::Function(_) => "function",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Class(_) => "class",
+            Value::Instance(_) => "instance",
+            Value::Nil => "nil",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dump_named_scopes() {
+        let mut global = Environment::named("global");
+        global.define(String::from("b"), Value::Number(2.0));
+        global.define(String::from("a"), Value::String(String::from("x")));
+        let mut block = Environment::named("block@line 3");
+        block.define(String::from("c"), Value::Boolean(true));
+        block.enclosing = Some(Box::new(global));
+        let mut inner = Environment::new();
+        inner.enclosing = Some(Box::new(block));
+
+        let dump = inner.dump();
+        assert_eq!(dump.lines().next(), Some("[<anonymous>]"));
+        assert_eq!(dump, "[<anonymous>]\n[block@line 3] c = true\n[global] a = x, b = 2");
+    }
+
+    #[test]
+    fn test_push_pop_and_assign_outer() {
+        let mut environment = Environment::named("global");
+        environment.define(String::from("a"), Value::Number(1.0));
+        environment.push(Environment::named("block"));
+        environment.define(String::from("b"), Value::Number(2.0));
+        assert_eq!(environment.assign(String::from("a"), Value::Number(3.0)), Ok(()));
+        assert!(!environment.values.borrow().contains_key("a"));
+        assert!(environment.assign(String::from("c"), Value::Nil).is_err());
+
+        environment.pop();
+        assert_eq!(environment.name.as_deref(), Some("global"));
+        assert_eq!(environment.get(&String::from("a")), Ok(Value::Number(3.0)));
+        assert!(environment.get(&String::from("b")).is_err());
+        environment.pop();
+        assert_eq!(environment.name.as_deref(), Some("global"));
+    }
+
+    #[test]
+    fn test_is_truthy() {
+        assert!(!Value::Nil.is_truthy());
+        assert!(!Value::Boolean(false).is_truthy());
+        assert!(Value::Boolean(true).is_truthy());
+        assert!(Value::Number(0.0).is_truthy());
+        assert!(Value::Number(f64::NAN).is_truthy());
+        assert!(Value::String(String::new()).is_truthy());
+        assert!(Value::String(String::from("false")).is_truthy());
+
+        let mut interpreter = crate::interpreter::Interpreter::new();
+        let native = interpreter.environment.get(&String::from("is_integer")).unwrap();
+        assert!(native.is_truthy());
+    }
+}
@@ -0,0 +1,58 @@
+use std::fmt;
+
+use crate::scanner::Span;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RloxError {
+    // Line, span of the offending token and the message
+    Scan(usize, Span, String),
+    // Line, lexeme of the offending name and the message
+    Resolve(usize, String, String),
+    Runtime(RuntimeErrorKind, String),
+}
+
+// What went wrong at runtime, so embedders and tests can match on the cause
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RuntimeErrorKind {
+    TypeMismatch,
+    DivByZero,
+    UndefinedVar,
+    UndefinedProperty,
+    NotCallable,
+    ArityMismatch,
+    IndexOutOfRange,
+    NotFinite,
+    AssertionFailed,
+    // Too many nested calls, see Interpreter::max_call_depth
+    StackOverflow,
+    // Raised by a throw statement
+    Thrown,
+    Io,
+    // from_json got text that isn't JSON
+    InvalidJson,
+    // Broken invariants, e.g. an operator the parser should never have produced
+    Internal,
+}
+
+impl RloxError {
+    pub fn runtime(kind: RuntimeErrorKind, message: impl Into<String>) -> RloxError {
+        RloxError::Runtime(kind, message.into())
+    }
+
+    pub fn kind(&self) -> Option<RuntimeErrorKind> {
+        match self {
+            RloxError::Runtime(kind, _) => Some(*kind),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RloxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RloxError::Scan(line, _, message) => write!(f, "[line {}] Error: {}", line, message),
+            RloxError::Resolve(line, lexeme, message) => write!(f, "[line {}] Error at '{}': {}", line, lexeme, message),
+            RloxError::Runtime(_, message) => write!(f, "{}", message),
+        }
+    }
+}
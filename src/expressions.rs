@@ -1,30 +1,129 @@
 use crate::token::Token;
+use crate::environment::Value;
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
     Binary(Box<Expr>, Token, Box<Expr>),
+    // `and`/`or`, kept apart from Binary since the right side may never run
+    Logical(Box<Expr>, Token, Box<Expr>),
     Ternary(Box<Expr>, Token, Box<Expr>, Token, Box<Expr>),
     Grouping(Box<Expr>),
-    Literal(Token),
+    // The value is decoded once by the parser so evaluation just clones it
+    Literal(Token, Value),
     Unary(Token, Box<Expr>),
     Assign(Token, Box<Expr>),
     Variable(Token),
+    Call(Box<Expr>, Token, Vec<Expr>),
+    Get(Box<Expr>, Token),
+    // Get with the value assigned to that field
+    Set(Box<Expr>, Token, Box<Expr>),
+    This(Token),
+    // The keyword and the method looked up on the superclass
+    Super(Token, Token),
+    // The opening bracket or brace, for its line, and the elements or key-value pairs
+    List(Token, Vec<Expr>),
+    Map(Token, Vec<(Expr, Expr)>),
+    // The collection, the opening bracket and the index or key
+    Index(Box<Expr>, Token, Box<Expr>),
+    // Index with the value assigned to that element
+    SetIndex(Box<Expr>, Token, Box<Expr>, Box<Expr>),
+    // The list and the start and end bounds, either of which may be left out as in a[:2]
+    Slice(Box<Expr>, Option<Box<Expr>>, Option<Box<Expr>>),
+}
+
+impl Expr {
+    // Line of the node's principal token, used for diagnostics
+    pub fn line(&self) -> usize {
+        match self {
+            Expr::Binary(_, operator, _) | Expr::Logical(_, operator, _) => operator.line,
+            Expr::Ternary(_, operator, _, _, _) => operator.line,
+            Expr::Grouping(expression) => expression.line(),
+            Expr::Literal(token, _) => token.line,
+            Expr::Unary(operator, _) => operator.line,
+            Expr::Assign(name, _) => name.line,
+            Expr::Variable(name) => name.line,
+            Expr::Call(_, paren, _) => paren.line,
+            Expr::Get(_, name) | Expr::Set(_, name, _) => name.line,
+            Expr::This(keyword) | Expr::Super(keyword, _) => keyword.line,
+            Expr::List(bracket, _) | Expr::Map(bracket, _) => bracket.line,
+            Expr::Index(_, bracket, _) | Expr::SetIndex(_, bracket, _, _) => bracket.line,
+            Expr::Slice(collection, _, _) => collection.line(),
+        }
+    }
+
+    // Whether evaluating the node can change anything, i.e. it calls or assigns somewhere
+    pub fn has_side_effects(&self) -> bool {
+        match self {
+            Expr::Binary(left, _, right) | Expr::Logical(left, _, right) => left.has_side_effects() || right.has_side_effects(),
+            Expr::Ternary(condition, _, then_branch, _, else_branch) => {
+                condition.has_side_effects() || then_branch.has_side_effects() || else_branch.has_side_effects()
+            }
+            Expr::Grouping(expression) | Expr::Unary(_, expression) | Expr::Get(expression, _) => expression.has_side_effects(),
+            Expr::Literal(_, _) | Expr::Variable(_) | Expr::This(_) | Expr::Super(_, _) => false,
+            Expr::Assign(_, _) | Expr::Call(_, _, _) | Expr::SetIndex(_, _, _, _) | Expr::Set(_, _, _) => true,
+            Expr::List(_, elements) => elements.iter().any(Expr::has_side_effects),
+            Expr::Map(_, entries) => entries.iter().any(|(key, value)| key.has_side_effects() || value.has_side_effects()),
+            Expr::Index(collection, _, index) => collection.has_side_effects() || index.has_side_effects(),
+            Expr::Slice(collection, start, end) => {
+                collection.has_side_effects() || [start, end].into_iter().flatten().any(|bound| bound.has_side_effects())
+            }
+        }
+    }
 }
 
 impl std::fmt::Display for Expr {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Expr::Binary(left, operator, right) => {
-                write!(f, "({} {} {})", operator, **left, **right)
+            Expr::Binary(left, operator, right) | Expr::Logical(left, operator, right) => {
+                write!(f, "({} {} {})", operator.lexeme, **left, **right)
             }
             Expr::Ternary(left, operator1, middle, operator2, right) => {
-                write!(f, "({} {} {} {} {})", operator1, **left, **middle, operator2, **right)
+                write!(f, "({} {} {} {} {})", operator1.lexeme, **left, **middle, operator2.lexeme, **right)
             }
             Expr::Grouping(expression) => write!(f, "(group {})", **expression),
-            Expr::Literal(value) => write!(f, "{}", value),
-            Expr::Unary(operator, right) => write!(f, "({} {})", operator, **right),
-            Expr::Assign(name, value) => write!(f, "(assign {} {})", name, **value),
-            Expr::Variable(name) => write!(f, "{}", name),
+            Expr::Literal(token, _) => write!(f, "{}", token.lexeme),
+            Expr::Unary(operator, right) => write!(f, "({} {})", operator.lexeme, **right),
+            Expr::Assign(name, value) => write!(f, "(assign {} {})", name.lexeme, **value),
+            Expr::Variable(name) => write!(f, "{}", name.lexeme),
+            Expr::Call(callee, _, arguments) => {
+                write!(f, "(call {}", **callee)?;
+                for argument in arguments {
+                    write!(f, " {}", argument)?;
+                }
+                write!(f, ")")
+            }
+            Expr::Get(object, name) => write!(f, "(. {} {})", **object, name.lexeme),
+            Expr::Set(object, name, value) => write!(f, "(.= {} {} {})", **object, name.lexeme, **value),
+            Expr::This(_) => write!(f, "this"),
+            Expr::Super(_, method) => write!(f, "(super {})", method.lexeme),
+            Expr::List(_, elements) => {
+                write!(f, "(list")?;
+                for element in elements {
+                    write!(f, " {}", element)?;
+                }
+                write!(f, ")")
+            }
+            Expr::Map(_, entries) => {
+                write!(f, "(map")?;
+                for (key, value) in entries {
+                    write!(f, " {} {}", key, value)?;
+                }
+                write!(f, ")")
+            }
+            Expr::Index(collection, _, index) => write!(f, "([] {} {})", **collection, **index),
+            Expr::SetIndex(collection, _, index, value) => write!(f, "([]= {} {} {})", **collection, **index, **value),
+            // A left-out bound shows as _
+            Expr::Slice(collection, start, end) => {
+                write!(f, "([:] {}", **collection)?;
+                for bound in [start, end] {
+                    match bound {
+                        Some(bound) => write!(f, " {}", **bound)?,
+                        None => write!(f, " _")?,
+                    }
+                }
+                write!(f, ")")
+            }
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,56 @@
+use std::fmt;
+
+use crate::environment::{Environment, Value};
+use crate::statements::Stmt;
+use crate::token::Token;
+
+// A `fun` declaration together with the scopes it was declared in
+pub struct LoxFunction {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Vec<Stmt>,
+    // Shares the scopes around the declaration, globals included
+    pub closure: Environment,
+    // A class's init method, which always returns the instance
+    pub is_initializer: bool,
+}
+
+impl LoxFunction {
+    pub fn arity(&self) -> usize {
+        self.params.len()
+    }
+
+    // The method as seen through an instance, with `this` bound to it in a scope of its own
+    pub fn bind(&self, instance: Value) -> LoxFunction {
+        let mut closure = self.closure.clone();
+        closure.push(Environment::named("this"));
+        closure.define(String::from("this"), instance);
+        LoxFunction {
+            name: self.name.clone(),
+            params: self.params.clone(),
+            body: self.body.clone(),
+            closure,
+            is_initializer: self.is_initializer,
+        }
+    }
+
+    // e.g. "fn add(a, b)", for inspect()
+    pub fn signature(&self) -> String {
+        let params = self.params.iter().map(|param| param.lexeme.as_str()).collect::<Vec<&str>>();
+        format!("fn {}({})", self.name.lexeme, params.join(", "))
+    }
+}
+
+// Compared by identity like natives, each declaration makes a new function
+impl PartialEq for LoxFunction {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+// The closure can hold the function itself, so it is left out
+impl fmt::Debug for LoxFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<fn {}>", self.name.lexeme)
+    }
+}
@@ -1,69 +1,633 @@
+use std::cell::{Ref, RefCell};
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fmt;
+use std::io::Write;
+use std::rc::Rc;
+use crate::token::Token;
 use crate::tokentype::*;
 use crate::expressions::*;
 use crate::statements::*;
 use crate::environment::*;
+use crate::error::{RloxError, RuntimeErrorKind};
+use crate::function::LoxFunction;
+use crate::class::{LoxClass, LoxInstance};
+use crate::natives::*;
+
+pub type Watcher = Box<dyn FnMut(&str, &Value)>;
+
+// Nested Lox calls allowed before a "Stack overflow." runtime error by default. Debug
+// builds use a few tens of KB of native stack per Lox call, so this fits a default
+// 8 MiB main thread stack.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 100;
+// The limit the CLI sets, which needs the interpreter to run on a STACK_SIZE thread
+pub const MAX_CALL_DEPTH: usize = 10_000;
+pub const STACK_SIZE: usize = 1024 * 1024 * 1024;
 
 pub struct Interpreter {
     pub had_error: bool,
     pub environment: Environment,
+    // Division by zero yields Infinity/NaN instead of an error
+    pub ieee_division: bool,
+    // Arithmetic producing Infinity/NaN is a runtime error
+    pub strict_math: bool,
+    // JavaScript-like arithmetic where booleans and nil act as numbers
+    pub coerce: bool,
+    // print prefixes values with their type, e.g. "number: 3", for teaching
+    pub annotate_types: bool,
+    // How print writes numbers: "1,5" instead of "1.5", and "1,000" (or "1.000") grouping.
+    // Literals in the source always use a period.
+    pub decimal_comma: bool,
+    pub group_thousands: bool,
+    // Sinks for program output, stdout/stderr unless the host replaces them
+    pub output: Rc<RefCell<dyn Write>>,
+    pub error_output: Rc<RefCell<dyn Write>>,
+    // Backs rand() and randint(), replace with Rng::new(seed) for reproducible runs
+    pub rng: Rng,
+    // Statements and calls being executed, innermost last, with the line of their
+    // opening token. Blocks, ifs, whiles, trys and calls carry a kind like "if" or
+    // "call fn f". Left as is when a runtime error escapes so the host can say where
+    // it happened.
+    pub trace: Vec<(Option<String>, usize)>,
+    // Called with the name and new value on every define or assign, for --watch
+    pub watcher: Option<Watcher>,
+    // Nested Lox calls allowed before a "Stack overflow." runtime error. Only raise it
+    // past DEFAULT_MAX_CALL_DEPTH when running on a thread with a bigger stack.
+    pub max_call_depth: usize,
+    // Top-level functions are defined before anything runs, so a call may come before
+    // the declaration. Off by default since a program can now see a function early.
+    pub hoist: bool,
+    // Set by a break, continue or return until the enclosing loop or call handles it, every
+    // statement sequence stops early while it is set
+    signal: Option<Signal>,
+    // Lox calls currently running
+    call_depth: usize,
+}
+
+// Control flow that unwinds through statements without being an error
+#[derive(Debug, Clone, PartialEq)]
+enum Signal {
+    Break,
+    Continue,
+    Return(Value),
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Interpreter {
-        Interpreter {
+        let mut interpreter = Interpreter {
             had_error: false,
-            environment: Environment::new(),
+            environment: Environment::named("global"),
+            ieee_division: false,
+            strict_math: false,
+            coerce: false,
+            annotate_types: false,
+            decimal_comma: false,
+            group_thousands: false,
+            output: Rc::new(RefCell::new(std::io::stdout())),
+            error_output: Rc::new(RefCell::new(std::io::stderr())),
+            rng: Rng::from_entropy(),
+            trace: Vec::new(),
+            watcher: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            hoist: false,
+            signal: None,
+            call_depth: 0,
+        };
+        define_globals(&mut interpreter);
+        interpreter
+    }
+
+    // A value as print or string concatenation shows it, numbers follow the configured number format
+    pub fn display(&self, value: &Value) -> String {
+        match value {
+            Value::Number(number) if self.decimal_comma || self.group_thousands => {
+                format_localized(*number, self.decimal_comma, self.group_thousands)
+            }
+            Value::Int(int) if self.group_thousands => localize(&int.to_string(), self.decimal_comma, self.group_thousands),
+            Value::List(_) | Value::Map(_) => format_collection(value, None, &|element| self.display(element)),
+            value => value.to_string(),
+        }
+    }
+
+    // display with every element of a list or map on its own line, indented by how
+    // deeply it is nested, for pretty()
+    pub fn display_pretty(&self, value: &Value) -> String {
+        match value {
+            Value::List(_) | Value::Map(_) => format_collection(value, Some("  "), &|element| self.display(element)),
+            value => self.display(value),
+        }
+    }
+
+    // Global bindings, natives included, for a host to read results back after running
+    pub fn globals(&self) -> Ref<'_, HashMap<String, Value>> {
+        self.environment.outermost().values.borrow()
+    }
+
+    // Lets a host program inject its own builtins, callable from Lox like any other function
+    pub fn define_native<F>(&mut self, name: &str, arity: Arity, function: F)
+    where
+        F: Fn(&mut Interpreter, Vec<Value>) -> Result<Value, RloxError> + 'static,
+    {
+        let native = NativeFunction {
+            name: name.to_string(),
+            arity,
+            function: Box::new(function),
+        };
+        self.environment.define(name.to_string(), Value::NativeFunction(Rc::new(native)));
+    }
+
+    fn watch(&mut self, name: &str, value: &Value) {
+        if let Some(watcher) = &mut self.watcher {
+            watcher(name, value);
+        }
+    }
+
+    // Single entry point for calling any callable value, also used by natives taking callbacks
+    pub fn call_value(&mut self, callee: Value, arguments: Vec<Value>) -> Result<Value, RloxError> {
+        match callee {
+            Value::NativeFunction(native) => {
+                if !native.arity.accepts(arguments.len()) {
+                    return Err(RloxError::runtime(RuntimeErrorKind::ArityMismatch, format!("Expected {} arguments but got {}.", native.arity, arguments.len())));
+                }
+                native.call(self, arguments)
+            }
+            Value::Function(function) => {
+                if arguments.len() != function.arity() {
+                    return Err(RloxError::runtime(RuntimeErrorKind::ArityMismatch, format!("Expected {} arguments but got {}.", function.arity(), arguments.len())));
+                }
+                self.call_function(function, arguments)
+            }
+            // A new instance, set up by init when the class has one
+            Value::Class(class) => {
+                if arguments.len() != class.arity() {
+                    return Err(RloxError::runtime(RuntimeErrorKind::ArityMismatch, format!("Expected {} arguments but got {}.", class.arity(), arguments.len())));
+                }
+                let instance = Value::Instance(Rc::new(LoxInstance::new(class.clone())));
+                if let Some(init) = class.find_method("init") {
+                    self.call_function(Rc::new(init.bind(instance.clone())), arguments)?;
+                }
+                Ok(instance)
+            }
+            _ => Err(RloxError::runtime(RuntimeErrorKind::NotCallable, "Can only call functions and classes.")),
+        }
+    }
+
+    // Runs the body in a new scope on top of the closure
+    fn call_function(&mut self, function: Rc<LoxFunction>, arguments: Vec<Value>) -> Result<Value, RloxError> {
+        if self.call_depth >= self.max_call_depth {
+            return Err(RloxError::runtime(RuntimeErrorKind::StackOverflow, "Stack overflow."));
+        }
+        let mut environment = function.closure.clone();
+        environment.push(Environment::named(&format!("fn {}", function.name.lexeme)));
+        for (param, argument) in function.params.iter().zip(arguments) {
+            environment.define(param.lexeme.clone(), argument);
+        }
+
+        let caller = std::mem::replace(&mut self.environment, environment);
+        self.call_depth += 1;
+        let result = self.execute_all(function.body.iter().cloned());
+        self.call_depth -= 1;
+        self.environment = caller;
+        result?;
+        let signal = self.signal.take();
+        // Even a bare return in init gives back the instance
+        if function.is_initializer {
+            return function.closure.clone().get(&String::from("this"));
+        }
+        match signal {
+            Some(Signal::Return(value)) => Ok(value),
+            _ => Ok(Value::Nil),
+        }
+    }
+
+    pub fn interpret(&mut self, statements: Vec<Stmt>) -> Result<(), RloxError> {
+        self.trace.clear();
+        // The resolver rejects a break or return out of place, but a loaded AST skips it
+        self.signal = None;
+        if !self.hoist {
+            return self.execute_all(statements);
+        }
+        // Declared in source order, so a later declaration of the same name wins
+        let (functions, rest): (Vec<Stmt>, Vec<Stmt>) = statements.into_iter().partition(|statement| matches!(statement, Stmt::Function(..)));
+        self.execute_all(functions)?;
+        self.execute_all(rest)
+    }
+
+    // Replaces each subexpression made only of literals with its value, as computed by
+    // this interpreter's own settings. One that fails to evaluate, like 1 / 0, is left in
+    // place so it raises the same error when the program runs.
+    pub fn fold_constants(&mut self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        statements.into_iter().map(|statement| self.fold_statement(statement)).collect()
+    }
+
+    fn fold_statement(&mut self, statement: Stmt) -> Stmt {
+        match statement {
+            Stmt::Expression(expression) => Stmt::Expression(self.fold(expression)),
+            Stmt::Print(keyword, expression) => Stmt::Print(keyword, expression.map(|expression| self.fold(expression))),
+            Stmt::Var(name, initializer) => Stmt::Var(name, self.fold(initializer)),
+            Stmt::VarGroup(declarations) => Stmt::VarGroup(
+                declarations.into_iter().map(|(name, initializer)| (name, self.fold(initializer))).collect(),
+            ),
+            Stmt::Block(brace, statements) => Stmt::Block(brace, self.fold_constants(statements)),
+            // The failure message shows the condition as written
+            Stmt::Assert(condition) => Stmt::Assert(condition),
+            Stmt::Try(keyword, body, name, handler) => {
+                Stmt::Try(keyword, Box::new(self.fold_statement(*body)), name, Box::new(self.fold_statement(*handler)))
+            }
+            Stmt::Throw(expression) => Stmt::Throw(self.fold(expression)),
+            Stmt::If(keyword, condition, then_branch, else_branch) => Stmt::If(
+                keyword,
+                self.fold(condition),
+                Box::new(self.fold_statement(*then_branch)),
+                else_branch.map(|else_branch| Box::new(self.fold_statement(*else_branch))),
+            ),
+            Stmt::While(keyword, condition, body, increment, else_branch) => Stmt::While(
+                keyword,
+                self.fold(condition),
+                Box::new(self.fold_statement(*body)),
+                increment.map(|increment| self.fold(increment)),
+                else_branch.map(|else_branch| Box::new(self.fold_statement(*else_branch))),
+            ),
+            Stmt::Break(keyword) => Stmt::Break(keyword),
+            Stmt::Continue(keyword) => Stmt::Continue(keyword),
+            Stmt::Return(keyword, value) => Stmt::Return(keyword, value.map(|value| self.fold(value))),
+            Stmt::Function(name, params, body) => Stmt::Function(name, params, self.fold_constants(body)),
+            Stmt::Class(name, superclass, methods) => Stmt::Class(name, superclass, self.fold_constants(methods)),
+        }
+    }
+
+    fn fold(&mut self, expression: Expr) -> Expr {
+        let expression = match expression {
+            Expr::Binary(left, operator, right) => Expr::Binary(Box::new(self.fold(*left)), operator, Box::new(self.fold(*right))),
+            Expr::Logical(left, operator, right) => Expr::Logical(Box::new(self.fold(*left)), operator, Box::new(self.fold(*right))),
+            Expr::Ternary(condition, operator1, then_branch, operator2, else_branch) => Expr::Ternary(
+                Box::new(self.fold(*condition)),
+                operator1,
+                Box::new(self.fold(*then_branch)),
+                operator2,
+                Box::new(self.fold(*else_branch)),
+            ),
+            Expr::Grouping(expression) => Expr::Grouping(Box::new(self.fold(*expression))),
+            Expr::Unary(operator, right) => Expr::Unary(operator, Box::new(self.fold(*right))),
+            Expr::Assign(name, value) => return Expr::Assign(name, Box::new(self.fold(*value))),
+            Expr::Call(callee, paren, arguments) => {
+                let arguments = arguments.into_iter().map(|argument| self.fold(argument)).collect();
+                return Expr::Call(Box::new(self.fold(*callee)), paren, arguments);
+            }
+            Expr::Get(object, name) => return Expr::Get(Box::new(self.fold(*object)), name),
+            Expr::Set(object, name, value) => return Expr::Set(Box::new(self.fold(*object)), name, Box::new(self.fold(*value))),
+            // Each evaluation of a list or map literal makes a new collection, so they stay expressions
+            Expr::List(bracket, elements) => {
+                return Expr::List(bracket, elements.into_iter().map(|element| self.fold(element)).collect());
+            }
+            Expr::Map(brace, entries) => {
+                return Expr::Map(brace, entries.into_iter().map(|(key, value)| (self.fold(key), self.fold(value))).collect());
+            }
+            Expr::Index(collection, bracket, index) => {
+                return Expr::Index(Box::new(self.fold(*collection)), bracket, Box::new(self.fold(*index)));
+            }
+            Expr::SetIndex(collection, bracket, index, value) => {
+                return Expr::SetIndex(Box::new(self.fold(*collection)), bracket, Box::new(self.fold(*index)), Box::new(self.fold(*value)));
+            }
+            Expr::Slice(collection, start, end) => {
+                let start = start.map(|start| Box::new(self.fold(*start)));
+                let end = end.map(|end| Box::new(self.fold(*end)));
+                return Expr::Slice(Box::new(self.fold(*collection)), start, end);
+            }
+            Expr::Literal(_, _) | Expr::Variable(_) | Expr::This(_) | Expr::Super(_, _) => return expression,
+        };
+
+        let is_literal = |expression: &Expr| matches!(expression, Expr::Literal(_, _));
+        let foldable = match &expression {
+            Expr::Binary(left, _, right) | Expr::Logical(left, _, right) => is_literal(left) && is_literal(right),
+            Expr::Ternary(condition, _, then_branch, _, else_branch) => {
+                is_literal(condition) && is_literal(then_branch) && is_literal(else_branch)
+            }
+            Expr::Grouping(inner) | Expr::Unary(_, inner) => is_literal(inner),
+            _ => false,
+        };
+        if !foldable {
+            return expression;
+        }
+        let line = expression.line();
+        match self.evaluate_expression(expression.clone()) {
+            Ok(value) => literal(value, line),
+            Err(_) => expression,
+        }
+    }
+
+    // Where the last runtime error happened, e.g. "Runtime error at line 4 (in while at line 3)"
+    pub fn error_context(&self) -> Option<String> {
+        let ((_, line), enclosing) = self.trace.split_last()?;
+        let mut context = format!("Runtime error at line {}", line);
+        let frames = enclosing
+            .iter()
+            .rev()
+            .filter_map(|(kind, line)| kind.as_ref().map(|kind| format!("{} at line {}", kind, line)))
+            .take(3)
+            .collect::<Vec<String>>();
+        if !frames.is_empty() {
+            context.push_str(&format!(" (in {})", frames.join(", ")));
         }
+        Some(context)
     }
 
-    pub fn interpret(&mut self, statements: Vec<Stmt>) -> Result<(), String> {
+    fn execute_statement(&mut self, statement: Stmt) -> Result<(), RloxError> {
+        let kind = match statement {
+            Stmt::Block(..) => Some("block"),
+            Stmt::If(..) => Some("if"),
+            Stmt::While(..) => Some("while"),
+            Stmt::Try(..) => Some("try"),
+            _ => None,
+        };
+        self.trace.push((kind.map(String::from), statement.line()));
+        self.run_statement(statement)?;
+        self.trace.pop();
+        Ok(())
+    }
+
+    // Runs statements in order until one of them raises a signal
+    fn execute_all(&mut self, statements: impl IntoIterator<Item = Stmt>) -> Result<(), RloxError> {
         for statement in statements {
             self.execute_statement(statement)?;
+            if self.signal.is_some() {
+                break;
+            }
         }
         Ok(())
     }
 
-    fn execute_statement(&mut self, statement: Stmt) -> Result<(), String> {
+    fn run_statement(&mut self, statement: Stmt) -> Result<(), RloxError> {
         match statement {
             Stmt::Expression(expression) => {
                 self.evaluate_expression(expression)?;
             }
-            Stmt::Print(expression) => {
+            Stmt::Print(_, Some(expression)) => {
                 let value = self.evaluate_expression(expression)?;
-                println!("{}", value);
+                self.print(&value)?;
+            }
+            Stmt::Print(_, None) => {
+                writeln!(self.output.borrow_mut()).map_err(io_error)?;
             }
             Stmt::Var(name, expression) => {
+                self.define(name, expression)?;
+            }
+            Stmt::VarGroup(declarations) => {
+                for (name, expression) in declarations {
+                    self.define(name, expression)?;
+                }
+            }
+            Stmt::Block(brace, statements) => {
+                self.block(Environment::named(&format!("block@line {}", brace.line)), statements)?;
+            }
+            Stmt::Try(_, body, name, handler) => {
+                self.try_statement(*body, name, *handler)?;
+            }
+            Stmt::If(_, condition, then_branch, else_branch) => {
+                self.if_statement(condition, then_branch, else_branch)?;
+            }
+            Stmt::While(keyword, condition, body, increment, else_branch) => {
+                self.while_statement(&keyword, condition, *body, increment, else_branch)?;
+            }
+            Stmt::Break(_) => {
+                self.signal = Some(Signal::Break);
+            }
+            Stmt::Continue(_) => {
+                self.signal = Some(Signal::Continue);
+            }
+            Stmt::Return(_, value) => {
+                let value = match value {
+                    Some(value) => self.evaluate_expression(value)?,
+                    None => Value::Nil,
+                };
+                self.signal = Some(Signal::Return(value));
+            }
+            Stmt::Function(name, params, body) => {
+                let function = LoxFunction { name: name.clone(), params, body, closure: self.environment.clone(), is_initializer: false };
+                self.environment.define(name.lexeme, Value::Function(Rc::new(function)));
+            }
+            Stmt::Class(name, superclass, methods) => {
+                self.class(name, superclass, methods)?;
+            }
+            Stmt::Throw(expression) => {
                 let value = self.evaluate_expression(expression)?;
-                self.environment.define(name.lexeme, value);
+                return Err(RloxError::runtime(RuntimeErrorKind::Thrown, self.display(&value)));
+            }
+            Stmt::Assert(condition) => {
+                self.assert(condition)?;
+            }
+        }
+        Ok(())
+    }
+
+    // The statements with more than a line or two of their own live in these helpers,
+    // which keeps run_statement's frame small since it is on the stack once per nesting level
+
+    fn define(&mut self, name: Token, initializer: Expr) -> Result<(), RloxError> {
+        let value = self.evaluate_expression(initializer)?;
+        self.watch(&name.lexeme, &value);
+        self.environment.define(name.lexeme, value);
+        Ok(())
+    }
+
+    fn class(&mut self, name: Token, superclass: Option<Expr>, methods: Vec<Stmt>) -> Result<(), RloxError> {
+        let superclass = match superclass.map(|superclass| self.evaluate_expression(superclass)).transpose()? {
+            Some(Value::Class(superclass)) => Some(superclass),
+            Some(_) => return Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, "Superclass must be a class.")),
+            None => None,
+        };
+        let mut closure = self.environment.clone();
+        if let Some(superclass) = &superclass {
+            closure.push(Environment::named("super"));
+            closure.define(String::from("super"), Value::Class(superclass.clone()));
+        }
+        let mut class = LoxClass { name: name.clone(), superclass, methods: HashMap::new() };
+        for method in methods {
+            if let Stmt::Function(method_name, params, body) = method {
+                let is_initializer = method_name.lexeme == "init";
+                let function = LoxFunction { name: method_name.clone(), params, body, closure: closure.clone(), is_initializer };
+                class.methods.insert(method_name.lexeme, Rc::new(function));
+            }
+        }
+        self.environment.define(name.lexeme, Value::Class(Rc::new(class)));
+        Ok(())
+    }
+
+    fn block(&mut self, scope: Environment, statements: Vec<Stmt>) -> Result<(), RloxError> {
+        self.environment.push(scope);
+        let result = self.execute_all(statements);
+        // Pop even on error so a REPL session keeps its globals
+        self.environment.pop();
+        result
+    }
+
+    fn if_statement(&mut self, condition: Expr, then_branch: Box<Stmt>, else_branch: Option<Box<Stmt>>) -> Result<(), RloxError> {
+        if self.evaluate_expression(condition)?.is_truthy() {
+            self.execute_statement(*then_branch)
+        } else if let Some(else_branch) = else_branch {
+            self.execute_statement(*else_branch)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn print(&self, value: &Value) -> Result<(), RloxError> {
+        let text = self.display(value);
+        if self.annotate_types && *value != Value::Nil {
+            writeln!(self.output.borrow_mut(), "{}: {}", value.type_name(), text).map_err(io_error)
+        } else {
+            writeln!(self.output.borrow_mut(), "{}", text).map_err(io_error)
+        }
+    }
+
+    fn try_statement(&mut self, body: Stmt, name: Token, handler: Stmt) -> Result<(), RloxError> {
+        let depth = self.trace.len();
+        match self.execute_statement(body) {
+            // Only runtime errors get here, scan and parse errors stop the program before it runs
+            Err(RloxError::Runtime(_, message)) => {
+                self.trace.truncate(depth);
+                self.environment.push(Environment::named(&format!("catch@line {}", name.line)));
+                self.environment.define(name.lexeme, Value::String(message));
+                let result = self.execute_statement(handler);
+                self.environment.pop();
+                result
+            }
+            result => result,
+        }
+    }
+
+    fn while_statement(&mut self, keyword: &Token, condition: Expr, body: Stmt, increment: Option<Expr>, else_branch: Option<Box<Stmt>>) -> Result<(), RloxError> {
+        // A block body that declares no function can't have its scope captured by a
+        // closure, so every iteration runs in the same one, emptied in between
+        let scope = match &body {
+            Stmt::Block(brace, _) if !body.declares_function() => Some(Environment::named(&format!("block@line {}", brace.line))),
+            _ => None,
+        };
+        // All are cloned per iteration since evaluation consumes the tree
+        let mut completed = true;
+        while self.evaluate_expression(condition.clone())?.is_truthy() {
+            if keyword.token_type == TokenType::For {
+                self.fresh_iteration(keyword, &body, &scope)?;
+            } else {
+                self.loop_body(&body, &scope)?;
             }
-            Stmt::Block(statements) => {
-                let previous = self.environment.clone();
-                self.environment.enclosing = Some(Box::new(previous.clone()));
-                for statement in statements {
-                    self.execute_statement(statement)?;
+            // A continue and a break end here, a return keeps unwinding to the call
+            match self.signal.take() {
+                None | Some(Signal::Continue) => (),
+                Some(Signal::Break) => {
+                    completed = false;
+                    break;
+                }
+                signal => {
+                    self.signal = signal;
+                    completed = false;
+                    break;
                 }
-                self.environment = previous;
             }
+            if let Some(increment) = &increment {
+                self.evaluate_expression(increment.clone())?;
+            }
+        }
+        if let (true, Some(else_branch)) = (completed, else_branch) {
+            self.execute_statement(*else_branch)?;
+        }
+        Ok(())
+    }
+
+    // A for loop's own variables, the scope the loop runs in, are copied into a new
+    // scope for each pass so a closure made in the body keeps that pass's values. The
+    // copies are written back for the increment and the next condition to see.
+    fn fresh_iteration(&mut self, keyword: &Token, body: &Stmt, scope: &Option<Environment>) -> Result<(), RloxError> {
+        let iteration = Environment::named(&format!("iteration@line {}", keyword.line));
+        iteration.values.borrow_mut().clone_from(&self.environment.values.borrow());
+        self.environment.push(iteration);
+        let result = self.loop_body(body, scope);
+        let copies = self.environment.values.borrow().clone();
+        self.environment.pop();
+        self.environment.values.borrow_mut().extend(copies);
+        result
+    }
+
+    fn loop_body(&mut self, body: &Stmt, scope: &Option<Environment>) -> Result<(), RloxError> {
+        match (body, scope) {
+            (Stmt::Block(brace, statements), Some(scope)) => {
+                scope.values.borrow_mut().clear();
+                self.trace.push((Some(String::from("block")), brace.line));
+                self.block(scope.clone(), statements.clone())?;
+                self.trace.pop();
+                Ok(())
+            }
+            _ => self.execute_statement(body.clone()),
+        }
+    }
+
+    fn assert(&mut self, condition: Expr) -> Result<(), RloxError> {
+        // assert(x > 3) parses as a grouping, the parentheses add nothing to the message
+        let shown = match &condition {
+            Expr::Grouping(inner) => inner.to_string(),
+            condition => condition.to_string(),
+        };
+        if !self.evaluate_expression(condition)?.is_truthy() {
+            return Err(RloxError::runtime(RuntimeErrorKind::AssertionFailed, format!("Assertion failed: {}", shown)));
         }
         Ok(())
     }
 
-    pub fn evaluate_expression(&mut self, expression: Expr) -> Result<Value, String> {
-        match expression {
+    // Exact while the result fits in an i64 and, for division, has no remainder,
+    // otherwise the same operation is done on floats
+    fn int_arithmetic(&self, left: i64, operator: &Token, right: i64) -> Result<Value, RloxError> {
+        let result = match operator.token_type {
+            TokenType::Plus => left.checked_add(right),
+            TokenType::Minus => left.checked_sub(right),
+            TokenType::Star => left.checked_mul(right),
+            TokenType::StarStar => u32::try_from(right).ok().and_then(|exponent| left.checked_pow(exponent)),
+            TokenType::Slash if left.checked_rem(right) == Some(0) => left.checked_div(right),
+            _ => None,
+        };
+        match result {
+            // A zero product or quotient of operands with different signs is -0 in floats
+            Some(0) if matches!(operator.token_type, TokenType::Star | TokenType::Slash) && (left < 0) != (right < 0) => {
+                Ok(Value::Number(-0.0))
+            }
+            Some(int) => Ok(Value::Int(int)),
+            None => self.float_arithmetic(left as f64, operator, right as f64),
+        }
+    }
 
-            // Literal evaluation
-            Expr::Literal(token) => {
-                match token.token_type {
-                    TokenType::Number(number) => Ok(Value::Number(number)),
-                    TokenType::String(string) => Ok(Value::String(string)),
-                    TokenType::True => Ok(Value::Boolean(true)),
-                    TokenType::False => Ok(Value::Boolean(false)),
-                    TokenType::Nil => Ok(Value::Nil),
-                    TokenType::Identifier(name) => self.environment.get(&name),
-                    _ => Err(format!("Unexpected token type: '{}' for Literal Expresion", token.token_type)),
+    fn float_arithmetic(&self, left: f64, operator: &Token, right: f64) -> Result<Value, RloxError> {
+        let result = match operator.token_type {
+            TokenType::Plus => left + right,
+            TokenType::Minus => left - right,
+            TokenType::Star => left * right,
+            TokenType::StarStar => left.powf(right),
+            TokenType::Slash => {
+                if right == 0.0 && !self.ieee_division {
+                    return Err(RloxError::runtime(RuntimeErrorKind::DivByZero, format!("Division by zero: {} {} {}", left, operator.token_type, right)));
                 }
+                left / right
             }
+            _ => return Err(RloxError::runtime(RuntimeErrorKind::Internal, format!("Unexpected token type: '{}' for Binary Expression", operator.token_type))),
+        };
+        if self.strict_math && !result.is_finite() {
+            Err(RloxError::runtime(RuntimeErrorKind::NotFinite, "Result is not a finite number."))
+        } else {
+            Ok(Value::Number(result))
+        }
+    }
+
+    // Every arm past the trivial ones calls a helper once its operands are evaluated, so
+    // this frame, which recursion stacks once per nested expression, stays small
+    pub fn evaluate_expression(&mut self, expression: Expr) -> Result<Value, RloxError> {
+        match expression {
+
+            // Literal evaluation
+            Expr::Literal(_, value) => Ok(value),
 
             Expr::Variable(name) => {
                 self.environment.get(&name.lexeme)
@@ -75,96 +639,28 @@ impl Interpreter {
             // Unary evaluation
             Expr::Unary(operator, right) => {
                 let right = self.evaluate_expression(*right)?;
-                match operator.token_type {
-                    TokenType::Minus => {
-                        match right {
-                            Value::Number(number) => Ok(Value::Number(-number)),
-                            _ => Err(format!("Unexpected value: '{}' for Unary Expression: -{}", right, right)),
-                        }
-                    }
-                    TokenType::Bang => {
-                        Ok(Value::Boolean(!self.is_truthy(right)))
-                    }
-                    _ => Err(format!("Unexpected token type: '{}' for Unary Expression", operator.token_type)),
-                }
+                unary(&operator, right)
             }
 
             // Binary evaluation
             Expr::Binary(left, operator, right) => {
                 let left = self.evaluate_expression(*left)?;
                 let right = self.evaluate_expression(*right)?;
-                match operator.token_type {
-
-                    // Comma expressions
-                    TokenType::Comma => {
-                        Ok(right)
-                    }
-
-                    // Equality expressions
-                    TokenType::EqualEqual => {
-                        Ok(Value::Boolean(left == right))
-                    }
-                    TokenType::BangEqual => {
-                        Ok(Value::Boolean(left != right))
-                    }
-
-                    // Comparison expressions
-                    TokenType::Greater | TokenType::Less | TokenType::GreaterEqual | TokenType::LessEqual => {
-                        match (&left, &right) {
-                            (Value::Number(left), Value::Number(right)) => {
-                                match operator.token_type {
-                                    TokenType::Greater => Ok(Value::Boolean(left > right)),
-                                    TokenType::Less => Ok(Value::Boolean(left < right)),
-                                    TokenType::GreaterEqual => Ok(Value::Boolean(left >= right)),
-                                    TokenType::LessEqual => Ok(Value::Boolean(left <= right)),
-                                    _ => Err(format!("Unexpected token type: '{}' for Binary Expression", operator.token_type)),
-                                }
-                            }
-                            _ => Err(format!("Unexpected values: '{}' and '{}' for Binary Expression: {} {} {}", left, right, left, operator.token_type, right)),
-                        }
-                    }
-
-                    // Arithmetic expressions
-                    TokenType::Plus | TokenType::Minus | TokenType::Star | TokenType::Slash => {
-                        match (&left, &right) {
-                            (Value::Number(left), Value::Number(right)) => {
-                                match operator.token_type {
-                                    TokenType::Plus => Ok(Value::Number(left + right)),
-                                    TokenType::Minus => Ok(Value::Number(left - right)),
-                                    TokenType::Star => Ok(Value::Number(left * right)),
-                                    TokenType::Slash => {
-                                        if right == &0.0 {
-                                            Err(format!("Division by zero: {} {} {}", left, operator.token_type, right))
-                                        } else {
-                                            Ok(Value::Number(left / right))
-                                        }
-                                    }
-                                    _ => Err(format!("Unexpected token type: '{}' for Binary Expression", operator.token_type)),
-                                }
-                            }
-                            (Value::String(left), Value::String(right)) => {
-                                match operator.token_type {
-                                    TokenType::Plus => Ok(Value::String(format!("{}{}", left, right))),
-                                    _ => Err(format!("Unexpected token type: '{}' for Binary Expression", operator.token_type)),
-                                }
-                            }
-                            (left, Value::String(right)) => {
-                                match operator.token_type {
-                                    TokenType::Plus => Ok(Value::String(format!("{}{}", left, right))),
-                                    _ => Err(format!("Unexpected token type: '{}' for Binary Expression", operator.token_type)),
-                                }
-                            }
-                            (Value::String(left), right) => {
-                                match operator.token_type {
-                                    TokenType::Plus => Ok(Value::String(format!("{}{}", left, right))),
-                                    _ => Err(format!("Unexpected token type: '{}' for Binary Expression", operator.token_type)),
-                                }
-                            }
-                            _ => Err(format!("Unexpected values: '{}' and '{}' for Binary Expression: {} {} {}", left, right, left, operator.token_type, right)),
-                        }
-                    }
+                self.binary(left, &operator, right)
+            }
 
-                    _ => Err(format!("Unexpected token type: '{}' for Binary Expression", operator.token_type)),
+            // Logical evaluation, the right side only runs when the left doesn't decide
+            // the result, and the deciding operand is returned as is
+            Expr::Logical(left, operator, right) => {
+                let left = self.evaluate_expression(*left)?;
+                let decided = match operator.token_type {
+                    TokenType::Or => left.is_truthy(),
+                    _ => !left.is_truthy(),
+                };
+                if decided {
+                    Ok(left)
+                } else {
+                    self.evaluate_expression(*right)
                 }
             }
 
@@ -173,119 +669,740 @@ impl Interpreter {
                 let left = self.evaluate_expression(*left)?;
                 let middle = self.evaluate_expression(*middle)?;
                 let right = self.evaluate_expression(*right)?;
-                match operator1.token_type {
-                    TokenType::QuestionMark => {
-                        match operator2.token_type {
-                            TokenType::Colon => {
-                                if self.is_truthy(left) {
-                                    Ok(middle)
-                                } else {
-                                    Ok(right)
-                                }
-                            }
-                            _ => Err(format!("Unexpected token type: '{}' for Ternary Expression: {} {} {} {} {}", operator2.token_type, left, operator1.token_type, middle, operator2.token_type, right)),
-                        }
-                    }
-                    _ => Err(format!("Unexpected token type: '{}' for Ternary Expression: {} {} {} {} {}", operator1.token_type, left, operator1.token_type, middle, operator2.token_type, right)),
-                }
+                ternary(left, &operator1, middle, &operator2, right)
             }
 
             // Assignment evaluation
             Expr::Assign(name, value) => {
                 let new_val = self.evaluate_expression(*value)?;
-                self.environment.assign(name.lexeme, new_val.clone())?;
-                Ok(new_val)
+                self.assign(&name, new_val)
+            }
+
+            // Call evaluation
+            Expr::Call(callee, _, arguments) => {
+                let line = callee.line();
+                let callee = self.evaluate_expression(*callee)?;
+                let mut values = Vec::new();
+                for argument in arguments {
+                    values.push(self.evaluate_expression(argument)?);
+                }
+                self.call(callee, values, line)
+            }
+
+            // Fields and methods of instances, and the built-in methods of primitives
+            Expr::Get(object, name) => {
+                let object = self.evaluate_expression(*object)?;
+                get(&object, &name)
+            }
+
+            Expr::Set(object, name, value) => {
+                let Value::Instance(instance) = self.evaluate_expression(*object)? else {
+                    return Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, "Only instances have fields."));
+                };
+                let value = self.evaluate_expression(*value)?;
+                instance.fields.borrow_mut().insert(name.lexeme, value.clone());
+                Ok(value)
+            }
+
+            Expr::This(keyword) => self.environment.get(&keyword.lexeme),
+
+            // The method from the superclass of the class it appears in, bound to this
+            Expr::Super(keyword, method) => {
+                let (Value::Class(superclass), instance) = (self.environment.get(&keyword.lexeme)?, self.environment.get(&String::from("this"))?) else {
+                    return Err(RloxError::runtime(RuntimeErrorKind::Internal, "super is bound to something other than a class."));
+                };
+                match superclass.find_method(&method.lexeme) {
+                    Some(function) => Ok(Value::Function(Rc::new(function.bind(instance)))),
+                    None => Err(RloxError::runtime(RuntimeErrorKind::UndefinedProperty, format!("Undefined property '{}'.", method.lexeme))),
+                }
+            }
+
+            Expr::List(_, elements) => self.list(elements),
+            Expr::Map(_, entries) => self.map(entries),
+
+            Expr::Index(collection, _, index) => {
+                let collection = self.evaluate_expression(*collection)?;
+                let index = self.evaluate_expression(*index)?;
+                index_get(&collection, &index)
+            }
+
+            Expr::SetIndex(collection, _, index, value) => {
+                let collection = self.evaluate_expression(*collection)?;
+                let index = self.evaluate_expression(*index)?;
+                let value = self.evaluate_expression(*value)?;
+                index_set(&collection, &index, value.clone())?;
+                Ok(value)
+            }
+
+            Expr::Slice(collection, start, end) => {
+                let collection = self.evaluate_expression(*collection)?;
+                let start = start.map(|start| self.evaluate_expression(*start)).transpose()?;
+                let end = end.map(|end| self.evaluate_expression(*end)).transpose()?;
+                slice(&collection, start.as_ref(), end.as_ref())
             }
         }
     }
 
-    fn is_truthy(&self, value: Value) -> bool {
-        match value {
-            Value::Nil => false,
-            Value::Boolean(boolean) => boolean,
-            _ => true,
+    fn list(&mut self, elements: Vec<Expr>) -> Result<Value, RloxError> {
+        let mut values = Vec::with_capacity(elements.len());
+        for element in elements {
+            values.push(self.evaluate_expression(element)?);
         }
+        Ok(Value::list(values))
     }
-}
 
-impl fmt::Display for Value {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Value::Number(number) => write!(f, "{}", number),
-            Value::String(string) => write!(f, "{}", string),
-            Value::Boolean(boolean) => write!(f, "{}", boolean),
-            Value::Nil => write!(f, "nil"),
+    fn map(&mut self, entries: Vec<(Expr, Expr)>) -> Result<Value, RloxError> {
+        let mut map = LoxMap::new();
+        for (key, value) in entries {
+            let key = map_key(self.evaluate_expression(key)?)?;
+            map.insert(key, self.evaluate_expression(value)?);
         }
+        Ok(Value::Map(Rc::new(RefCell::new(map))))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::parser::Parser;
-    use crate::scanner::Scanner;
+    fn binary(&self, left: Value, operator: &Token, right: Value) -> Result<Value, RloxError> {
+        match operator.token_type {
 
-    fn get_result_from_expression(expression: &str) -> Result<Value, String> {
-        let mut scanner = Scanner::new(String::from(expression));
-        let mut parser = Parser::new(scanner.scan_tokens());
-        let mut interpreter = Interpreter::new();
+            // Comma expressions
+            TokenType::Comma => {
+                Ok(right)
+            }
 
-        let expression = parser.expression();
-        match expression {
-            Ok(expression) => interpreter.evaluate_expression(expression),
-            Err(error) => Err(error),
-        }
-    }
+            // Equality expressions
+            TokenType::EqualEqual => {
+                Ok(Value::Boolean(left == right))
+            }
+            TokenType::BangEqual => {
+                Ok(Value::Boolean(left != right))
+            }
 
-    #[test]
-    fn test_evaluate_literals_expression() {
-        assert_eq!(get_result_from_expression("true"), Ok(Value::Boolean(true)));
-        assert_eq!(get_result_from_expression("false"), Ok(Value::Boolean(false)));
-        assert_eq!(get_result_from_expression("nil"), Ok(Value::Nil));
-        assert_eq!(get_result_from_expression("1"), Ok(Value::Number(1.0)));
-        assert_eq!(get_result_from_expression("1.5"), Ok(Value::Number(1.5)));
-        assert_eq!(get_result_from_expression("\"Hello\""), Ok(Value::String(String::from("Hello"))));
-    }
+            // Comparison expressions
+            TokenType::Greater | TokenType::Less | TokenType::GreaterEqual | TokenType::LessEqual => {
+                // Two Ints compare exactly, anything mixed as floats, where NaN compares false
+                let ordering = match (&left, &right, left.as_number(), right.as_number()) {
+                    (Value::Int(left), Value::Int(right), _, _) => Some(left.cmp(right)),
+                    (_, _, Some(left), Some(right)) => left.partial_cmp(&right),
+                    _ => return Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("Unexpected values: '{}' and '{}' for Binary Expression: {} {} {}", left, right, left, operator.token_type, right))),
+                };
+                match operator.token_type {
+                    TokenType::Greater => Ok(Value::Boolean(ordering == Some(Ordering::Greater))),
+                    TokenType::Less => Ok(Value::Boolean(ordering == Some(Ordering::Less))),
+                    TokenType::GreaterEqual => Ok(Value::Boolean(matches!(ordering, Some(Ordering::Greater | Ordering::Equal)))),
+                    TokenType::LessEqual => Ok(Value::Boolean(matches!(ordering, Some(Ordering::Less | Ordering::Equal)))),
+                    _ => Err(RloxError::runtime(RuntimeErrorKind::Internal, format!("Unexpected token type: '{}' for Binary Expression", operator.token_type))),
+                }
+            }
 
-    #[test]
-    fn test_evaluate_grouping_expression() {
-        assert_eq!(get_result_from_expression("(1)"), Ok(Value::Number(1.0)));
-        assert_eq!(get_result_from_expression("(1 + 2)"), Ok(Value::Number(3.0)));
-        assert_eq!(get_result_from_expression("(1 + 2) * 3"), Ok(Value::Number(9.0)));
-        assert_eq!(get_result_from_expression("1 + (2 * 3)"), Ok(Value::Number(7.0)));
-    }
+            // Arithmetic expressions
+            TokenType::Plus | TokenType::Minus | TokenType::Star | TokenType::Slash | TokenType::StarStar => {
+                let (left, right) = if self.coerce {
+                    coerce_operands(left, right)
+                } else {
+                    (left, right)
+                };
+                match (&left, &right) {
+                    (Value::Int(left), Value::Int(right)) => self.int_arithmetic(*left, operator, *right),
+                    (Value::Int(_) | Value::Number(_), Value::Int(_) | Value::Number(_)) => {
+                        self.float_arithmetic(left.as_number().unwrap_or(f64::NAN), operator, right.as_number().unwrap_or(f64::NAN))
+                    }
+                    (Value::String(left), Value::String(right)) => {
+                        match operator.token_type {
+                            TokenType::Plus => Ok(Value::String(format!("{}{}", left, right))),
+                            _ => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("Unexpected token type: '{}' for Binary Expression", operator.token_type))),
+                        }
+                    }
+                    (left, Value::String(right)) => {
+                        match operator.token_type {
+                            TokenType::Plus => Ok(Value::String(format!("{}{}", self.display(left), right))),
+                            _ => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("Unexpected token type: '{}' for Binary Expression", operator.token_type))),
+                        }
+                    }
+                    (Value::String(left), right) => {
+                        match operator.token_type {
+                            TokenType::Plus => Ok(Value::String(format!("{}{}", left, self.display(right)))),
+                            _ => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("Unexpected token type: '{}' for Binary Expression", operator.token_type))),
+                        }
+                    }
+                    _ => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("Unexpected values: '{}' and '{}' for Binary Expression: {} {} {}", left, right, left, operator.token_type, right))),
+                }
+            }
 
-    #[test]
-    fn test_evaluate_unary_expression() {
-        assert_eq!(get_result_from_expression("-1"), Ok(Value::Number(-1.0)));
-        assert_eq!(get_result_from_expression("!true"), Ok(Value::Boolean(false)));
-        assert_eq!(get_result_from_expression("!false"), Ok(Value::Boolean(true)));
-        assert_eq!(get_result_from_expression("!!true"), Ok(Value::Boolean(true)));
-        assert_eq!(get_result_from_expression("!!false"), Ok(Value::Boolean(false)));
-        assert_eq!(get_result_from_expression("!!1"), Ok(Value::Boolean(true)));
-        assert_eq!(get_result_from_expression("!!nil"), Ok(Value::Boolean(false)));
+            TokenType::Ampersand | TokenType::Pipe | TokenType::Caret | TokenType::LessLess | TokenType::GreaterGreater => {
+                bitwise(as_i64(&left)?, operator, as_i64(&right)?)
+            }
+
+            _ => Err(RloxError::runtime(RuntimeErrorKind::Internal, format!("Unexpected token type: '{}' for Binary Expression", operator.token_type))),
+        }
     }
 
-    #[test]
-    fn test_evaluate_binary_numbers_expression() {
-        assert_eq!(get_result_from_expression("1 + 2"), Ok(Value::Number(3.0)));
-        assert_eq!(get_result_from_expression("1 - 2"), Ok(Value::Number(-1.0)));
-        assert_eq!(get_result_from_expression("1 * 2"), Ok(Value::Number(2.0)));
-        assert_eq!(get_result_from_expression("1 / 2"), Ok(Value::Number(0.5)));
-        assert_eq!(get_result_from_expression("1 + 2 * 3"), Ok(Value::Number(7.0)));
-        assert_eq!(get_result_from_expression("(1 + 2) * 3"), Ok(Value::Number(9.0)));
-        assert_eq!(get_result_from_expression("1 + 2 * 3 + 4 / 2"), Ok(Value::Number(9.0)));
-        assert_eq!(get_result_from_expression("1 + 1 + (2 + 3) + 5 + (8 + 13)"), Ok(Value::Number(33.0)));
+    fn assign(&mut self, name: &Token, value: Value) -> Result<Value, RloxError> {
+        self.environment.assign(name.lexeme.clone(), value.clone())?;
+        self.watch(&name.lexeme, &value);
+        Ok(value)
     }
 
-    #[test]
-    fn test_division_by_zero_error() {
-        assert_eq!(get_result_from_expression("1 / 0"), Err(String::from("Division by zero: 1 / 0")));
+    // Calls to Lox functions get a frame in the trace, natives run without one
+    fn call(&mut self, callee: Value, arguments: Vec<Value>, line: usize) -> Result<Value, RloxError> {
+        let Value::Function(function) = &callee else {
+            return self.call_value(callee, arguments);
+        };
+        self.trace.push((Some(format!("call fn {}", function.name.lexeme)), line));
+        let value = self.call_value(callee, arguments)?;
+        self.trace.pop();
+        Ok(value)
     }
+}
 
-    #[test]
-    fn test_evaluate_binary_bool_expression() {
-        assert_eq!(get_result_from_expression("true == false"), Ok(Value::Boolean(false)));
+fn unary(operator: &Token, right: Value) -> Result<Value, RloxError> {
+    match operator.token_type {
+        TokenType::Minus => {
+            match right {
+                Value::Number(number) => Ok(Value::Number(-number)),
+                // Ints have no -0, so negating 0 gives the float like jlox does
+                Value::Int(0) => Ok(Value::Number(-0.0)),
+                Value::Int(int) => Ok(int.checked_neg().map_or(Value::Number(-(int as f64)), Value::Int)),
+                _ => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("Unexpected value: '{}' for Unary Expression: -{}", right, right))),
+            }
+        }
+        TokenType::Bang => {
+            Ok(Value::Boolean(!right.is_truthy()))
+        }
+        _ => Err(RloxError::runtime(RuntimeErrorKind::Internal, format!("Unexpected token type: '{}' for Unary Expression", operator.token_type))),
+    }
+}
+
+fn ternary(left: Value, operator1: &Token, middle: Value, operator2: &Token, right: Value) -> Result<Value, RloxError> {
+    match operator1.token_type {
+        TokenType::QuestionMark => {
+            match operator2.token_type {
+                TokenType::Colon => {
+                    if left.is_truthy() {
+                        Ok(middle)
+                    } else {
+                        Ok(right)
+                    }
+                }
+                _ => Err(RloxError::runtime(RuntimeErrorKind::Internal, format!("Unexpected token type: '{}' for Ternary Expression: {} {} {} {} {}", operator2.token_type, left, operator1.token_type, middle, operator2.token_type, right))),
+            }
+        }
+        _ => Err(RloxError::runtime(RuntimeErrorKind::Internal, format!("Unexpected token type: '{}' for Ternary Expression: {} {} {} {} {}", operator1.token_type, left, operator1.token_type, middle, operator2.token_type, right))),
+    }
+}
+
+fn get(object: &Value, name: &Token) -> Result<Value, RloxError> {
+    if let Value::Instance(instance) = object {
+        if let Some(field) = instance.fields.borrow().get(&name.lexeme) {
+            return Ok(field.clone());
+        }
+        return match instance.class.find_method(&name.lexeme) {
+            Some(method) => Ok(Value::Function(Rc::new(method.bind(object.clone())))),
+            None => Err(RloxError::runtime(RuntimeErrorKind::UndefinedProperty, format!("Undefined property '{}'.", name.lexeme))),
+        };
+    }
+    match (primitive_method(object, &name.lexeme), object) {
+        (Some(method), _) => Ok(method),
+        (None, Value::String(_) | Value::Number(_) | Value::Int(_) | Value::List(_) | Value::Map(_)) => {
+            Err(RloxError::runtime(RuntimeErrorKind::UndefinedProperty, format!("Undefined property '{}'.", name.lexeme)))
+        }
+        (None, _) => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, "Only instances have properties.")),
+    }
+}
+
+// Always an Int. A left shift that would push bits past the sign is an error rather than
+// wrapping around.
+fn bitwise(left: i64, operator: &Token, right: i64) -> Result<Value, RloxError> {
+    let shift = || match u32::try_from(right) {
+        Ok(shift) if shift < 64 => Ok(shift),
+        _ => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("Shift amount must be between 0 and 63, got {}.", right))),
+    };
+    let result = match operator.token_type {
+        TokenType::Ampersand => left & right,
+        TokenType::Pipe => left | right,
+        TokenType::Caret => left ^ right,
+        TokenType::LessLess => {
+            let shift = shift()?;
+            let shifted = left << shift;
+            if shifted >> shift != left {
+                return Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, "Number too large for bitwise operation."));
+            }
+            shifted
+        }
+        TokenType::GreaterGreater => left >> shift()?,
+        _ => return Err(RloxError::runtime(RuntimeErrorKind::Internal, format!("Unexpected token type: '{}' for Binary Expression", operator.token_type))),
+    };
+    Ok(Value::Int(result))
+}
+
+fn index_get(collection: &Value, index: &Value) -> Result<Value, RloxError> {
+    match collection {
+        Value::List(list) => {
+            let list = list.borrow();
+            Ok(list[list_position(index, list.len())?].clone())
+        }
+        Value::Map(map) => {
+            let key = map_key(index.clone())?;
+            map.borrow().get(&key).cloned().ok_or_else(|| RloxError::runtime(RuntimeErrorKind::UndefinedProperty, format!("Undefined key '{}'.", key)))
+        }
+        _ => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("Can only index lists and maps, got {}.", collection.type_name()))),
+    }
+}
+
+// An element of a list is replaced, a map entry is added or replaced
+fn index_set(collection: &Value, index: &Value, value: Value) -> Result<(), RloxError> {
+    match collection {
+        Value::List(list) => {
+            let mut list = list.borrow_mut();
+            let position = list_position(index, list.len())?;
+            list[position] = value;
+            Ok(())
+        }
+        Value::Map(map) => {
+            map.borrow_mut().insert(map_key(index.clone())?, value);
+            Ok(())
+        }
+        _ => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("Can only index lists and maps, got {}.", collection.type_name()))),
+    }
+}
+
+// Indexes are whole numbers from 0 up to the length, exclusive
+fn list_position(index: &Value, length: usize) -> Result<usize, RloxError> {
+    let position = match index.as_number() {
+        Some(number) if is_integer(number) => number,
+        _ => return Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("List index must be a whole number, got {}.", index))),
+    };
+    if position < 0.0 || position >= length as f64 {
+        return Err(RloxError::runtime(RuntimeErrorKind::IndexOutOfRange, format!("Index {} is out of range for a list of length {}.", index, length)));
+    }
+    Ok(position as usize)
+}
+
+// A new list with the elements from start up to end, exclusive. Like Python, negative
+// bounds count from the end and out-of-range bounds are clamped rather than an error.
+fn slice(collection: &Value, start: Option<&Value>, end: Option<&Value>) -> Result<Value, RloxError> {
+    let Value::List(list) = collection else {
+        return Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("Can only slice lists, got {}.", collection.type_name())));
+    };
+    let list = list.borrow();
+    let start = slice_bound(start, 0, list.len())?;
+    let end = slice_bound(end, list.len(), list.len())?;
+    Ok(Value::list(if start < end { list[start..end].to_vec() } else { Vec::new() }))
+}
+
+fn slice_bound(bound: Option<&Value>, default: usize, length: usize) -> Result<usize, RloxError> {
+    let Some(bound) = bound else {
+        return Ok(default);
+    };
+    let position = match bound.as_number() {
+        Some(number) if is_integer(number) => number,
+        _ => return Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("Slice bounds must be whole numbers, got {}.", bound))),
+    };
+    let position = if position < 0.0 { position + length as f64 } else { position };
+    Ok(position.clamp(0.0, length as f64) as usize)
+}
+
+fn map_key(key: Value) -> Result<String, RloxError> {
+    match key {
+        Value::String(key) => Ok(key),
+        key => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("Map keys must be strings, got {}.", key))),
+    }
+}
+
+// true -> 1, false -> 0 and nil -> 0, strings are left alone so concatenation still applies
+fn to_number_coerced(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => Some(*number),
+        Value::Int(int) => Some(*int as f64),
+        Value::Boolean(boolean) => Some(if *boolean { 1.0 } else { 0.0 }),
+        Value::Nil => Some(0.0),
+        _ => None,
+    }
+}
+
+fn coerce_operands(left: Value, right: Value) -> (Value, Value) {
+    match (to_number_coerced(&left), to_number_coerced(&right)) {
+        (Some(left), Some(right)) => (Value::Number(left), Value::Number(right)),
+        _ => (left, right),
+    }
+}
+
+// Numbers print like jlox/JavaScript: integral values without a fraction,
+// exponent notation outside [1e-6, 1e21), and NaN/Infinity spelled out
+pub fn format_number(number: f64) -> String {
+    if number.is_nan() {
+        String::from("NaN")
+    } else if number.is_infinite() {
+        String::from(if number > 0.0 { "Infinity" } else { "-Infinity" })
+    } else if number == 0.0 {
+        String::from(if number.is_sign_negative() { "-0" } else { "0" })
+    } else if number.abs() >= 1e21 || number.abs() < 1e-6 {
+        let formatted = format!("{:e}", number);
+        match formatted.split_once('e') {
+            Some((mantissa, exponent)) if !exponent.starts_with('-') => format!("{}e+{}", mantissa, exponent),
+            _ => formatted,
+        }
+    } else {
+        format!("{}", number)
+    }
+}
+
+// A literal node for a value computed ahead of time, with the token the parser would
+// have produced for it
+fn literal(value: Value, line: usize) -> Expr {
+    let token = match &value {
+        Value::Number(number) => Token::new(TokenType::Number(*number), format_number(*number), line),
+        Value::Int(int) => Token::new(TokenType::Int(*int), int.to_string(), line),
+        Value::String(string) => Token::new(TokenType::String(string.clone()), format!("\"{}\"", string), line),
+        Value::Boolean(true) => Token::new(TokenType::True, String::from("true"), line),
+        Value::Boolean(false) => Token::new(TokenType::False, String::from("false"), line),
+        value => Token::new(TokenType::Nil, value.to_string(), line),
+    };
+    Expr::Literal(token, value)
+}
+
+// format_number with a different decimal separator and optional grouping of the
+// integer digits, which uses whichever of '.' and ',' isn't the decimal separator
+pub fn format_localized(number: f64, decimal_comma: bool, group_thousands: bool) -> String {
+    let formatted = format_number(number);
+    if !number.is_finite() || formatted.contains('e') {
+        return formatted.replace('.', if decimal_comma { "," } else { "." });
+    }
+    localize(&formatted, decimal_comma, group_thousands)
+}
+
+// Rewrites a plain decimal like -1234.5, shared with Ints which have no float to format
+fn localize(formatted: &str, decimal_comma: bool, group_thousands: bool) -> String {
+    let (decimal, group) = if decimal_comma { (',', '.') } else { ('.', ',') };
+    let (sign, digits) = match formatted.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", formatted),
+    };
+    let (integer, fraction) = match digits.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (digits, None),
+    };
+    let mut localized = String::from(sign);
+    for (index, digit) in integer.chars().enumerate() {
+        if group_thousands && index > 0 && (integer.len() - index) % 3 == 0 {
+            localized.push(group);
+        }
+        localized.push(digit);
+    }
+    if let Some(fraction) = fraction {
+        localized.push(decimal);
+        localized.push_str(fraction);
+    }
+    localized
+}
+
+// Collections nested deeper than this print as [...] or {...}, like one inside itself
+pub const MAX_DISPLAY_DEPTH: usize = 64;
+
+// A list or map as print shows it, e.g. [1, "a", {"b": nil}], with the strings inside
+// quoted and any other element shown by `scalar`. With an indent, each element goes on
+// its own line, indented once per level of nesting.
+pub fn format_collection(value: &Value, indent: Option<&str>, scalar: &dyn Fn(&Value) -> String) -> String {
+    let mut formatted = String::new();
+    write_collection(&mut formatted, value, indent, scalar, &mut Vec::new());
+    formatted
+}
+
+// `open` holds the collections being written, outermost first, to catch one inside itself
+fn write_collection(formatted: &mut String, value: &Value, indent: Option<&str>, scalar: &dyn Fn(&Value) -> String, open: &mut Vec<*const ()>) {
+    let (list, map);
+    let (pointer, brackets, entries): (*const (), _, Vec<(Option<&String>, &Value)>) = match value {
+        Value::List(rc) => {
+            list = rc.borrow();
+            (Rc::as_ptr(rc) as *const (), ('[', ']'), list.iter().map(|element| (None, element)).collect())
+        }
+        Value::Map(rc) => {
+            map = rc.borrow();
+            (Rc::as_ptr(rc) as *const (), ('{', '}'), map.iter().map(|(key, value)| (Some(key), value)).collect())
+        }
+        value => {
+            formatted.push_str(&scalar(value));
+            return;
+        }
+    };
+    formatted.push(brackets.0);
+    if open.contains(&pointer) || open.len() >= MAX_DISPLAY_DEPTH {
+        formatted.push_str("...");
+        formatted.push(brackets.1);
+        return;
+    }
+    open.push(pointer);
+    for (position, (key, element)) in entries.iter().enumerate() {
+        if position > 0 {
+            formatted.push(',');
+            if indent.is_none() {
+                formatted.push(' ');
+            }
+        }
+        if let Some(indent) = indent {
+            formatted.push('\n');
+            formatted.push_str(&indent.repeat(open.len()));
+        }
+        if let Some(key) = key {
+            formatted.push_str(&format!("{:?}: ", key));
+        }
+        match element {
+            Value::String(string) => formatted.push_str(&format!("{:?}", string)),
+            element => write_collection(formatted, element, indent, scalar, open),
+        }
+    }
+    open.pop();
+    if let (Some(indent), false) = (indent, entries.is_empty()) {
+        formatted.push('\n');
+        formatted.push_str(&indent.repeat(open.len()));
+    }
+    formatted.push(brackets.1);
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Number(number) => write!(f, "{}", format_number(*number)),
+            Value::Int(int) => write!(f, "{}", int),
+            Value::String(string) => write!(f, "{}", string),
+            Value::Boolean(boolean) => write!(f, "{}", boolean),
+            Value::NativeFunction(native) => write!(f, "<native fn {}>", native.name),
+            Value::Function(function) => write!(f, "<fn {}>", function.name.lexeme),
+            Value::Class(class) => write!(f, "{}", class.name.lexeme),
+            Value::Instance(instance) => write!(f, "{} instance", instance.class.name.lexeme),
+            Value::List(_) | Value::Map(_) => write!(f, "{}", format_collection(self, None, &|element| element.to_string())),
+            Value::Nil => write!(f, "nil"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn run_with_interpreter(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
+        let mut scanner = Scanner::new(String::from(source));
+        let mut parser = Parser::new(scanner.scan_tokens());
+        interpreter.interpret(parser.parse()?).map_err(|err| err.to_string())
+    }
+
+    fn get_result_from_expression(expression: &str) -> Result<Value, String> {
+        get_result_with_interpreter(&mut Interpreter::new(), expression)
+    }
+
+    fn get_result_with_interpreter(interpreter: &mut Interpreter, expression: &str) -> Result<Value, String> {
+        let mut scanner = Scanner::new(String::from(expression));
+        let mut parser = Parser::new(scanner.scan_tokens());
+
+        let expression = parser.comma();
+        match expression {
+            Ok(expression) => interpreter.evaluate_expression(expression).map_err(|err| err.to_string()),
+            Err(error) => Err(error),
+        }
+    }
+
+    #[test]
+    fn test_evaluate_literals_expression() {
+        assert_eq!(get_result_from_expression("true"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_from_expression("false"), Ok(Value::Boolean(false)));
+        assert_eq!(get_result_from_expression("nil"), Ok(Value::Nil));
+        assert_eq!(get_result_from_expression("1"), Ok(Value::Number(1.0)));
+        assert_eq!(get_result_from_expression("1.5"), Ok(Value::Number(1.5)));
+        assert_eq!(get_result_from_expression("\"Hello\""), Ok(Value::String(String::from("Hello"))));
+    }
+
+    #[test]
+    fn test_evaluate_grouping_expression() {
+        assert_eq!(get_result_from_expression("(1)"), Ok(Value::Number(1.0)));
+        assert_eq!(get_result_from_expression("(1 + 2)"), Ok(Value::Number(3.0)));
+        assert_eq!(get_result_from_expression("(1 + 2) * 3"), Ok(Value::Number(9.0)));
+        assert_eq!(get_result_from_expression("1 + (2 * 3)"), Ok(Value::Number(7.0)));
+    }
+
+    #[test]
+    fn test_evaluate_unary_expression() {
+        assert_eq!(get_result_from_expression("-1"), Ok(Value::Number(-1.0)));
+        assert_eq!(get_result_from_expression("!true"), Ok(Value::Boolean(false)));
+        assert_eq!(get_result_from_expression("!false"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_from_expression("!!true"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_from_expression("!!false"), Ok(Value::Boolean(false)));
+        assert_eq!(get_result_from_expression("!!1"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_from_expression("!!nil"), Ok(Value::Boolean(false)));
+    }
+
+    #[test]
+    fn test_evaluate_binary_numbers_expression() {
+        assert_eq!(get_result_from_expression("1 + 2"), Ok(Value::Number(3.0)));
+        assert_eq!(get_result_from_expression("1 - 2"), Ok(Value::Number(-1.0)));
+        assert_eq!(get_result_from_expression("1 * 2"), Ok(Value::Number(2.0)));
+        assert_eq!(get_result_from_expression("1 / 2"), Ok(Value::Number(0.5)));
+        assert_eq!(get_result_from_expression("1 + 2 * 3"), Ok(Value::Number(7.0)));
+        assert_eq!(get_result_from_expression("(1 + 2) * 3"), Ok(Value::Number(9.0)));
+        assert_eq!(get_result_from_expression("1 + 2 * 3 + 4 / 2"), Ok(Value::Number(9.0)));
+        assert_eq!(get_result_from_expression("1 + 1 + (2 + 3) + 5 + (8 + 13)"), Ok(Value::Number(33.0)));
+    }
+
+    #[test]
+    fn test_bitwise() {
+        for (expression, expected) in [
+            ("12 & 10", 8),
+            ("12 | 10", 14),
+            ("12 ^ 10", 6),
+            ("1 << 4", 16),
+            ("-16 >> 2", -4),
+            ("6.0 & 3", 2),
+            ("1 << 62", 1 << 62),
+        ] {
+            assert_eq!(get_result_from_expression(expression), Ok(Value::Int(expected)), "{}", expression);
+        }
+
+        // Decimals are exact up to 2^53, 2^53 + 1 already rounds to 2^53 before the operator sees it
+        assert_eq!(get_result_from_expression("2.0 ** 53 | 1"), Ok(Value::Int((1 << 53) | 1)));
+        assert_eq!(get_result_from_expression("(2.0 ** 53 + 1) | 0"), Ok(Value::Int(1 << 53)));
+        assert_eq!(get_result_from_expression("-(2.0 ** 53) & -1"), Ok(Value::Int(-(1 << 53))));
+        // Ints are exact, so they are used as they are
+        assert_eq!(get_result_from_expression("9007199254740993 & 1"), Ok(Value::Int(1)));
+
+        for (expression, message) in [
+            ("(2.0 ** 53 + 2) | 0", "Number too large for bitwise operation."),
+            ("(10.0 ** 300) & 1", "Number too large for bitwise operation."),
+            ("1 << 63", "Number too large for bitwise operation."),
+            ("1 << 64", "Shift amount must be between 0 and 63, got 64."),
+            ("1 >> -1", "Shift amount must be between 0 and 63, got -1."),
+            ("1.5 | 0", "Bitwise operands must be whole numbers, got 1.5."),
+            ("\"a\" & 1", "Bitwise operands must be whole numbers, got a."),
+        ] {
+            assert_eq!(get_result_from_expression(expression), Err(String::from(message)), "{}", expression);
+        }
+
+        assert_eq!(as_i64(&Value::Number(9_007_199_254_740_992.0)), Ok(9_007_199_254_740_992));
+        assert!(as_i64(&Value::Number(9_007_199_254_740_994.0)).is_err());
+        assert!(as_i64(&Value::Number(f64::INFINITY)).is_err());
+    }
+
+    #[test]
+    fn test_power() {
+        assert_eq!(get_result_from_expression("-2 ** 2"), Ok(Value::Number(-4.0)));
+        assert_eq!(get_result_from_expression("(-2) ** 2"), Ok(Value::Number(4.0)));
+        assert_eq!(get_result_from_expression("2 ** 3 ** 2"), Ok(Value::Number(512.0)));
+        assert_eq!(get_result_from_expression("2 ** -1"), Ok(Value::Number(0.5)));
+    }
+
+    #[test]
+    fn test_int_arithmetic() {
+        // 2^53 + 1 has no exact f64, so only the Int version survives the addition
+        assert!(matches!(get_result_from_expression("9007199254740993 + 1"), Ok(Value::Int(9007199254740994))));
+        assert!(matches!(get_result_from_expression("9007199254740993.0 + 1"), Ok(Value::Number(n)) if n == 9007199254740992.0));
+        assert!(matches!(get_result_from_expression("1 + 1.0"), Ok(Value::Number(n)) if n == 2.0));
+        assert!(matches!(get_result_from_expression("6 / 3"), Ok(Value::Int(2))));
+        assert!(matches!(get_result_from_expression("7 / 2"), Ok(Value::Number(n)) if n == 3.5));
+        assert!(matches!(get_result_from_expression("9223372036854775807 + 1"), Ok(Value::Number(n)) if n == 9223372036854775808.0));
+        assert_eq!(get_result_from_expression("1 == 1.0"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_from_expression("9007199254740993 > 9007199254740992"), Ok(Value::Boolean(true)));
+        assert_eq!(Value::Int(9007199254740993).to_string(), "9007199254740993");
+    }
+
+    #[test]
+    fn test_int_negative_zero() {
+        for expression in ["-0", "0 * -1", "-1 * 0", "0 / -5", "-(0 * 3)"] {
+            assert!(matches!(get_result_from_expression(expression), Ok(Value::Number(n)) if n == 0.0 && n.is_sign_negative()), "{}", expression);
+        }
+        assert!(matches!(get_result_from_expression("0 * 5"), Ok(Value::Int(0))));
+        assert!(matches!(get_result_from_expression("-3 * -2"), Ok(Value::Int(6))));
+    }
+
+    #[test]
+    fn test_division_by_zero_error() {
+        assert_eq!(get_result_from_expression("1 / 0"), Err(String::from("Division by zero: 1 / 0")));
+    }
+
+    #[test]
+    fn test_assert() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(run_with_interpreter(&mut interpreter, "var x = 5; assert(x > 3); assert x == 5;"), Ok(()));
+        assert_eq!(run_with_interpreter(&mut interpreter, "assert(x > 6);"), Err(String::from("Assertion failed: (> x 6)")));
+        assert_eq!(run_with_interpreter(&mut interpreter, "assert !(x - 5 == 0);"), Err(String::from("Assertion failed: (! (group (== (- x 5) 0)))")));
+    }
+
+    #[test]
+    fn test_error_context() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = output.clone();
+        let source = "var a = 1;\n{\n  print a;\n  {\n    var b = 2;\n    print b - \"x\";\n  }\n}";
+        assert!(run_with_interpreter(&mut interpreter, source).is_err());
+        assert_eq!(interpreter.error_context(), Some(String::from("Runtime error at line 6 (in block at line 4, block at line 2)")));
+
+        let source = "fun f(n) {\n  while (n > 0)\n    if (n == 1)\n      print -nil;\n    else n = n - 1;\n}\nf(3);";
+        assert!(run_with_interpreter(&mut interpreter, source).is_err());
+        assert_eq!(
+            interpreter.error_context(),
+            Some(String::from("Runtime error at line 4 (in if at line 3, while at line 2, call fn f at line 7)"))
+        );
+
+        assert!(run_with_interpreter(&mut interpreter, "print 1;\nprint -nil;").is_err());
+        assert_eq!(interpreter.error_context(), Some(String::from("Runtime error at line 2")));
+
+        assert_eq!(run_with_interpreter(&mut interpreter, "{ print 1; }"), Ok(()));
+        assert_eq!(interpreter.error_context(), None);
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "1\n1\n1\n");
+    }
+
+    #[test]
+    fn test_runtime_error_kinds() {
+        let kind_of = |expression: &str| {
+            let expression = Parser::new(Scanner::new(String::from(expression)).scan_tokens()).comma().unwrap();
+            Interpreter::new().evaluate_expression(expression).unwrap_err().kind()
+        };
+        assert_eq!(kind_of("\"a\" - 1"), Some(RuntimeErrorKind::TypeMismatch));
+        assert_eq!(kind_of("\"a\" * \"b\""), Some(RuntimeErrorKind::TypeMismatch));
+        assert_eq!(kind_of("1 / 0"), Some(RuntimeErrorKind::DivByZero));
+        assert_eq!(kind_of("missing"), Some(RuntimeErrorKind::UndefinedVar));
+        assert_eq!(kind_of("1()"), Some(RuntimeErrorKind::NotCallable));
+        assert_eq!(kind_of("is_integer(1, 2)"), Some(RuntimeErrorKind::ArityMismatch));
+        assert_eq!(kind_of("\"a\".nope"), Some(RuntimeErrorKind::UndefinedProperty));
+    }
+
+    #[test]
+    fn test_ieee_division() {
+        let mut interpreter = Interpreter::new();
+        interpreter.ieee_division = true;
+        match get_result_with_interpreter(&mut interpreter, "0 / 0") {
+            Ok(Value::Number(number)) => assert!(number.is_nan()),
+            result => panic!("Expected NaN, got {:?}", result),
+        }
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "1 / 0"), Ok(Value::Number(f64::INFINITY)));
+    }
+
+    #[test]
+    fn test_strict_math() {
+        let mut interpreter = Interpreter::new();
+        interpreter.ieee_division = true;
+        interpreter.strict_math = true;
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "0 / 0"), Err(String::from("Result is not a finite number.")));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "1 / 0"), Err(String::from("Result is not a finite number.")));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "1 / 2"), Ok(Value::Number(0.5)));
+    }
+
+    #[test]
+    fn test_coerce() {
+        let mut interpreter = Interpreter::new();
+        interpreter.coerce = true;
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "true + 1 == 2"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "nil + 1 == 1"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "false * 3"), Ok(Value::Number(0.0)));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "\"a\" + true"), Ok(Value::String(String::from("atrue"))));
+    }
+
+    #[test]
+    fn test_no_coerce_by_default() {
+        assert_eq!(get_result_from_expression("true + 1"), Err(String::from("Unexpected values: 'true' and '1' for Binary Expression: true + 1")));
+        assert_eq!(get_result_from_expression("nil * 2"), Err(String::from("Unexpected values: 'nil' and '2' for Binary Expression: nil * 2")));
+    }
+
+    #[test]
+    fn test_evaluate_binary_bool_expression() {
+        assert_eq!(get_result_from_expression("true == false"), Ok(Value::Boolean(false)));
         assert_eq!(get_result_from_expression("true != false"), Ok(Value::Boolean(true)));
         assert_eq!(get_result_from_expression("true == true"), Ok(Value::Boolean(true)));
         assert_eq!(get_result_from_expression("true != true"), Ok(Value::Boolean(false)));
@@ -299,6 +1416,13 @@ mod tests {
         assert_eq!(get_result_from_expression("\"Hello\" + \" \" + \"World\""), Ok(Value::String(String::from("Hello World"))));
     }
 
+    #[test]
+    fn test_evaluate_binary_string_number_concatenation() {
+        assert_eq!(get_result_from_expression("\"n=\" + 3.0"), Ok(Value::String(String::from("n=3"))));
+        assert_eq!(get_result_from_expression("\"n=\" + 0.25"), Ok(Value::String(String::from("n=0.25"))));
+        assert_eq!(get_result_from_expression("0.5 + \"=n\""), Ok(Value::String(String::from("0.5=n"))));
+    }
+
     #[test]
     fn test_evaluate_binary_comma_expression() {
         assert_eq!(get_result_from_expression("1, 2, 3"), Ok(Value::Number(3.0)));
@@ -333,4 +1457,959 @@ mod tests {
     fn test_error_initialized_variable() {
         assert_eq!(get_result_from_expression("a = 1"), Err(String::from("Undefined variable 'a'.")));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_define_native() {
+        let mut interpreter = Interpreter::new();
+        interpreter.define_native("double", Arity::Fixed(1), |_, args| {
+            match args[0].as_number() {
+                Some(number) => Ok(Value::Number(number * 2.0)),
+                None => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, "Expected a number.")),
+            }
+        });
+
+        let mut scanner = Scanner::new(String::from("double(21)"));
+        let mut parser = Parser::new(scanner.scan_tokens());
+        let expression = parser.expression().unwrap();
+        assert_eq!(interpreter.evaluate_expression(expression.clone()), Ok(Value::Number(42.0)));
+
+        let mut scanner = Scanner::new(String::from("double(1, 2)"));
+        let mut parser = Parser::new(scanner.scan_tokens());
+        let expression = parser.expression().unwrap();
+        assert_eq!(interpreter.evaluate_expression(expression), Err(RloxError::runtime(RuntimeErrorKind::ArityMismatch, "Expected 1 arguments but got 2.")));
+
+        let mut scanner = Scanner::new(String::from("double(\"a\")"));
+        let mut parser = Parser::new(scanner.scan_tokens());
+        let expression = parser.expression().unwrap();
+        assert_eq!(interpreter.evaluate_expression(expression), Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, "Expected a number.")));
+    }
+
+    #[test]
+    fn test_call_value_from_native() {
+        let mut interpreter = Interpreter::new();
+        interpreter.define_native("double", Arity::Fixed(1), |_, args| {
+            match args[0].as_number() {
+                Some(number) => Ok(Value::Number(number * 2.0)),
+                None => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, "Expected a number.")),
+            }
+        });
+        interpreter.define_native("apply", Arity::Fixed(2), |interpreter, mut args| {
+            let argument = args.pop().unwrap();
+            let callee = args.pop().unwrap();
+            interpreter.call_value(callee, vec![argument])
+        });
+
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "apply(double, 4)"), Ok(Value::Number(8.0)));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "apply(1, 4)"), Err(String::from("Can only call functions and classes.")));
+    }
+
+    #[test]
+    fn test_is_integer_native() {
+        assert_eq!(get_result_from_expression("is_integer(3)"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_from_expression("is_integer(-0)"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_from_expression("is_integer(3.5)"), Ok(Value::Boolean(false)));
+        assert_eq!(get_result_from_expression("is_integer(\"3\")"), Ok(Value::Boolean(false)));
+    }
+
+    #[test]
+    fn test_approx_eq_native() {
+        assert_eq!(get_result_from_expression("0.1 + 0.2 == 0.3"), Ok(Value::Boolean(false)));
+        assert_eq!(get_result_from_expression("approx_eq(0.1 + 0.2, 0.3, 0.000000001)"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_from_expression("approx_eq(1, 1.5, 0.5)"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_from_expression("approx_eq(1, 1.5, 0.25)"), Ok(Value::Boolean(false)));
+        assert_eq!(get_result_from_expression("approx_eq(1, \"1\", 0)"), Err(String::from("approx_eq expects numbers, got 1, 1 and 0.")));
+    }
+
+    #[test]
+    fn test_min_max_natives() {
+        assert_eq!(get_result_from_expression("min(3, -1)"), Ok(Value::Number(-1.0)));
+        assert_eq!(get_result_from_expression("max(3, -1)"), Ok(Value::Number(3.0)));
+        assert_eq!(get_result_from_expression("max(1, \"2\")"), Err(String::from("max expects numbers, got 1 and 2.")));
+
+        let mut interpreter = Interpreter::new();
+        interpreter.ieee_division = true;
+        for source in ["max(0 / 0, 1)", "max(1, 0 / 0)", "min(0 / 0, 1)", "min(1, 0 / 0)"] {
+            match get_result_with_interpreter(&mut interpreter, source) {
+                Ok(Value::Number(number)) => assert!(number.is_nan(), "{}", source),
+                result => panic!("{}: {:?}", source, result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_hash_native() {
+        assert_eq!(get_result_from_expression("hash(\"abc\")"), Ok(Value::Number(440920331.0)));
+        assert_eq!(get_result_from_expression("hash(\"abc\") == hash(\"ab\" + \"c\")"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_from_expression("hash(\"\")"), Ok(Value::Number(2166136261.0)));
+        assert_eq!(get_result_from_expression("hash(0) == hash(-0)"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_from_expression("hash(1) == hash(2)"), Ok(Value::Boolean(false)));
+        assert_eq!(get_result_from_expression("hash(nil)"), Ok(Value::Number(0.0)));
+        assert_eq!(get_result_from_expression("hash(hash)"), Err(String::from("Can't hash <native fn hash>.")));
+    }
+
+    #[test]
+    fn test_seeded_rand() {
+        let mut first = Interpreter::new();
+        first.rng = Rng::new(42);
+        let mut second = Interpreter::new();
+        second.rng = Rng::new(42);
+        let sequence = |interpreter: &mut Interpreter| {
+            (0..5).map(|_| get_result_with_interpreter(interpreter, "rand()").unwrap()).collect::<Vec<Value>>()
+        };
+        let numbers = sequence(&mut first);
+        assert_eq!(numbers, sequence(&mut second));
+        assert!(numbers.iter().all(|number| matches!(number, Value::Number(n) if (0.0..1.0).contains(n))));
+
+        for _ in 0..20 {
+            match get_result_with_interpreter(&mut first, "randint(1, 6)") {
+                Ok(Value::Int(n)) => assert!((1..=6).contains(&n)),
+                result => panic!("Expected a die roll, got {:?}", result),
+            }
+        }
+        assert_eq!(get_result_with_interpreter(&mut first, "randint(3, 3)"), Ok(Value::Number(3.0)));
+        assert_eq!(get_result_with_interpreter(&mut first, "randint(3, 3.0)"), Ok(Value::Number(3.0)));
+        for _ in 0..20 {
+            match get_result_with_interpreter(&mut first, "randint(1, 2.0)") {
+                Ok(Value::Number(n)) => assert!(n == 1.0 || n == 2.0),
+                result => panic!("Expected 1 or 2, got {:?}", result),
+            }
+        }
+        assert_eq!(get_result_with_interpreter(&mut first, "randint(1, 2.5)"), Err(String::from("randint expects integers a <= b, got 1 and 2.5.")));
+        assert_eq!(get_result_with_interpreter(&mut first, "randint(2, 1)"), Err(String::from("randint expects integers a <= b, got 2 and 1.")));
+    }
+
+    #[test]
+    fn test_inspect_native() {
+        assert_eq!(get_result_from_expression("inspect(is_integer)"), Ok(Value::String(String::from("<native>"))));
+        assert_eq!(get_result_from_expression("inspect(\"abc\".length)"), Ok(Value::String(String::from("<native>"))));
+        assert_eq!(get_result_from_expression("inspect(1)"), Err(String::from("Can only inspect functions, got 1.")));
+    }
+
+    #[test]
+    fn test_user_functions() {
+        let mut interpreter = Interpreter::new();
+        run_with_interpreter(&mut interpreter, "fun add(a, b) { var sum = a + b; print sum; }").unwrap();
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "add(1, 2)"), Ok(Value::Nil));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "add").map(|value| value.to_string()), Ok(String::from("<fn add>")));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "add(1)"), Err(String::from("Expected 2 arguments but got 1.")));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "add(1, 2, 3)"), Err(String::from("Expected 2 arguments but got 3.")));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "inspect(add)"), Ok(Value::String(String::from("fn add(a, b)"))));
+        // Parameters and locals don't leak into the caller
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "sum"), Err(String::from("Undefined variable 'sum'.")));
+    }
+
+    #[test]
+    fn test_return() {
+        let mut interpreter = Interpreter::new();
+        let source = "
+            fun first_square_above(limit) {
+                var i = 0;
+                while (true) {
+                    { if (i * i > limit) return i; }
+                    i = i + 1;
+                }
+                print \"unreachable\";
+            }
+            fun nothing() { return; print \"unreachable\"; }
+            fun fall_through() {}
+        ";
+        run_with_interpreter(&mut interpreter, source).unwrap();
+        assert!(matches!(get_result_with_interpreter(&mut interpreter, "first_square_above(50)"), Ok(Value::Int(8))));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "nothing()"), Ok(Value::Nil));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "fall_through()"), Ok(Value::Nil));
+
+        // A return inside a loop's body skips its else branch and the rest of the caller's loop goes on
+        run_with_interpreter(&mut interpreter, "fun f() { while (false) {} else return 1; return 2; } var total = 0; for (var i = 0; i < 3; i = i + 1) total = total + f();").unwrap();
+        assert_eq!(interpreter.globals().get("total"), Some(&Value::Int(3)));
+    }
+
+    fn check_stack_overflow(max_call_depth: usize, finite_depth: usize) {
+        let mut interpreter = Interpreter::new();
+        interpreter.max_call_depth = max_call_depth;
+        run_with_interpreter(&mut interpreter, "fun forever(n) { return forever(n + 1); } fun depth(n) { if (n == 0) return 0; return 1 + depth(n - 1); }").unwrap();
+        let mut parser = Parser::new(Scanner::new(String::from("forever(0)")).scan_tokens());
+        let result = interpreter.evaluate_expression(parser.expression().unwrap());
+        assert_eq!(result, Err(RloxError::runtime(RuntimeErrorKind::StackOverflow, "Stack overflow.")));
+
+        // The depth unwinds with the error, so deep but finite recursion still works afterwards
+        assert_eq!(get_result_with_interpreter(&mut interpreter, &format!("depth({})", finite_depth)), Ok(Value::Int(finite_depth as i64)));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, &format!("depth({})", max_call_depth)), Err(String::from("Stack overflow.")));
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        // The default limit fits the stack of a main thread
+        std::thread::Builder::new()
+            .stack_size(8 * 1024 * 1024)
+            .spawn(|| check_stack_overflow(DEFAULT_MAX_CALL_DEPTH, DEFAULT_MAX_CALL_DEPTH - 1))
+            .unwrap()
+            .join()
+            .unwrap();
+        // The CLI's limit on the stack the CLI gives the interpreter
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(|| check_stack_overflow(MAX_CALL_DEPTH, 2000))
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_hoist() {
+        let source = "print twice(2); fun twice(n) { return helper(n) * 2; } fun helper(n) { return n; }";
+        let mut interpreter = Interpreter::new();
+        interpreter.hoist = true;
+        let output = Rc::new(RefCell::new(Vec::new()));
+        interpreter.output = output.clone();
+        assert_eq!(run_with_interpreter(&mut interpreter, source), Ok(()));
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "4\n");
+
+        // Without hoisting the call runs before the declaration
+        let mut interpreter = Interpreter::new();
+        assert_eq!(run_with_interpreter(&mut interpreter, source), Err(String::from("Undefined variable 'twice'.")));
+        // Only top-level declarations move, one in a block still runs in place
+        let mut interpreter = Interpreter::new();
+        interpreter.hoist = true;
+        assert_eq!(run_with_interpreter(&mut interpreter, "{ f(); fun f() {} }"), Err(String::from("Undefined variable 'f'.")));
+    }
+
+    #[test]
+    fn test_closure_counter() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = output.clone();
+        let source = "
+            fun make_counter() {
+                var i = 0;
+                fun count() { i = i + 1; print i; }
+                return count;
+            }
+            var counter = make_counter();
+            counter();
+            counter();
+            var other = make_counter();
+            other();
+        ";
+        assert_eq!(run_with_interpreter(&mut interpreter, source), Ok(()));
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "1\n2\n1\n");
+    }
+
+    #[test]
+    fn test_closure_sees_later_changes() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = output.clone();
+        let source = "
+            {
+                var y = 10;
+                fun show() { print y; }
+                show();
+                y = 20;
+                show();
+                fun is_even(n) { if (n == 0) return true; return is_odd(n - 1); }
+                fun is_odd(n) { if (n == 0) return false; return is_even(n - 1); }
+                print is_even(4);
+            }
+            var count = 0;
+            fun countdown(n) { if (n > 0) { count = count + 1; countdown(n - 1); } }
+            countdown(3);
+        ";
+        assert_eq!(run_with_interpreter(&mut interpreter, source), Ok(()));
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "10\n20\ntrue\n");
+        assert_eq!(interpreter.globals().get("count"), Some(&Value::Int(3)));
+    }
+
+    #[test]
+    fn test_primitive_methods() {
+        assert_eq!(get_result_from_expression("\"hi\".length() == 2"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_from_expression("(2.9).floor() == 2"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_from_expression("\"abc\".upper()"), Ok(Value::String(String::from("ABC"))));
+        assert_eq!(get_result_from_expression("\"  a \".trim().length()"), Ok(Value::Number(1.0)));
+        assert_eq!(get_result_from_expression("(-1.5).abs().ceil()"), Ok(Value::Number(2.0)));
+        assert_eq!(get_result_from_expression("\"abc\".floor()"), Err(String::from("Undefined property 'floor'.")));
+        assert_eq!(get_result_from_expression("\"abc\".length(1)"), Err(String::from("Expected 0 arguments but got 1.")));
+    }
+
+    #[test]
+    fn test_property_errors() {
+        assert_eq!(get_result_from_expression("\"abc\".missing"), Err(String::from("Undefined property 'missing'.")));
+        assert_eq!(get_result_from_expression("(1).missing"), Err(String::from("Undefined property 'missing'.")));
+        assert_eq!(get_result_from_expression("nil.missing"), Err(String::from("Only instances have properties.")));
+        assert_eq!(get_result_from_expression("true.length"), Err(String::from("Only instances have properties.")));
+        assert_eq!(get_result_from_expression("is_integer.name"), Err(String::from("Only instances have properties.")));
+    }
+
+    #[test]
+    fn test_lists_and_maps() {
+        let mut interpreter = Interpreter::new();
+        run_with_interpreter(&mut interpreter, "var l = [1, \"a\", [true]]; var m = {\"x\": 1}; var alias = l;").unwrap();
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "l[1]"), Ok(Value::String(String::from("a"))));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "l[2][0]"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "l[1.0] == l[1]"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "m[\"x\"]"), Ok(Value::Int(1)));
+
+        // Assignment changes the collection in place, every reference sees it
+        run_with_interpreter(&mut interpreter, "l[0] = 2; m[\"y\"] = l; alias.push(nil);").unwrap();
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "m[\"y\"][0]"), Ok(Value::Int(2)));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "l.length()"), Ok(Value::Number(4.0)));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "m.length()"), Ok(Value::Number(2.0)));
+
+        // Equal by contents, maps whatever order their keys were added in
+        assert_eq!(get_result_from_expression("[1, [2]] == [1.0, [2]]"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_from_expression("[1, 2] == [2, 1]"), Ok(Value::Boolean(false)));
+        assert_eq!(get_result_from_expression("{\"a\": 1, \"b\": 2} == {\"b\": 2, \"a\": 1}"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_from_expression("{\"a\": 1} == {\"a\": 2}"), Ok(Value::Boolean(false)));
+        assert!(get_result_from_expression("[]").is_ok_and(|value| value.is_truthy() && value.type_name() == "list"));
+
+        for (expression, message) in [
+            ("l[4]", "Index 4 is out of range for a list of length 4."),
+            ("l[-1]", "Index -1 is out of range for a list of length 4."),
+            ("l[0.5]", "List index must be a whole number, got 0.5."),
+            ("m[\"z\"]", "Undefined key 'z'."),
+            ("m[1] = 2", "Map keys must be strings, got 1."),
+            ("{1: 2}", "Map keys must be strings, got 1."),
+            ("\"abc\"[0]", "Can only index lists and maps, got string."),
+        ] {
+            assert_eq!(get_result_with_interpreter(&mut interpreter, expression), Err(String::from(message)), "{}", expression);
+        }
+        let error = interpreter.evaluate_expression(Parser::new(Scanner::new(String::from("l[9]")).scan_tokens()).expression().unwrap());
+        assert_eq!(error.map_err(|error| error.kind()), Err(Some(RuntimeErrorKind::IndexOutOfRange)));
+    }
+
+    #[test]
+    fn test_slices() {
+        let mut interpreter = Interpreter::new();
+        run_with_interpreter(&mut interpreter, "var l = [0, 1, 2, 3, 4];").unwrap();
+        let list = |elements: &[i64]| Ok(Value::list(elements.iter().map(|element| Value::Int(*element)).collect()));
+        for (expression, expected) in [
+            ("l[1:3]", list(&[1, 2])),
+            ("l[:2]", list(&[0, 1])),
+            ("l[3:]", list(&[3, 4])),
+            ("l[:]", list(&[0, 1, 2, 3, 4])),
+            // Negative bounds count from the end
+            ("l[-2:]", list(&[3, 4])),
+            ("l[:-4]", list(&[0])),
+            // Out-of-range bounds clamp
+            ("l[2:99]", list(&[2, 3, 4])),
+            ("l[-99:1]", list(&[0])),
+            ("l[9:]", list(&[])),
+            ("l[3:1]", list(&[])),
+        ] {
+            assert_eq!(get_result_with_interpreter(&mut interpreter, expression), expected, "{}", expression);
+        }
+
+        // A slice is a copy, changing it leaves the list alone
+        run_with_interpreter(&mut interpreter, "var copy = l[:]; copy[0] = 9;").unwrap();
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "l[0]"), Ok(Value::Int(0)));
+
+        for (expression, message) in [
+            ("l[0.5:]", "Slice bounds must be whole numbers, got 0.5."),
+            ("l[:\"a\"]", "Slice bounds must be whole numbers, got a."),
+            ("{}[1:]", "Can only slice lists, got map."),
+        ] {
+            assert_eq!(get_result_with_interpreter(&mut interpreter, expression), Err(String::from(message)), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn test_classes() {
+        let mut interpreter = Interpreter::new();
+        let source = "class Point {\n\
+            init(x, y) { this.x = x; this.y = y; }\n\
+            sum() { return this.x + this.y; }\n\
+        }\n\
+        class Point3 < Point {\n\
+            init(x, y, z) { super.init(x, y); this.z = z; }\n\
+            sum() { return super.sum() + this.z; }\n\
+        }\n\
+        var p = Point3(1, 2, 3);";
+        run_with_interpreter(&mut interpreter, source).unwrap();
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "p.sum()"), Ok(Value::Int(6)));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "p.x = 10"), Ok(Value::Int(10)));
+        // A bound method remembers its instance
+        run_with_interpreter(&mut interpreter, "var sum = p.sum; p.z = 0;").unwrap();
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "sum()"), Ok(Value::Int(12)));
+        // init always returns the instance, even called again directly
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "p.init(0, 0, 0) == p"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "Point(1, 2) == Point(1, 2)"), Ok(Value::Boolean(false)));
+        assert!(get_result_with_interpreter(&mut interpreter, "p").is_ok_and(|value| value.to_string() == "Point3 instance" && value.type_name() == "instance"));
+        assert!(get_result_with_interpreter(&mut interpreter, "Point").is_ok_and(|value| value.to_string() == "Point" && value.type_name() == "class"));
+
+        for (expression, message) in [
+            ("p.w", "Undefined property 'w'."),
+            ("Point(1)", "Expected 2 arguments but got 1."),
+            ("(1).x = 2", "Only instances have fields."),
+        ] {
+            assert_eq!(get_result_with_interpreter(&mut interpreter, expression), Err(String::from(message)), "{}", expression);
+        }
+        assert_eq!(run_with_interpreter(&mut interpreter, "var NotAClass = 1; class C < NotAClass {}"), Err(String::from("Superclass must be a class.")));
+    }
+
+    #[test]
+    fn test_method_chaining() {
+        let mut interpreter = Interpreter::new();
+        let source = "class Builder {\n\
+            init() { this.parts = []; }\n\
+            set(part) { this.parts.push(part); return this; }\n\
+            child() { return Builder(); }\n\
+        }\n\
+        var builder = Builder();";
+        run_with_interpreter(&mut interpreter, source).unwrap();
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "builder.set(1).set(2) == builder"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "builder.parts"), Ok(Value::list(vec![Value::Int(1), Value::Int(2)])));
+        // Each link applies to what the previous one returned, here a new instance
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "builder.child().set(3).parts"), Ok(Value::list(vec![Value::Int(3)])));
+        run_with_interpreter(&mut interpreter, "builder.child().set(4).label = \"x\";").unwrap();
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "builder.set(5).parts.length()"), Ok(Value::Number(3.0)));
+    }
+
+    #[test]
+    fn test_keys_and_values_natives() {
+        let mut interpreter = Interpreter::new();
+        run_with_interpreter(&mut interpreter, "var m = {\"zeta\": 1, \"alpha\": 2}; m[\"mid\"] = 3; m[\"zeta\"] = 4;").unwrap();
+        // Insertion order, a key assigned again keeps its place
+        let keys = ["zeta", "alpha", "mid"].map(|key| Value::String(String::from(key)));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "keys(m)"), Ok(Value::list(keys.to_vec())));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "values(m)"), Ok(Value::list(vec![Value::Int(4), Value::Int(2), Value::Int(3)])));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "keys({})"), Ok(Value::list(Vec::new())));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "keys([1])"), Err(String::from("keys expects a map, got [1].")));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "values(nil)"), Err(String::from("values expects a map, got nil.")));
+    }
+
+    #[test]
+    fn test_contains_and_index_of() {
+        let mut interpreter = Interpreter::new();
+        run_with_interpreter(&mut interpreter, "var l = [1, \"a\", nil, [2, {\"k\": true}], 1];").unwrap();
+        for (expression, expected) in [
+            ("contains(l, \"a\")", Value::Boolean(true)),
+            ("contains(l, nil)", Value::Boolean(true)),
+            ("contains(l, [2.0, {\"k\": true}])", Value::Boolean(true)),
+            ("contains(l, [2])", Value::Boolean(false)),
+            ("contains(l, \"1\")", Value::Boolean(false)),
+            ("contains([], nil)", Value::Boolean(false)),
+            // The first match
+            ("index_of(l, 1.0)", Value::Number(0.0)),
+            ("index_of(l, [2, {\"k\": true}])", Value::Number(3.0)),
+            ("index_of(l, false)", Value::Number(-1.0)),
+        ] {
+            assert_eq!(get_result_with_interpreter(&mut interpreter, expression), Ok(expected), "{}", expression);
+        }
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "contains(\"abc\", \"a\")"), Err(String::from("contains expects a list, got abc.")));
+        assert_eq!(get_result_with_interpreter(&mut interpreter, "index_of({}, 1)"), Err(String::from("index_of expects a list, got {}.")));
+    }
+
+    #[test]
+    fn test_chars_natives() {
+        assert_eq!(get_result_from_expression("chars(\"abc\").length()"), Ok(Value::Number(3.0)));
+        assert_eq!(get_result_from_expression("from_chars(chars(\"xy\")) == \"xy\""), Ok(Value::Boolean(true)));
+        let chars = ["n", "é", "🦀"].map(|char| Value::String(String::from(char)));
+        assert_eq!(get_result_from_expression("chars(\"né🦀\")"), Ok(Value::list(chars.to_vec())));
+        assert_eq!(get_result_from_expression("from_chars([])"), Ok(Value::String(String::new())));
+        assert_eq!(get_result_from_expression("chars(1)"), Err(String::from("chars expects a string, got 1.")));
+        assert_eq!(get_result_from_expression("from_chars(\"ab\")"), Err(String::from("from_chars expects a list, got ab.")));
+        assert_eq!(get_result_from_expression("from_chars([\"a\", 1])"), Err(String::from("from_chars expects a list of strings, got 1.")));
+    }
+
+    #[test]
+    fn test_call_non_callable() {
+        assert_eq!(get_result_from_expression("\"a\"()"), Err(String::from("Can only call functions and classes.")));
+    }
+
+    #[test]
+    fn test_if_runs_only_the_taken_branch() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = output.clone();
+        let source = "var a = 0; var b = 0;
+            if (1 < 2) a = 1; else b = 1;
+            if (nil) a = 2; else b = 2;
+            if (false) a = 3;
+            if (\"\") if (false) a = 4; else b = 4;
+            print a; print b;";
+        assert_eq!(run_with_interpreter(&mut interpreter, source), Ok(()));
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "1\n4\n");
+    }
+
+    #[test]
+    fn test_while() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = output.clone();
+        let source = "var i = 0; while (i < 3) { print i; i = i + 1; } while (false) print \"never\"; print i;";
+        assert_eq!(run_with_interpreter(&mut interpreter, source), Ok(()));
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "0\n1\n2\n3\n");
+    }
+
+    #[test]
+    fn test_while_else() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = output.clone();
+        let search = |target: i64| format!("var i = 0; while (i < 5) {{ if (i == {}) {{ print \"found\"; break; }} i = i + 1; }} else print \"missing\"; print i;", target);
+
+        assert_eq!(run_with_interpreter(&mut interpreter, &search(7)), Ok(()));
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "missing\n5\n");
+
+        output.borrow_mut().clear();
+        assert_eq!(run_with_interpreter(&mut interpreter, &search(2)), Ok(()));
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "found\n2\n");
+
+        // A break only leaves the innermost loop, and skips the rest of a for body, increment included
+        output.borrow_mut().clear();
+        let source = "for (var i = 0; i < 2; i = i + 1) { for (var j = 0; j < 5; j = j + 1) { if (j == 1) break; print i + j; } }";
+        assert_eq!(run_with_interpreter(&mut interpreter, source), Ok(()));
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "0\n1\n");
+    }
+
+    #[test]
+    fn test_for() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(run_with_interpreter(&mut interpreter, "var sum = 0; for (var i = 1; i <= 5; i = i + 1) sum = sum + i;"), Ok(()));
+        assert_eq!(interpreter.globals().get("sum"), Some(&Value::Number(15.0)));
+        assert_eq!(interpreter.globals().get("i"), None);
+    }
+
+    #[test]
+    fn test_loop_body_scope() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = output.clone();
+        // A closure made in the body keeps the binding of its own iteration
+        let source = "
+            var first; var second;
+            for (var i = 0; i < 2; i = i + 1) {
+                var j = i * 10;
+                fun get() { return j; }
+                if (i == 0) first = get; else second = get;
+            }
+            print first(); print second();
+        ";
+        assert_eq!(run_with_interpreter(&mut interpreter, source), Ok(()));
+        // A variable from the last iteration is gone from a reused scope
+        let source = "var i = 0; while (i < 2) { if (i == 1) print x; var x = i; i = i + 1; }";
+        assert_eq!(run_with_interpreter(&mut interpreter, source), Err(String::from("Undefined variable 'x'.")));
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "0\n10\n");
+    }
+
+    #[test]
+    fn test_for_fresh_binding_per_iteration() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = output.clone();
+        let source = "
+            var a; var b; var c;
+            for (var i = 0; i < 3; i = i + 1) {
+                fun get() { return i; }
+                if (i == 0) a = get; else if (i == 1) b = get; else c = get;
+            }
+            print a(); print b(); print c();
+        ";
+        assert_eq!(run_with_interpreter(&mut interpreter, source), Ok(()));
+        // Changes made in the body carry over to the increment and the next iteration
+        let source = "for (var i = 0; i < 5; i = i + 1) { i = i + 1; print i; }";
+        assert_eq!(run_with_interpreter(&mut interpreter, source), Ok(()));
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "0\n1\n2\n1\n3\n5\n");
+        assert_eq!(interpreter.environment.name.as_deref(), Some("global"));
+    }
+
+    #[test]
+    fn test_continue() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = output.clone();
+        // The increment still runs after a continue, so the loop ends
+        let source = "for (var i = 0; i < 6; i = i + 1) { if (i / 2 == (i / 2).floor()) continue; print i; }";
+        assert_eq!(run_with_interpreter(&mut interpreter, source), Ok(()));
+        // A continue from a nested block still counts as completing the loop for its else
+        let source = "var i = 0; while (i < 4) { i = i + 1; { if (i == 2) continue; } print i; } else print \"done\";";
+        assert_eq!(run_with_interpreter(&mut interpreter, source), Ok(()));
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "1\n3\n5\n1\n3\n4\ndone\n");
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let folded = |interpreter: &mut Interpreter, source: &str| {
+            let statements = Parser::new(Scanner::new(String::from(source)).scan_tokens()).parse().unwrap();
+            interpreter.fold_constants(statements).iter().map(|statement| statement.to_string()).collect::<Vec<String>>()
+        };
+
+        let mut interpreter = Interpreter::new();
+        assert_eq!(folded(&mut interpreter, "print 2 * 3 + 4;"), vec!["(print 10)"]);
+        assert_eq!(folded(&mut interpreter, "var a = x + 1 * 2 - (3);"), vec!["(var a (- (+ x 2) 3))"]);
+        assert_eq!(folded(&mut interpreter, "print \"a\" + 1 > 2 ? -1 : nil;"), vec!["(print (? (> \"a1\" 2) -1 : nil))"]);
+        assert_eq!(folded(&mut interpreter, "while (1 < 2) { print 1 + 1; }"), vec!["(while true (block (print 2)))"]);
+        assert_eq!(folded(&mut interpreter, "assert 1 + 1 == 2;"), vec!["(assert (== (+ 1 1) 2))"]);
+
+        // Division by zero is still raised when the program runs
+        assert_eq!(folded(&mut interpreter, "print 1 + 1 / 0;"), vec!["(print (+ 1 (/ 1 0)))"]);
+        let statements = Parser::new(Scanner::new(String::from("print 1 + 1 / 0;")).scan_tokens()).parse().unwrap();
+        let statements = interpreter.fold_constants(statements);
+        assert_eq!(interpreter.interpret(statements).map_err(|err| err.to_string()), Err(String::from("Division by zero: 1 / 0")));
+
+        interpreter.ieee_division = true;
+        assert_eq!(folded(&mut interpreter, "print 1 / 0;"), vec!["(print Infinity)"]);
+    }
+
+    #[test]
+    fn test_logical_short_circuit() {
+        assert_eq!(get_result_from_expression("false and (1 / 0)"), Ok(Value::Boolean(false)));
+        assert_eq!(get_result_from_expression("true or (1 / 0)"), Ok(Value::Boolean(true)));
+        assert_eq!(get_result_from_expression("nil or \"default\""), Ok(Value::String(String::from("default"))));
+        assert_eq!(get_result_from_expression("1 and 2"), Ok(Value::Number(2.0)));
+        assert_eq!(get_result_from_expression("nil and 1"), Ok(Value::Nil));
+        assert_eq!(get_result_from_expression("true and (1 / 0)"), Err(String::from("Division by zero: 1 / 0")));
+
+        let mut interpreter = Interpreter::new();
+        assert_eq!(run_with_interpreter(&mut interpreter, "var a = 0; true or (a = 1); false and (a = 2); false or (a = 3);"), Ok(()));
+        assert_eq!(interpreter.globals().get("a"), Some(&Value::Number(3.0)));
+    }
+
+    #[test]
+    fn test_try_catch() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = output.clone();
+        let source = "var a = 1; try { { var b = 2; a = b / 0; } } catch (e) { print e; } print a; print b;";
+        assert_eq!(run_with_interpreter(&mut interpreter, source), Err(String::from("Undefined variable 'b'.")));
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "Division by zero: 2 / 0\n1\n");
+        assert_eq!(interpreter.error_context(), Some(String::from("Runtime error at line 1")));
+        assert_eq!(interpreter.environment.name.as_deref(), Some("global"));
+    }
+
+    #[test]
+    fn test_throw() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = output.clone();
+        let source = "try { throw \"no \" + 1; print \"unreachable\"; } catch (e) { print \"caught \" + e; } throw 2.5;";
+        let statements = Parser::new(Scanner::new(String::from(source)).scan_tokens()).parse().unwrap();
+        let err = interpreter.interpret(statements).unwrap_err();
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "caught no 1\n");
+        assert_eq!(err, RloxError::runtime(RuntimeErrorKind::Thrown, "2.5"));
+    }
+
+    #[test]
+    fn test_var_without_initializer() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = output.clone();
+        assert_eq!(run_with_interpreter(&mut interpreter, "var x; print x; x = 1; print x; { var y; print y == nil; }"), Ok(()));
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "nil\n1\ntrue\n");
+    }
+
+    #[test]
+    fn test_globals() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(run_with_interpreter(&mut interpreter, "var answer = 42; { var hidden = 1; answer = answer + hidden; }"), Ok(()));
+        assert_eq!(interpreter.globals().get("answer"), Some(&Value::Number(43.0)));
+        assert_eq!(interpreter.globals().get("hidden"), None);
+
+        interpreter.environment.push(Environment::named("block"));
+        assert!(interpreter.globals().contains_key("answer"));
+    }
+
+    #[test]
+    fn test_var_group_binds_all() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(run_with_interpreter(&mut interpreter, "var a = 1, b = a + 1, c;"), Ok(()));
+        assert_eq!(interpreter.environment.get(&String::from("a")), Ok(Value::Number(1.0)));
+        assert_eq!(interpreter.environment.get(&String::from("b")), Ok(Value::Number(2.0)));
+        assert_eq!(interpreter.environment.get(&String::from("c")), Ok(Value::Nil));
+    }
+
+    #[test]
+    fn test_print_output_sink() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = output.clone();
+        assert_eq!(run_with_interpreter(&mut interpreter, "print 1 + 2; print \"a\";"), Ok(()));
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "3\na\n");
+    }
+
+    #[test]
+    fn test_annotate_types() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = output.clone();
+        interpreter.annotate_types = true;
+        assert_eq!(run_with_interpreter(&mut interpreter, "print 3; print 1.5; print \"hi\"; print 1 < 2; print nil; print eprint;"), Ok(()));
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "number: 3\nnumber: 1.5\nstring: hi\nbool: true\nnil\nfunction: <native fn eprint>\n");
+    }
+
+    #[test]
+    fn test_print_blank_line() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = output.clone();
+        assert_eq!(run_with_interpreter(&mut interpreter, "print 1; print; print 2;"), Ok(()));
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "1\n\n2\n");
+    }
+
+    #[test]
+    fn test_eprint_error_sink() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let error_output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = output.clone();
+        interpreter.error_output = error_output.clone();
+        assert_eq!(run_with_interpreter(&mut interpreter, "eprint(\"uh \"); eprintln(\"oops\");"), Ok(()));
+        assert_eq!(String::from_utf8(error_output.borrow().clone()).unwrap(), "uh oops\n");
+        assert!(output.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_watcher() {
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let seen = changes.clone();
+        let mut interpreter = Interpreter::new();
+        interpreter.watcher = Some(Box::new(move |name, value| {
+            if name == "a" {
+                seen.borrow_mut().push(value.to_string());
+            }
+        }));
+        assert_eq!(run_with_interpreter(&mut interpreter, "var a = 1; var b = 2; { a = a + b; b = 5; } a = \"x\";"), Ok(()));
+        assert_eq!(changes.borrow().clone(), vec!["1", "3", "x"]);
+    }
+}
+
+#[cfg(test)]
+mod display_tests {
+    use super::*;
+
+    fn display(number: f64) -> String {
+        Value::Number(number).to_string()
+    }
+
+    #[test]
+    fn test_integral_numbers() {
+        assert_eq!(display(0.0), "0");
+        assert_eq!(display(3.0), "3");
+        assert_eq!(display(-12.0), "-12");
+        assert_eq!(display(100000000000.0), "100000000000");
+        assert_eq!(display(123456789012345680000.0), "123456789012345680000");
+    }
+
+    #[test]
+    fn test_fractional_numbers() {
+        assert_eq!(display(0.5), "0.5");
+        assert_eq!(display(-2.25), "-2.25");
+        assert_eq!(display(0.000001), "0.000001");
+    }
+
+    #[test]
+    fn test_exponent_numbers() {
+        assert_eq!(display(1e21), "1e+21");
+        assert_eq!(display(1.5e300), "1.5e+300");
+        assert_eq!(display(0.0000001), "1e-7");
+        assert_eq!(display(-2.5e-8), "-2.5e-8");
+    }
+
+    #[test]
+    fn test_localized_numbers() {
+        assert_eq!(format_localized(1.5, true, false), "1,5");
+        assert_eq!(format_localized(-1234567.25, true, true), "-1.234.567,25");
+        assert_eq!(format_localized(1234567.25, false, true), "1,234,567.25");
+        assert_eq!(format_localized(123.0, false, true), "123");
+        assert_eq!(format_localized(1e21, true, true), "1e+21");
+        assert_eq!(format_localized(1.5e21, true, true), "1,5e+21");
+        assert_eq!(format_localized(f64::NAN, true, true), "NaN");
+    }
+
+    #[test]
+    fn test_negative_zero() {
+        assert_eq!(display(-0.0), "-0");
+    }
+
+    #[test]
+    fn test_non_finite_numbers() {
+        assert_eq!(display(f64::NAN), "NaN");
+        assert_eq!(display(f64::INFINITY), "Infinity");
+        assert_eq!(display(f64::NEG_INFINITY), "-Infinity");
+    }
+}
+
+#[cfg(test)]
+mod value_display_tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    #[test]
+    fn test_print_lists_and_maps() {
+        assert_eq!(printed("print [1, \"two\", [3.5, nil], {\"k\": true}];"), "[1, \"two\", [3.5, nil], {\"k\": true}]\n");
+        assert_eq!(printed("var m = {\"b\": 1, \"a\": 2}; m[\"c\"] = 3; m[\"b\"] = 4; print m;"), "{\"b\": 4, \"a\": 2, \"c\": 3}\n");
+        assert_eq!(printed("print [] + \"\" + {};"), "[]{}\n");
+        // A collection inside itself is shown once instead of forever
+        assert_eq!(printed("var a = [1]; a.push(a); print a; var m = {}; m[\"self\"] = m; print m;"), "[1, [...]]\n{\"self\": {...}}\n");
+    }
+
+    #[test]
+    fn test_pretty_native() {
+        assert_eq!(printed("print pretty([1, [2, [3]], []]);"), "[\n  1,\n  [\n    2,\n    [\n      3\n    ]\n  ],\n  []\n]\n");
+        assert_eq!(printed("print pretty({\"a\": [1], \"b\": {}});"), "{\n  \"a\": [\n    1\n  ],\n  \"b\": {}\n}\n");
+        assert_eq!(printed("print pretty(\"text\"); print pretty(1.5);"), "text\n1.5\n");
+        // Past the depth limit nesting is cut short, which also bounds a cycle
+        let nested = format!("var l = []; for (var i = 0; i < {}; i = i + 1) l = [l]; print l;", MAX_DISPLAY_DEPTH + 5);
+        assert!(printed(&nested).contains("[...]"));
+    }
+
+    fn printed(source: &str) -> String {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = output.clone();
+        let mut scanner = Scanner::new(String::from(source));
+        let statements = Parser::new(scanner.scan_tokens()).parse().unwrap();
+        interpreter.interpret(statements).unwrap();
+        let printed = String::from_utf8(output.borrow().clone()).unwrap();
+        printed
+    }
+
+    #[test]
+    fn test_print_negative_zero() {
+        assert_eq!(printed("print -0; print 0 * -1; print -0 + 0;"), "-0\n-0\n0\n");
+    }
+
+    #[test]
+    fn test_print_native_returns_its_argument() {
+        assert_eq!(printed("var x = print(1 + 2); print x * 2;"), "3\n6\n");
+        assert_eq!(printed("print print(\"a\") + \"b\";"), "a\nab\n");
+        assert_eq!(printed("print(nil);"), "nil\n");
+    }
+
+    #[test]
+    fn test_decimal_comma() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = output.clone();
+        interpreter.decimal_comma = true;
+        let statements = Parser::new(Scanner::new(String::from("print 1.5; print(2.25); print \"1.5\"; print 1.5 + \"\"; print \"\" + 1.5;")).scan_tokens()).parse().unwrap();
+        interpreter.interpret(statements).unwrap();
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "1,5\n2,25\n1.5\n1,5\n1,5\n");
+    }
+
+    #[test]
+    fn test_every_output_uses_the_number_format() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let error_output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = output.clone();
+        interpreter.error_output = error_output.clone();
+        interpreter.decimal_comma = true;
+        interpreter.group_thousands = true;
+        let source = "debug(1234.5); eprint(1234.5); eprintln(2.5); try { throw 1234.5; } catch (e) { print e; }";
+        let statements = Parser::new(Scanner::new(String::from(source)).scan_tokens()).parse().unwrap();
+        interpreter.interpret(statements).unwrap();
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "1.234,5\n1.234,5\n");
+        assert_eq!(String::from_utf8(error_output.borrow().clone()).unwrap(), "1.234,52,5\n");
+    }
+
+    #[test]
+    fn test_statement_and_concatenation_agree() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = output.clone();
+        interpreter.decimal_comma = true;
+        interpreter.group_thousands = true;
+        let statements = Parser::new(Scanner::new(String::from("print 1234.5; print \"\" + 1234.5; print(1234.5 + \"\");")).scan_tokens()).parse().unwrap();
+        interpreter.interpret(statements).unwrap();
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "1.234,5\n1.234,5\n1.234,5\n");
+    }
+
+    #[test]
+    fn test_debug_native() {
+        assert_eq!(printed("debug(\"a\nb\");"), "\"a\\nb\"\n");
+        assert_eq!(printed("debug(\"tab\tback\\slash\");"), r#""tab\tback\\slash""#.to_string() + "\n");
+        assert_eq!(printed("debug(1.5); debug(nil);"), "1.5\nnil\n");
+    }
+
+    #[test]
+    fn test_print_booleans() {
+        assert_eq!(printed("print true;"), "true\n");
+        assert_eq!(printed("print false;"), "false\n");
+    }
+
+    #[test]
+    fn test_print_nil() {
+        assert_eq!(printed("print nil;"), "nil\n");
+    }
+
+    #[test]
+    fn test_concatenated_booleans_and_nil() {
+        assert_eq!(printed("print \"x=\" + true;"), "x=true\n");
+        assert_eq!(printed("print \"x=\" + nil;"), "x=nil\n");
+    }
+
+    #[test]
+    fn test_booleans_in_error_messages() {
+        assert_eq!(
+            Interpreter::new().interpret(Parser::new(Scanner::new(String::from("-true;")).scan_tokens()).parse().unwrap()),
+            Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, "Unexpected value: 'true' for Unary Expression: -true"))
+        );
+    }
+}
+
+#[cfg(test)]
+mod value_equality_tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn equal(source: &str) -> Value {
+        let expression = Parser::new(Scanner::new(String::from(source)).scan_tokens()).comma().unwrap();
+        Interpreter::new().evaluate_expression(expression).unwrap()
+    }
+
+    #[test]
+    fn test_no_cross_variant_coercion() {
+        assert_ne!(Value::Number(0.0), Value::Boolean(false));
+        assert_ne!(Value::Number(1.0), Value::Boolean(true));
+        assert_ne!(Value::Nil, Value::Boolean(false));
+        assert_ne!(Value::Nil, Value::Number(0.0));
+        assert_ne!(Value::String(String::new()), Value::Boolean(false));
+        assert_ne!(Value::String(String::from("0")), Value::Number(0.0));
+        assert_ne!(Value::String(String::from("nil")), Value::Nil);
+    }
+
+    #[test]
+    fn test_same_variant_equality() {
+        assert_eq!(Value::Nil, Value::Nil);
+        assert_eq!(Value::Number(0.0), Value::Number(-0.0));
+        assert_ne!(Value::Number(f64::NAN), Value::Number(f64::NAN));
+        assert_eq!(Value::String(String::from("a")), Value::String(String::from("a")));
+    }
+
+    #[test]
+    fn test_lox_equality_operator() {
+        assert_eq!(equal("0 == false"), Value::Boolean(false));
+        assert_eq!(equal("nil == false"), Value::Boolean(false));
+        assert_eq!(equal("\"\" == nil"), Value::Boolean(false));
+        assert_eq!(equal("\"1\" == 1"), Value::Boolean(false));
+        assert_eq!(equal("1 != true"), Value::Boolean(true));
+        assert_eq!(equal("nil == nil"), Value::Boolean(true));
+    }
+
+    #[test]
+    fn test_function_identity() {
+        assert_eq!(equal("eprint == eprint"), Value::Boolean(true));
+        assert_eq!(equal("eprint == eprintln"), Value::Boolean(false));
+
+        let mut interpreter = Interpreter::new();
+        interpreter.define_native("f", Arity::Fixed(0), |_, _| Ok(Value::Nil));
+        interpreter.define_native("g", Arity::Fixed(0), |_, _| Ok(Value::Nil));
+        let f = interpreter.environment.get(&String::from("f")).unwrap();
+        let g = interpreter.environment.get(&String::from("g")).unwrap();
+        assert_eq!(f, f.clone());
+        assert_ne!(f, g);
+
+        // Each declaration makes its own function, even with the same body
+        let source = "fun a() { return 1; }\nfun b() { return 1; }\nvar c = a;";
+        let statements = Parser::new(Scanner::new(String::from(source)).scan_tokens()).parse().unwrap();
+        assert_eq!(interpreter.interpret(statements), Ok(()));
+        let a = interpreter.environment.get(&String::from("a")).unwrap();
+        let b = interpreter.environment.get(&String::from("b")).unwrap();
+        let c = interpreter.environment.get(&String::from("c")).unwrap();
+        assert_eq!(a, a.clone());
+        assert_eq!(a, c);
+        assert_ne!(a, b);
+    }
+}
@@ -0,0 +1,357 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::environment::{LoxMap, Value};
+use crate::error::{RloxError, RuntimeErrorKind};
+use crate::interpreter::format_number;
+
+// Deeper documents are refused rather than risking the native stack
+const MAX_DEPTH: usize = 128;
+
+// Maps become objects with their keys in insertion order, lists become arrays and nil
+// becomes null. Functions, classes, instances, NaN, the infinities and a collection
+// inside itself have no JSON form and are errors.
+pub fn to_json(value: &Value) -> Result<String, RloxError> {
+    let mut json = String::new();
+    write_value(&mut json, value, &mut Vec::new())?;
+    Ok(json)
+}
+
+// `open` holds the collections being written, outermost first, to catch one inside itself
+fn write_value(json: &mut String, value: &Value, open: &mut Vec<*const ()>) -> Result<(), RloxError> {
+    match value {
+        Value::Nil => json.push_str("null"),
+        Value::Boolean(boolean) => json.push_str(if *boolean { "true" } else { "false" }),
+        Value::Int(int) => json.push_str(&int.to_string()),
+        Value::Number(number) if number.is_finite() => json.push_str(&format_number(*number)),
+        Value::String(string) => write_string(json, string),
+        Value::List(list) => {
+            enter(open, Rc::as_ptr(list) as *const (), value)?;
+            json.push('[');
+            for (position, element) in list.borrow().iter().enumerate() {
+                if position > 0 {
+                    json.push(',');
+                }
+                write_value(json, element, open)?;
+            }
+            json.push(']');
+            open.pop();
+        }
+        Value::Map(map) => {
+            enter(open, Rc::as_ptr(map) as *const (), value)?;
+            json.push('{');
+            for (position, (key, element)) in map.borrow().iter().enumerate() {
+                if position > 0 {
+                    json.push(',');
+                }
+                write_string(json, key);
+                json.push(':');
+                write_value(json, element, open)?;
+            }
+            json.push('}');
+            open.pop();
+        }
+        Value::Number(_) | Value::NativeFunction(_) | Value::Function(_) | Value::Class(_) | Value::Instance(_) => {
+            return Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("Can't convert {} to JSON.", value)));
+        }
+    }
+    Ok(())
+}
+
+fn enter(open: &mut Vec<*const ()>, pointer: *const (), value: &Value) -> Result<(), RloxError> {
+    if open.contains(&pointer) {
+        return Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("Can't convert a {} that contains itself to JSON.", value.type_name())));
+    }
+    if open.len() >= MAX_DEPTH {
+        return Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, "Too deeply nested to convert to JSON."));
+    }
+    open.push(pointer);
+    Ok(())
+}
+
+fn write_string(json: &mut String, string: &str) {
+    json.push('"');
+    for char in string.chars() {
+        match char {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            char if (char as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", char as u32)),
+            char => json.push(char),
+        }
+    }
+    json.push('"');
+}
+
+// Objects become maps, arrays become lists and null becomes nil. Numbers without a
+// fraction or exponent become Ints when they fit, like number literals do.
+pub fn from_json(text: &str) -> Result<Value, RloxError> {
+    let mut reader = Reader { chars: text.chars().collect(), current: 0 };
+    let value = reader.value(0)?;
+    reader.skip_whitespace();
+    if reader.current < reader.chars.len() {
+        return Err(reader.error("Expect end of input after JSON value."));
+    }
+    Ok(value)
+}
+
+struct Reader {
+    chars: Vec<char>,
+    current: usize,
+}
+
+impl Reader {
+    fn value(&mut self, depth: usize) -> Result<Value, RloxError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') | Some('[') if depth >= MAX_DEPTH => Err(self.error("Too deeply nested.")),
+            Some('{') => self.object(depth),
+            Some('[') => self.array(depth),
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('-' | '0'..='9') => self.number(),
+            Some(_) if self.keyword("true") => Ok(Value::Boolean(true)),
+            Some(_) if self.keyword("false") => Ok(Value::Boolean(false)),
+            Some(_) if self.keyword("null") => Ok(Value::Nil),
+            _ => Err(self.error("Expect a JSON value.")),
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value, RloxError> {
+        self.current += 1;
+        let mut map = LoxMap::new();
+        self.skip_whitespace();
+        if self.matches('}') {
+            return Ok(Value::Map(Rc::new(RefCell::new(map))));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("Expect a string key."));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            if !self.matches(':') {
+                return Err(self.error("Expect ':' after object key."));
+            }
+            let value = self.value(depth + 1)?;
+            map.insert(key, value);
+            self.skip_whitespace();
+            if self.matches('}') {
+                return Ok(Value::Map(Rc::new(RefCell::new(map))));
+            }
+            if !self.matches(',') {
+                return Err(self.error("Expect ',' or '}' after object entry."));
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value, RloxError> {
+        self.current += 1;
+        let mut elements = Vec::new();
+        self.skip_whitespace();
+        if self.matches(']') {
+            return Ok(Value::list(elements));
+        }
+        loop {
+            elements.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            if self.matches(']') {
+                return Ok(Value::list(elements));
+            }
+            if !self.matches(',') {
+                return Err(self.error("Expect ',' or ']' after array element."));
+            }
+        }
+    }
+
+    // The opening quote hasn't been consumed yet
+    fn string(&mut self) -> Result<String, RloxError> {
+        self.current += 1;
+        let mut string = String::new();
+        loop {
+            match self.advance() {
+                None => return Err(self.error("Unterminated string.")),
+                Some('"') => return Ok(string),
+                Some('\\') => {
+                    let escaped = match self.advance() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape()?,
+                        _ => return Err(self.error("Invalid escape sequence.")),
+                    };
+                    string.push(escaped);
+                }
+                Some(char) if (char as u32) < 0x20 => return Err(self.error("Unescaped control character in string.")),
+                Some(char) => string.push(char),
+            }
+        }
+    }
+
+    // \uXXXX, where characters outside the BMP come as a surrogate pair of two escapes
+    fn unicode_escape(&mut self) -> Result<char, RloxError> {
+        let high = self.hex4()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error("Invalid \\u escape."));
+        }
+        if !(self.matches('\\') && self.matches('u')) {
+            return Err(self.error("Expect a low surrogate after a high surrogate."));
+        }
+        let low = self.hex4()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return Err(self.error("Expect a low surrogate after a high surrogate."));
+        }
+        char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)).ok_or_else(|| self.error("Invalid \\u escape."))
+    }
+
+    fn hex4(&mut self) -> Result<u32, RloxError> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self.advance().and_then(|char| char.to_digit(16)).ok_or_else(|| self.error("Invalid \\u escape."))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Result<Value, RloxError> {
+        let start = self.current;
+        self.matches('-');
+        match self.peek() {
+            Some('0') => self.current += 1,
+            Some('1'..='9') => self.digits(),
+            _ => return Err(self.error("Expect a digit.")),
+        }
+        let mut integral = true;
+        if self.matches('.') {
+            integral = false;
+            if !self.peek().is_some_and(|char| char.is_ascii_digit()) {
+                return Err(self.error("Expect a digit after '.'."));
+            }
+            self.digits();
+        }
+        if self.matches('e') || self.matches('E') {
+            integral = false;
+            if !self.matches('+') {
+                self.matches('-');
+            }
+            if !self.peek().is_some_and(|char| char.is_ascii_digit()) {
+                return Err(self.error("Expect a digit in the exponent."));
+            }
+            self.digits();
+        }
+
+        let lexeme = self.chars[start..self.current].iter().collect::<String>();
+        if integral {
+            if let Ok(int) = lexeme.parse::<i64>() {
+                return Ok(Value::Int(int));
+            }
+        }
+        match lexeme.parse::<f64>() {
+            Ok(number) if number.is_finite() => Ok(Value::Number(number)),
+            _ => Err(self.error("Number out of range.")),
+        }
+    }
+
+    fn digits(&mut self) {
+        while self.peek().is_some_and(|char| char.is_ascii_digit()) {
+            self.current += 1;
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let end = self.current + keyword.len();
+        if end <= self.chars.len() && self.chars[self.current..end].iter().copied().eq(keyword.chars()) {
+            self.current = end;
+            return true;
+        }
+        false
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.current += 1;
+        }
+    }
+
+    fn matches(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.current += 1;
+            return true;
+        }
+        false
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let char = self.peek()?;
+        self.current += 1;
+        Some(char)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.current).copied()
+    }
+
+    fn error(&self, message: &str) -> RloxError {
+        RloxError::runtime(RuntimeErrorKind::InvalidJson, format!("Invalid JSON at offset {}: {}", self.current, message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_object_with_nested_array() {
+        let text = r#"{"name":"lox","tags":["a","b"],"nested":{"scores":[1,2.5,-3,[true,null]]}}"#;
+        let value = from_json(text).unwrap();
+        assert_eq!(to_json(&value).unwrap(), text);
+        assert_eq!(from_json(&to_json(&value).unwrap()).unwrap(), value);
+
+        // Whitespace is accepted but not kept
+        let spaced = from_json(" { \"tags\" : [ 1 , 2 ] }\n").unwrap();
+        assert_eq!(to_json(&spaced).unwrap(), r#"{"tags":[1,2]}"#);
+    }
+
+    #[test]
+    fn test_scalars_and_escapes() {
+        assert_eq!(from_json("12").unwrap(), Value::Int(12));
+        assert_eq!(from_json("1.5e2").unwrap(), Value::Number(150.0));
+        assert_eq!(from_json("99999999999999999999").unwrap(), Value::Number(1e20));
+        assert_eq!(from_json(r#""a\"b\\c\né😀""#).unwrap(), Value::String(String::from("a\"b\\c\né😀")));
+        assert_eq!(to_json(&Value::String(String::from("a\"b\\c\n\u{1}"))).unwrap(), r#""a\"b\\c\n\u0001""#);
+        assert_eq!(to_json(&Value::Number(0.5)).unwrap(), "0.5");
+        assert_eq!(to_json(&Value::Nil).unwrap(), "null");
+    }
+
+    #[test]
+    fn test_unconvertible_values() {
+        assert_eq!(to_json(&Value::Number(f64::NAN)).unwrap_err().kind(), Some(RuntimeErrorKind::TypeMismatch));
+
+        let list = Value::list(vec![Value::Int(1)]);
+        if let Value::List(rc) = &list {
+            rc.borrow_mut().push(list.clone());
+        }
+        let err = to_json(&list).unwrap_err();
+        assert_eq!(err, RloxError::runtime(RuntimeErrorKind::TypeMismatch, "Can't convert a list that contains itself to JSON."));
+
+        // The same list twice is fine, it's only a cycle when it's inside itself
+        let shared = Value::list(vec![Value::Int(1)]);
+        assert_eq!(to_json(&Value::list(vec![shared.clone(), shared])).unwrap(), "[[1],[1]]");
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        for text in ["", "[1,]", "{\"a\" 1}", "{1: 2}", "tru", "01", "1.", "\"open", "[1] 2", "NaN", "1e999"] {
+            assert_eq!(from_json(text).unwrap_err().kind(), Some(RuntimeErrorKind::InvalidJson), "{}", text);
+        }
+        let deep = "[".repeat(MAX_DEPTH + 1) + &"]".repeat(MAX_DEPTH + 1);
+        assert!(from_json(&deep).is_err());
+    }
+}
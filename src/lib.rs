@@ -1,14 +1,20 @@
-pub fn add(left: usize, right: usize) -> usize {
-    left + right
-}
+pub mod rlox;
+pub mod tokentype;
+pub mod token;
+pub mod scanner;
+pub mod tools;
+pub mod expressions;
+pub mod parser;
+pub mod resolver;
+pub mod interpreter;
+pub mod statements;
+pub mod environment;
+pub mod error;
+pub mod natives;
+pub mod json;
+pub mod function;
+pub mod class;
+#[cfg(feature = "serde")]
+pub mod cache;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
+pub use environment::Value;
@@ -1,15 +1,4 @@
-mod rlox;
-mod tokentype;
-mod token;
-mod scanner;
-mod tools;
-mod expressions;
-mod parser;
-mod interpreter;
-mod statements;
-mod environment;
-
 fn main() {
     let args = std::env::args().collect();
-    rlox::main(args);
-}
\ No newline at end of file
+    rlox::rlox::main(args);
+}
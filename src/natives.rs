@@ -0,0 +1,374 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::environment::Value;
+use crate::error::{RloxError, RuntimeErrorKind};
+use crate::interpreter::Interpreter;
+use crate::json;
+
+pub type NativeFn = dyn Fn(&mut Interpreter, Vec<Value>) -> Result<Value, RloxError>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arity {
+    Fixed(usize),
+    Variadic,
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match self {
+            Arity::Fixed(arity) => *arity == count,
+            Arity::Variadic => true,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Arity::Fixed(arity) => write!(f, "{}", arity),
+            Arity::Variadic => write!(f, "any number of"),
+        }
+    }
+}
+
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: Arity,
+    pub function: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn call(&self, interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, RloxError> {
+        (self.function)(interpreter, arguments)
+    }
+}
+
+// Natives are compared by identity, two closures can't be compared structurally
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+pub fn define_globals(interpreter: &mut Interpreter) {
+    interpreter.define_native("eprint", Arity::Fixed(1), |interpreter, args| {
+        let text = interpreter.display(&args[0]);
+        write!(interpreter.error_output.borrow_mut(), "{}", text).map_err(io_error)?;
+        Ok(Value::Nil)
+    });
+    interpreter.define_native("eprintln", Arity::Fixed(1), |interpreter, args| {
+        let text = interpreter.display(&args[0]);
+        writeln!(interpreter.error_output.borrow_mut(), "{}", text).map_err(io_error)?;
+        Ok(Value::Nil)
+    });
+    // Like the print statement but returns its argument, for debugging inside expressions
+    interpreter.define_native("print", Arity::Fixed(1), |interpreter, mut args| {
+        let text = interpreter.display(&args[0]);
+        writeln!(interpreter.output.borrow_mut(), "{}", text).map_err(io_error)?;
+        Ok(args.remove(0))
+    });
+    // A list or map with one element per line, indented by nesting, e.g. for print pretty(config);
+    interpreter.define_native("pretty", Arity::Fixed(1), |interpreter, args| {
+        Ok(Value::String(interpreter.display_pretty(&args[0])))
+    });
+    // Shows strings quoted with escapes, like "a\nb", everything else as print would
+    interpreter.define_native("debug", Arity::Fixed(1), |interpreter, args| {
+        let text = match &args[0] {
+            Value::String(string) => format!("{:?}", string),
+            value => interpreter.display(value),
+        };
+        writeln!(interpreter.output.borrow_mut(), "{}", text).map_err(io_error)?;
+        Ok(Value::Nil)
+    });
+    interpreter.define_native("is_integer", Arity::Fixed(1), |_, args| {
+        match args[0] {
+            Value::Number(number) => Ok(Value::Boolean(is_integer(number))),
+            Value::Int(_) => Ok(Value::Boolean(true)),
+            _ => Ok(Value::Boolean(false)),
+        }
+    });
+    interpreter.define_native("approx_eq", Arity::Fixed(3), |_, args| {
+        match (args[0].as_number(), args[1].as_number(), args[2].as_number()) {
+            (Some(a), Some(b), Some(epsilon)) => Ok(Value::Boolean((a - b).abs() <= epsilon)),
+            _ => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("approx_eq expects numbers, got {}, {} and {}.", args[0], args[1], args[2]))),
+        }
+    });
+    // NaN propagates: if either argument is NaN so is the result, unlike f64::min/max
+    interpreter.define_native("min", Arity::Fixed(2), |_, args| {
+        match (&args[0], &args[1]) {
+            (Value::Int(a), Value::Int(b)) => Ok(Value::Int(*a.min(b))),
+            (a, b) => match (a.as_number(), b.as_number()) {
+                (Some(a), Some(b)) => Ok(Value::Number(nan_aware(a, b, Ordering::Less))),
+                _ => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("min expects numbers, got {} and {}.", args[0], args[1]))),
+            },
+        }
+    });
+    interpreter.define_native("max", Arity::Fixed(2), |_, args| {
+        match (&args[0], &args[1]) {
+            (Value::Int(a), Value::Int(b)) => Ok(Value::Int(*a.max(b))),
+            (a, b) => match (a.as_number(), b.as_number()) {
+                (Some(a), Some(b)) => Ok(Value::Number(nan_aware(a, b, Ordering::Greater))),
+                _ => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("max expects numbers, got {} and {}.", args[0], args[1]))),
+            },
+        }
+    });
+    interpreter.define_native("rand", Arity::Fixed(0), |interpreter, _| {
+        Ok(Value::Number(interpreter.rng.next_f64()))
+    });
+    // Inclusive on both ends, like randint(1, 6) for a die
+    interpreter.define_native("randint", Arity::Fixed(2), |interpreter, args| {
+        match (args[0].as_number(), args[1].as_number()) {
+            (Some(low), Some(high)) if is_integer(low) && is_integer(high) && low <= high => {
+                let offset = (interpreter.rng.next_f64() * (high - low + 1.0)).floor();
+                // Two Ints give an Int, exact even past 2^53
+                match (&args[0], &args[1]) {
+                    (Value::Int(low), Value::Int(_)) => Ok(Value::Int(low + offset as i64)),
+                    _ => Ok(Value::Number(low + offset)),
+                }
+            }
+            _ => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("randint expects integers a <= b, got {} and {}.", args[0], args[1]))),
+        }
+    });
+    // Stable across runs and platforms, unlike std's RandomState
+    interpreter.define_native("hash", Arity::Fixed(1), |_, args| {
+        let hash = match &args[0] {
+            Value::String(string) => fnv1a(string.as_bytes()),
+            // -0 == 0 and 1 == 1.0 in Lox, so each pair hashes the same
+            Value::Number(_) | Value::Int(_) => {
+                let number = args[0].as_number().unwrap_or_default();
+                let number = if number == 0.0 { 0.0f64 } else { number };
+                fnv1a(&[&[b'n'][..], &number.to_bits().to_le_bytes()].concat())
+            }
+            Value::Boolean(false) => 1,
+            Value::Boolean(true) => 2,
+            Value::Nil => 0,
+            // Lists and maps can change after being hashed
+            Value::NativeFunction(_) | Value::Function(_) | Value::List(_) | Value::Map(_) | Value::Class(_) | Value::Instance(_) => {
+                return Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("Can't hash {}.", args[0])));
+            }
+        };
+        Ok(Value::Number(hash as f64))
+    });
+    // One string per Unicode scalar value, so "né" gives two, not one per byte
+    interpreter.define_native("chars", Arity::Fixed(1), |_, args| {
+        match &args[0] {
+            Value::String(string) => Ok(Value::list(string.chars().map(|char| Value::String(char.to_string())).collect())),
+            _ => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("chars expects a string, got {}.", args[0]))),
+        }
+    });
+    // Joins a list of strings back into one, the inverse of chars
+    interpreter.define_native("from_chars", Arity::Fixed(1), |_, args| {
+        let Value::List(list) = &args[0] else {
+            return Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("from_chars expects a list, got {}.", args[0])));
+        };
+        let mut joined = String::new();
+        for element in list.borrow().iter() {
+            match element {
+                Value::String(string) => joined.push_str(string),
+                _ => return Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("from_chars expects a list of strings, got {}.", element))),
+            }
+        }
+        Ok(Value::String(joined))
+    });
+    // Elements are compared with ==, so [1] is found in [[1.0]]
+    interpreter.define_native("contains", Arity::Fixed(2), |_, args| {
+        Ok(Value::Boolean(position(&args[0], &args[1], "contains")?.is_some()))
+    });
+    interpreter.define_native("index_of", Arity::Fixed(2), |_, args| {
+        Ok(Value::Number(position(&args[0], &args[1], "index_of")?.map_or(-1.0, |position| position as f64)))
+    });
+    // Both in the order the keys were first added
+    interpreter.define_native("keys", Arity::Fixed(1), |_, args| {
+        match &args[0] {
+            Value::Map(map) => Ok(Value::list(map.borrow().iter().map(|(key, _)| Value::String(key.clone())).collect())),
+            _ => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("keys expects a map, got {}.", args[0]))),
+        }
+    });
+    interpreter.define_native("values", Arity::Fixed(1), |_, args| {
+        match &args[0] {
+            Value::Map(map) => Ok(Value::list(map.borrow().iter().map(|(_, value)| value.clone()).collect())),
+            _ => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("values expects a map, got {}.", args[0]))),
+        }
+    });
+    interpreter.define_native("to_json", Arity::Fixed(1), |_, args| {
+        Ok(Value::String(json::to_json(&args[0])?))
+    });
+    interpreter.define_native("from_json", Arity::Fixed(1), |_, args| {
+        match &args[0] {
+            Value::String(text) => json::from_json(text),
+            _ => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("from_json expects a string, got {}.", args[0]))),
+        }
+    });
+    // Describes a callable, natives don't carry a parameter list
+    interpreter.define_native("inspect", Arity::Fixed(1), |_, args| {
+        match &args[0] {
+            Value::NativeFunction(_) => Ok(Value::String(String::from("<native>"))),
+            Value::Function(function) => Ok(Value::String(function.signature())),
+            _ => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("Can only inspect functions, got {}.", args[0]))),
+        }
+    });
+}
+
+// Built-in methods on strings, numbers and collections, e.g. "abc".length(), (2.5).floor()
+// or list.push(1).
+// The method comes back bound to its receiver, ready to be called.
+pub fn primitive_method(receiver: &Value, name: &str) -> Option<Value> {
+    let function: Box<NativeFn> = match (receiver, name) {
+        (Value::String(string), "length") => {
+            let length = string.chars().count() as f64;
+            Box::new(move |_, _| Ok(Value::Number(length)))
+        }
+        (Value::String(string), "upper") => {
+            let string = string.clone();
+            Box::new(move |_, _| Ok(Value::String(string.to_uppercase())))
+        }
+        (Value::String(string), "lower") => {
+            let string = string.clone();
+            Box::new(move |_, _| Ok(Value::String(string.to_lowercase())))
+        }
+        (Value::String(string), "trim") => {
+            let string = string.clone();
+            Box::new(move |_, _| Ok(Value::String(string.trim().to_string())))
+        }
+        // Collections can change between looking the method up and calling it
+        (Value::List(list), "length") => {
+            let list = list.clone();
+            Box::new(move |_, _| Ok(Value::Number(list.borrow().len() as f64)))
+        }
+        (Value::Map(map), "length") => {
+            let map = map.clone();
+            Box::new(move |_, _| Ok(Value::Number(map.borrow().len() as f64)))
+        }
+        // Adds to the end of the list itself, every reference to it sees the new element
+        (Value::List(list), "push") => {
+            let list = list.clone();
+            Box::new(move |_, mut args| {
+                list.borrow_mut().push(args.remove(0));
+                Ok(Value::Nil)
+            })
+        }
+        (Value::Number(number), "floor") => {
+            let number = *number;
+            Box::new(move |_, _| Ok(Value::Number(number.floor())))
+        }
+        (Value::Number(number), "ceil") => {
+            let number = *number;
+            Box::new(move |_, _| Ok(Value::Number(number.ceil())))
+        }
+        (Value::Number(number), "round") => {
+            let number = *number;
+            Box::new(move |_, _| Ok(Value::Number(number.round())))
+        }
+        (Value::Number(number), "abs") => {
+            let number = *number;
+            Box::new(move |_, _| Ok(Value::Number(number.abs())))
+        }
+        // Already whole, so rounding gives the Int back
+        (Value::Int(int), "floor" | "ceil" | "round") => {
+            let int = *int;
+            Box::new(move |_, _| Ok(Value::Int(int)))
+        }
+        (Value::Int(int), "abs") => {
+            let int = *int;
+            Box::new(move |_, _| Ok(int.checked_abs().map_or(Value::Number((int as f64).abs()), Value::Int)))
+        }
+        _ => return None,
+    };
+    // push is the only method taking an argument
+    let arity = Arity::Fixed(if name == "push" { 1 } else { 0 });
+    Some(Value::NativeFunction(Rc::new(NativeFunction {
+        name: name.to_string(),
+        arity,
+        function,
+    })))
+}
+
+// SplitMix64, small and good enough for games and simulations, not for secrets
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub fn from_entropy() -> Rng {
+        use std::hash::{BuildHasher, Hasher};
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        if let Ok(elapsed) = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+            hasher.write_u128(elapsed.as_nanos());
+        }
+        Rng::new(hasher.finish())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1) using the top 53 bits
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// Where the first element equal to `value` is, for the contains and index_of natives
+fn position(list: &Value, value: &Value, name: &str) -> Result<Option<usize>, RloxError> {
+    match list {
+        Value::List(list) => Ok(list.borrow().iter().position(|element| element == value)),
+        _ => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("{} expects a list, got {}.", name, list))),
+    }
+}
+
+// Picks whichever of a and b is `wanted` relative to the other, NaN if either is NaN
+fn nan_aware(a: f64, b: f64, wanted: Ordering) -> f64 {
+    match a.partial_cmp(&b) {
+        None => f64::NAN,
+        Some(ordering) if ordering == wanted => a,
+        Some(_) => b,
+    }
+}
+
+// 32-bit FNV-1a, small enough that every hash is exact as a Lox number
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c9dc5, |hash, byte| (hash ^ *byte as u32).wrapping_mul(0x01000193))
+}
+
+// Finite whole numbers, shared by every place that needs an integral value
+pub fn is_integer(number: f64) -> bool {
+    number.is_finite() && number.fract() == 0.0
+}
+
+// The largest magnitude below which every whole number is exactly representable as a decimal
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_992.0;
+
+// The integer a bitwise operation works on. Ints are exact and used as they are. A
+// decimal must be whole and within 2^53 of zero, past that it may already have been
+// rounded, so converting it would silently give a different integer.
+pub fn as_i64(value: &Value) -> Result<i64, RloxError> {
+    match value {
+        Value::Int(int) => Ok(*int),
+        Value::Number(number) if is_integer(*number) && number.abs() <= MAX_SAFE_INTEGER => Ok(*number as i64),
+        Value::Number(number) if is_integer(*number) => {
+            Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, "Number too large for bitwise operation."))
+        }
+        _ => Err(RloxError::runtime(RuntimeErrorKind::TypeMismatch, format!("Bitwise operands must be whole numbers, got {}.", value))),
+    }
+}
+
+pub fn io_error(err: std::io::Error) -> RloxError {
+    RloxError::runtime(RuntimeErrorKind::Io, err.to_string())
+}
@@ -3,16 +3,49 @@ use crate::expressions::*;
 use crate::tokentype::*;
 use crate::rlox::report;
 use crate::statements::*;
+use crate::environment::Value;
+
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+// Same limit as jlox, which clox needs to fit the count in a byte
+pub const MAX_ARGUMENTS: usize = 255;
+
+// What a recursive rule nests, each kind has its own depth count
+#[derive(Debug, Clone, Copy)]
+enum Nesting {
+    Statement,
+    Expression,
+}
 
 #[derive(Debug, Clone)]
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    statement_depth: usize,
+    expression_depth: usize,
+    // Deepest statement nesting, and deepest expression nesting within it, accepted before
+    // giving up instead of overflowing the stack
+    pub max_depth: usize,
+    // Every error found so far, parsing resumes at the next statement after each one
+    errors: Vec<String>,
+    // Only the book's grammar: the comma, ternary, power and bitwise operators, while-else,
+    // break, continue, try, throw, assert, bare print, var with several names, print(...)
+    // in an expression, and lists and maps are rejected. Natives and methods on primitives are runtime
+    // features and stay available.
+    pub strict: bool,
+    // A line break, '}' or the end of input ends a statement when the ';' is missing
+    pub auto_semicolons: bool,
+    // Errors are only collected, not reported, for a speculative parse like the REPL's
+    // retry of a failed line as an expression
+    pub quiet: bool,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Parser {
-        Parser { tokens, current: 0 }
+        Parser { tokens, current: 0, statement_depth: 0, expression_depth: 0, max_depth: DEFAULT_MAX_DEPTH, errors: Vec::new(), strict: false, auto_semicolons: false, quiet: false }
+    }
+
+    pub fn errors(&self) -> &[String] {
+        &self.errors
     }
 
     // Grammar for Lox
@@ -23,49 +56,153 @@ impl Parser {
             match self.declaration() {
                 Ok(stmt) => statements.push(stmt),
                 Err(message) => {
+                    // Resume at the next statement so later errors are reported too
+                    self.errors.push(message);
                     self.synchronize();
-                    return Err(message);
                 }
             }
         }
 
-        Ok(statements)
+        match self.errors.first() {
+            Some(message) => Err(message.clone()),
+            None => Ok(statements),
+        }
     }
 
-    // declaration -> varDecl | statement ;
+    // declaration -> funDecl | varDecl | statement ;
     fn declaration(&mut self) -> Result<Stmt, String> {
-        if self.match_token(vec![TokenType::Var]) {
+        if self.match_token(vec![TokenType::Class]) {
+            self.class_declaration()
+        } else if self.match_token(vec![TokenType::Fun]) {
+            self.function()
+        } else if self.match_token(vec![TokenType::Var]) {
             self.var_declaration()
         } else {
             self.statement()
         }
     }
 
-    // varDecl -> "var" IDENTIFIER ( "=" expression )? ";" ;
+    // classDecl -> "class" IDENTIFIER ( "<" IDENTIFIER )? "{" function* "}" ;
+    // Methods are written like functions without the fun keyword
+    fn class_declaration(&mut self) -> Result<Stmt, String> {
+        let name = self.identifier("Expect class name.")?;
+        let superclass = if self.match_token(vec![TokenType::Less]) {
+            Some(Expr::Variable(self.identifier("Expect superclass name.")?))
+        } else {
+            None
+        };
+        self.consume(TokenType::LeftBrace, String::from("Expect '{' before class body."))?;
+        let mut methods = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            methods.push(self.function()?);
+        }
+        self.consume(TokenType::RightBrace, String::from("Expect '}' after class body."))?;
+        Ok(Stmt::Class(name, superclass, methods))
+    }
+
+    // funDecl -> "fun" IDENTIFIER "(" parameters? ")" block ;
+    // parameters -> IDENTIFIER ( "," IDENTIFIER )* ;
+    // Nested like statement(), a function body can declare functions without passing through it
+    fn function(&mut self) -> Result<Stmt, String> {
+        self.nested(Nesting::Statement, Parser::function_inner)
+    }
+
+    fn function_inner(&mut self) -> Result<Stmt, String> {
+        let name = self.identifier("Expect function name.")?;
+        self.consume(TokenType::LeftParen, String::from("Expect '(' after function name."))?;
+        let mut params = Vec::new();
+        if !self.check(TokenType::RightParen) {
+            params.push(self.identifier("Expect parameter name.")?);
+            while self.match_token(vec![TokenType::Comma]) {
+                if params.len() >= MAX_ARGUMENTS {
+                    let message = format!("Can't have more than {} parameters.", MAX_ARGUMENTS);
+                    self.error(self.peek(), &message);
+                    self.errors.push(message);
+                }
+                params.push(self.identifier("Expect parameter name.")?);
+            }
+        }
+        self.consume(TokenType::RightParen, String::from("Expect ')' after parameters."))?;
+        self.consume(TokenType::LeftBrace, String::from("Expect '{' before function body."))?;
+        match self.block()? {
+            Stmt::Block(_, body) => Ok(Stmt::Function(name, params, body)),
+            _ => Err(String::from("Expect function body.")),
+        }
+    }
+
+    fn identifier(&mut self, message: &str) -> Result<Token, String> {
+        match self.peek().token_type {
+            TokenType::Identifier(_) => Ok(self.advance()),
+            _ => Err(self.peek_error(message)),
+        }
+    }
+
+    // varDecl -> "var" varBinding ( "," varBinding )* ";" ;
     fn var_declaration(&mut self) -> Result<Stmt, String> {
+        let mut declarations = vec![self.var_binding()?];
+        while self.match_token(vec![TokenType::Comma]) {
+            if self.strict {
+                return Err(self.extension_error("Declaring several variables in one var"));
+            }
+            declarations.push(self.var_binding()?);
+        }
+
+        self.end_statement("Expect ';' after variable declaration.")?;
+        if declarations.len() == 1 {
+            let (name, initializer) = declarations.remove(0);
+            Ok(Stmt::Var(name, initializer))
+        } else {
+            Ok(Stmt::VarGroup(declarations))
+        }
+    }
+
+    // varBinding -> IDENTIFIER ( "=" expression )? ;
+    fn var_binding(&mut self) -> Result<(Token, Expr), String> {
         let name = match self.peek().token_type {
             TokenType::Identifier(_) => {
                 self.advance();
                 self.previous()
             }
             _ => {
-                return Err(String::from("Expect variable name."));
+                return Err(self.peek_error("Expect variable name."));
             }
         };
         let initializer = if self.match_token(vec![TokenType::Equal]) {
             self.expression()?
         } else {
-            Expr::Literal(Token::new(TokenType::Nil, String::from("nil"), 0))
+            Expr::Literal(Token::new(TokenType::Nil, String::from("nil"), 0), Value::Nil)
         };
 
-        self.consume(TokenType::Semicolon, String::from("Expect ';' after variable declaration."))?;
-        Ok(Stmt::Var(name, initializer))
+        Ok((name, initializer))
     }
 
-    // statement -> exprStmt | printStmt | block ;
+    // statement -> exprStmt | forStmt | ifStmt | whileStmt | breakStmt | continueStmt | returnStmt | printStmt | assertStmt | tryStmt | throwStmt | block ;
+    // Blocks, if and loop bodies all recurse through here
     fn statement(&mut self) -> Result<Stmt, String> {
-        if self.match_token(vec![TokenType::Print]) {
+        self.nested(Nesting::Statement, Parser::statement_inner)
+    }
+
+    fn statement_inner(&mut self) -> Result<Stmt, String> {
+        if self.match_token(vec![TokenType::If]) {
+            self.if_statement()
+        } else if self.match_token(vec![TokenType::For]) {
+            self.for_statement()
+        } else if self.match_token(vec![TokenType::While]) {
+            self.while_statement()
+        } else if self.match_token(vec![TokenType::Break]) {
+            self.break_statement()
+        } else if self.match_token(vec![TokenType::Continue]) {
+            self.continue_statement()
+        } else if self.match_token(vec![TokenType::Return]) {
+            self.return_statement()
+        } else if self.match_token(vec![TokenType::Print]) {
             self.print_statement()
+        } else if self.match_token(vec![TokenType::Assert]) {
+            self.assert_statement()
+        } else if self.match_token(vec![TokenType::Try]) {
+            self.try_statement()
+        } else if self.match_token(vec![TokenType::Throw]) {
+            self.throw_statement()
         } else if self.match_token(vec![TokenType::LeftBrace]) {
             self.block()
         } else {
@@ -73,49 +210,224 @@ impl Parser {
         }
     }
 
+    // ifStmt -> "if" "(" expression ")" statement ( "else" statement )? ;
+    // An else binds to the nearest if, the inner statement() call takes it first
+    fn if_statement(&mut self) -> Result<Stmt, String> {
+        let keyword = self.previous();
+        self.consume(TokenType::LeftParen, String::from("Expect '(' after 'if'."))?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, String::from("Expect ')' after if condition."))?;
+
+        let then_branch = self.statement()?;
+        let else_branch = if self.match_token(vec![TokenType::Else]) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+        Ok(Stmt::If(keyword, condition, Box::new(then_branch), else_branch))
+    }
+
+    // forStmt -> "for" "(" ( varDecl | exprStmt | ";" ) expression? ";" expression? ")" statement ;
+    // Desugared into a while loop, so the interpreter never sees a for. The increment
+    // stays apart from the body so a continue doesn't skip it.
+    fn for_statement(&mut self) -> Result<Stmt, String> {
+        let keyword = self.previous();
+        self.consume(TokenType::LeftParen, String::from("Expect '(' after 'for'."))?;
+        let initializer = if self.match_token(vec![TokenType::Semicolon]) {
+            None
+        } else if self.match_token(vec![TokenType::Var]) {
+            Some(self.var_declaration()?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+
+        let condition = if self.check(TokenType::Semicolon) {
+            // No condition loops forever
+            Expr::Literal(Token::new(TokenType::True, String::from("true"), keyword.line), Value::Boolean(true))
+        } else {
+            self.expression()?
+        };
+        self.consume(TokenType::Semicolon, String::from("Expect ';' after loop condition."))?;
+
+        let increment = if self.check(TokenType::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::RightParen, String::from("Expect ')' after for clauses."))?;
+
+        let body = self.statement()?;
+        // The statements a for adds report the for's line. A loop declaring its variables
+        // keeps the for keyword, telling the interpreter to give each iteration its own copy.
+        let loop_keyword = match initializer {
+            Some(Stmt::Var(..) | Stmt::VarGroup(..)) => keyword.clone(),
+            _ => Token::new(TokenType::While, String::from("while"), keyword.line),
+        };
+        let mut lowered = Stmt::While(loop_keyword, condition, Box::new(body), increment, None);
+        if let Some(initializer) = initializer {
+            let brace = Token::new(TokenType::LeftBrace, String::from("{"), keyword.line);
+            lowered = Stmt::Block(brace, vec![initializer, lowered]);
+        }
+        Ok(lowered)
+    }
+
+    // whileStmt -> "while" "(" expression ")" statement ( "else" statement )? ;
+    fn while_statement(&mut self) -> Result<Stmt, String> {
+        let keyword = self.previous();
+        self.consume(TokenType::LeftParen, String::from("Expect '(' after 'while'."))?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, String::from("Expect ')' after condition."))?;
+        let body = self.statement()?;
+        let else_branch = if self.match_token(vec![TokenType::Else]) {
+            if self.strict {
+                return Err(self.extension_error("The while-else statement"));
+            }
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+        Ok(Stmt::While(keyword, condition, Box::new(body), None, else_branch))
+    }
+
+    // returnStmt -> "return" expression? ";" ;
+    // Only valid inside a function, the resolver checks that
+    fn return_statement(&mut self) -> Result<Stmt, String> {
+        let keyword = self.previous();
+        if self.match_token(vec![TokenType::Semicolon]) || self.inserts_semicolon() {
+            return Ok(Stmt::Return(keyword, None));
+        }
+
+        let value = self.expression()?;
+        self.end_statement("Expect ';' after return value.")?;
+        Ok(Stmt::Return(keyword, Some(value)))
+    }
+
+    // breakStmt -> "break" ";" ;
+    // Only valid inside a loop, the resolver checks that
+    fn break_statement(&mut self) -> Result<Stmt, String> {
+        if self.strict {
+            return Err(self.extension_error("The break statement"));
+        }
+        let keyword = self.previous();
+        self.end_statement("Expect ';' after 'break'.")?;
+        Ok(Stmt::Break(keyword))
+    }
+
+    // continueStmt -> "continue" ";" ;
+    // Only valid inside a loop, the resolver checks that
+    fn continue_statement(&mut self) -> Result<Stmt, String> {
+        if self.strict {
+            return Err(self.extension_error("The continue statement"));
+        }
+        let keyword = self.previous();
+        self.end_statement("Expect ';' after 'continue'.")?;
+        Ok(Stmt::Continue(keyword))
+    }
+
     // block -> "{" declaration* "}" ;
     fn block(&mut self) -> Result<Stmt, String> {
+        let opening = self.previous();
         let mut statements = Vec::new();
 
         while !self.check(TokenType::RightBrace) && !self.is_at_end() {
             match self.declaration() {
                 Ok(stmt) => statements.push(stmt),
                 Err(message) => {
+                    self.errors.push(message);
                     self.synchronize();
-                    return Err(message);
                 }
             }
         }
 
-        self.consume(TokenType::RightBrace, String::from("Expect '}' after block."))?;
-        Ok(Stmt::Block(statements))
+        // An unterminated block usually fails at EOF, so point back at the brace too
+        if !self.check(TokenType::RightBrace) {
+            let message = format!("Expect '}}' to close block opened at line {}.", opening.line);
+            return Err(self.peek_error(&message));
+        }
+        self.advance();
+        Ok(Stmt::Block(opening, statements))
     }
 
-    // printStmt -> "print" expression ";" ;
+    // printStmt -> "print" expression? ";" ;
     fn print_statement(&mut self) -> Result<Stmt, String> {
+        let keyword = self.previous();
+        // A bare "print;" just prints an empty line
+        if self.match_token(vec![TokenType::Semicolon]) || self.inserts_semicolon() {
+            if self.strict {
+                return Err(self.extension_error("A print statement without a value"));
+            }
+            return Ok(Stmt::Print(keyword, None));
+        }
+
         let value = self.expression()?;
-        self.consume(TokenType::Semicolon, String::from("Expect ';' after expression."))?;
-        Ok(Stmt::Print(value))
+        self.end_statement("Expect ';' after expression.")?;
+        Ok(Stmt::Print(keyword, Some(value)))
     }
 
-    // exprStmt -> expression ";" ;
+    // assertStmt -> "assert" expression ";" ;
+    // A statement rather than a native so a failure can show the unevaluated expression
+    fn assert_statement(&mut self) -> Result<Stmt, String> {
+        if self.strict {
+            return Err(self.extension_error("The assert statement"));
+        }
+        let condition = self.expression()?;
+        self.end_statement("Expect ';' after assertion.")?;
+        Ok(Stmt::Assert(condition))
+    }
+
+    // tryStmt -> "try" block "catch" "(" IDENTIFIER ")" block ;
+    fn try_statement(&mut self) -> Result<Stmt, String> {
+        if self.strict {
+            return Err(self.extension_error("The try statement"));
+        }
+        let keyword = self.previous();
+        self.consume(TokenType::LeftBrace, String::from("Expect '{' after 'try'."))?;
+        let body = self.block()?;
+        self.consume(TokenType::Catch, String::from("Expect 'catch' after try block."))?;
+        self.consume(TokenType::LeftParen, String::from("Expect '(' after 'catch'."))?;
+        let name = match self.peek().token_type {
+            TokenType::Identifier(_) => self.advance(),
+            _ => return Err(self.peek_error("Expect error variable name.")),
+        };
+        self.consume(TokenType::RightParen, String::from("Expect ')' after error variable name."))?;
+        self.consume(TokenType::LeftBrace, String::from("Expect '{' before catch block."))?;
+        let handler = self.block()?;
+        Ok(Stmt::Try(keyword, Box::new(body), name, Box::new(handler)))
+    }
+
+    // throwStmt -> "throw" expression ";" ;
+    fn throw_statement(&mut self) -> Result<Stmt, String> {
+        if self.strict {
+            return Err(self.extension_error("The throw statement"));
+        }
+        let value = self.expression()?;
+        self.end_statement("Expect ';' after thrown value.")?;
+        Ok(Stmt::Throw(value))
+    }
+
+    // exprStmt -> comma ";" ;
     fn expression_statement(&mut self) -> Result<Stmt, String> {
-        let expr = self.expression()?;
-        self.consume(TokenType::Semicolon, String::from("Expect ';' after expression."))?;
+        let expr = self.comma()?;
+        self.end_statement("Expect ';' after expression.")?;
         Ok(Stmt::Expression(expr))
     }
 
     // Expressions grammar
-    // expresion -> comma ;
+    // expresion -> assignment ;
     pub fn expression(&mut self) -> Result<Expr, String> {
-        self.comma()
+        self.assignment()
     }
 
+    // The comma operator is only allowed at statement level, so commas
+    // inside parentheses and argument lists stay structural
     // comma -> assignment ( "," assignment )* ;
-    fn comma(&mut self) -> Result<Expr, String> {
+    pub fn comma(&mut self) -> Result<Expr, String> {
         let mut expr = self.assignment()?;
 
         while self.match_token(vec![TokenType::Comma]) {
+            if self.strict {
+                return Err(self.extension_error("The comma operator"));
+            }
             let operator = self.previous();
             let right = self.assignment()?;
             expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
@@ -124,41 +436,86 @@ impl Parser {
         Ok(expr)
     }
 
-    // assignment -> IDENTIFIER "=" assignment | ternary ;
+    // assignment -> ( IDENTIFIER | call "[" expression "]" ) "=" assignment | ternary ;
     fn assignment(&mut self) -> Result<Expr, String> {
+        self.nested(Nesting::Expression, Parser::assignment_inner)
+    }
+
+    fn assignment_inner(&mut self) -> Result<Expr, String> {
         let expr = self.ternary()?;
 
         if self.match_token(vec![TokenType::Equal]) {
+            let equals = self.previous();
             let value = self.assignment()?;
-
-            match expr {
-                Expr::Variable(name) => Ok(Expr::Assign(name, Box::new(value))),
-                _ => Err(String::from("Invalid assignment target.")),
-            }
+            self.assignment_target(expr, equals, value)
         } else {
             Ok(expr)
         }
     }
 
-    // ternary -> equality ( "?" equality ":" equality )? ;
+    // The target is parsed as an ordinary expression first and rewritten into
+    // the matching assign node here, new lvalue forms only need a new arm
+    fn assignment_target(&mut self, target: Expr, equals: Token, value: Expr) -> Result<Expr, String> {
+        match target {
+            Expr::Variable(name) => Ok(Expr::Assign(name, Box::new(value))),
+            Expr::Index(collection, bracket, index) => Ok(Expr::SetIndex(collection, bracket, index, Box::new(value))),
+            Expr::Get(object, name) => Ok(Expr::Set(object, name, Box::new(value))),
+            _ => {
+                let message = String::from("Invalid assignment target.");
+                self.error(equals, &message);
+                Err(message)
+            }
+        }
+    }
+
+    // ternary -> logicOr ( "?" logicOr ":" logicOr )? ;
     fn ternary(&mut self) -> Result<Expr, String> {
-        let mut expr = self.equality()?;
+        let mut expr = self.logic_or()?;
 
         if self.match_token(vec![TokenType::QuestionMark]) {
+            if self.strict {
+                return Err(self.extension_error("The ternary operator"));
+            }
             let operator1 = self.previous();
-            let middle = self.equality()?;
+            let middle = self.logic_or()?;
             let operator2 = self.consume(TokenType::Colon, String::from("Expect ':' after expression."));
             match operator2 {
                 Ok(_) => (),
                 Err(message) => return Err(message),
             }
-            let right = self.equality()?;
+            let right = self.logic_or()?;
             expr = Expr::Ternary(Box::new(expr), operator1, Box::new(middle), operator2?, Box::new(right));
         }
 
         Ok(expr)
     }
 
+    // logicOr -> logicAnd ( "or" logicAnd )* ;
+    fn logic_or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.logic_and()?;
+
+        while self.match_token(vec![TokenType::Or]) {
+            let operator = self.previous();
+            let right = self.logic_and()?;
+            expr = Expr::Logical(Box::new(expr), operator, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    // logicAnd -> equality ( "and" equality )* ;
+    fn logic_and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.equality()?;
+
+        while self.match_token(vec![TokenType::And]) {
+            let operator = self.previous();
+            let right = self.equality()?;
+            expr = Expr::Logical(Box::new(expr), operator, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
     // equality -> comparison ( ( "!=" | "==" ) comparison )* ;
     fn equality(&mut self) -> Result<Expr, String> {
         let mut expr = self.comparison()?;
@@ -172,9 +529,9 @@ impl Parser {
         Ok(expr)
     }
 
-    // comparison -> addition ( ( ">" | ">=" | "<" | "<=" ) addition )* ;
+    // comparison -> bitwise_or ( ( ">" | ">=" | "<" | "<=" ) bitwise_or )* ;
     fn comparison(&mut self) -> Result<Expr, String> {
-        let mut expr = self.addition()?;
+        let mut expr = self.bitwise_or()?;
 
         while self.match_token(vec![
             TokenType::Greater,
@@ -183,7 +540,45 @@ impl Parser {
             TokenType::LessEqual,
         ]) {
             let operator = self.previous();
-            let right = self.addition()?;
+            let right = self.bitwise_or()?;
+            expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    // Bitwise operators bind tighter than comparisons, as in Python, so x & 1 == 0
+    // means (x & 1) == 0
+    // bitwise_or -> bitwise_xor ( "|" bitwise_xor )* ;
+    fn bitwise_or(&mut self) -> Result<Expr, String> {
+        self.bitwise(vec![TokenType::Pipe], Parser::bitwise_xor)
+    }
+
+    // bitwise_xor -> bitwise_and ( "^" bitwise_and )* ;
+    fn bitwise_xor(&mut self) -> Result<Expr, String> {
+        self.bitwise(vec![TokenType::Caret], Parser::bitwise_and)
+    }
+
+    // bitwise_and -> shift ( "&" shift )* ;
+    fn bitwise_and(&mut self) -> Result<Expr, String> {
+        self.bitwise(vec![TokenType::Ampersand], Parser::shift)
+    }
+
+    // shift -> addition ( ( "<<" | ">>" ) addition )* ;
+    fn shift(&mut self) -> Result<Expr, String> {
+        self.bitwise(vec![TokenType::LessLess, TokenType::GreaterGreater], Parser::addition)
+    }
+
+    // A left-associative level of bitwise operators over `operand`
+    fn bitwise(&mut self, operators: Vec<TokenType>, operand: fn(&mut Parser) -> Result<Expr, String>) -> Result<Expr, String> {
+        let mut expr = operand(self)?;
+
+        while self.match_token(operators.clone()) {
+            if self.strict {
+                return Err(self.extension_error("A bitwise operator"));
+            }
+            let operator = self.previous();
+            let right = operand(self)?;
             expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
         }
 
@@ -216,30 +611,139 @@ impl Parser {
         Ok(expr)
     }
 
-    // unary -> ( "!" | "-" ) unary | primary ;
+    // unary -> ( "!" | "-" ) unary | power ;
     fn unary(&mut self) -> Result<Expr, String> {
         if self.match_token(vec![TokenType::Bang, TokenType::Minus]) {
             let operator = self.previous();
-            let right = self.unary()?;
+            let right = self.nested(Nesting::Expression, Parser::unary)?;
             return Ok(Expr::Unary(operator, Box::new(right)));
         }
 
-        self.primary()
+        self.power()
+    }
+
+    // power -> call ( "**" unary )? ;
+    // Binds tighter than a unary minus on its left, so -2 ** 2 is -(2 ** 2) as in
+    // Python, and is right associative through the unary on its right
+    fn power(&mut self) -> Result<Expr, String> {
+        let expr = self.call()?;
+
+        if self.match_token(vec![TokenType::StarStar]) {
+            if self.strict {
+                return Err(self.extension_error("The power operator"));
+            }
+            let operator = self.previous();
+            let right = self.nested(Nesting::Expression, Parser::unary)?;
+            return Ok(Expr::Binary(Box::new(expr), operator, Box::new(right)));
+        }
+
+        Ok(expr)
+    }
+
+    // call -> primary ( "(" arguments? ")" | "." IDENTIFIER | "[" expression "]"
+    //         | "[" expression? ":" expression? "]" )* ;
+    fn call(&mut self) -> Result<Expr, String> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.match_token(vec![TokenType::LeftParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.match_token(vec![TokenType::Dot]) {
+                let name = match self.peek().token_type {
+                    TokenType::Identifier(_) => self.advance(),
+                    _ => return Err(self.peek_error("Expect property name after '.'.")),
+                };
+                expr = Expr::Get(Box::new(expr), name);
+            } else if self.match_token(vec![TokenType::LeftBracket]) {
+                if self.strict {
+                    return Err(self.extension_error("Indexing"));
+                }
+                let bracket = self.previous();
+                // Only None when a ':' follows, so it's always a slice
+                let start = if self.check(TokenType::Colon) { None } else { Some(self.expression()?) };
+                if self.match_token(vec![TokenType::Colon]) {
+                    expr = self.finish_slice(expr, start)?;
+                } else if let Some(index) = start {
+                    self.consume(TokenType::RightBracket, String::from("Expect ']' after index."))?;
+                    expr = Expr::Index(Box::new(expr), bracket, Box::new(index));
+                }
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
     }
 
-    // primary -> NUMBER | STRING | "false" | "true" | "nil" | "(" expression ")" | IDENTIFIER;
+    // The ':' is consumed, what's left is the optional end bound and the ']'
+    fn finish_slice(&mut self, collection: Expr, start: Option<Expr>) -> Result<Expr, String> {
+        let end = if self.check(TokenType::RightBracket) { None } else { Some(Box::new(self.expression()?)) };
+        self.consume(TokenType::RightBracket, String::from("Expect ']' after slice."))?;
+        Ok(Expr::Slice(Box::new(collection), start.map(Box::new), end))
+    }
+
+    // arguments -> assignment ( "," assignment )* ;
+    fn finish_call(&mut self, callee: Expr) -> Result<Expr, String> {
+        let mut arguments = Vec::new();
+
+        if !self.check(TokenType::RightParen) {
+            arguments.push(self.assignment()?);
+            while self.match_token(vec![TokenType::Comma]) {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    // Reported but not thrown, the parser isn't confused
+                    let message = format!("Can't have more than {} arguments.", MAX_ARGUMENTS);
+                    self.error(self.peek(), &message);
+                    self.errors.push(message);
+                }
+                arguments.push(self.assignment()?);
+            }
+        }
+
+        let paren = self.consume(TokenType::RightParen, String::from("Expect ')' after arguments."))?;
+        Ok(Expr::Call(Box::new(callee), paren, arguments))
+    }
+
+    // primary -> NUMBER | STRING | "false" | "true" | "nil" | "(" expression ")" | IDENTIFIER | "print" | list | map ;
     fn primary(&mut self) -> Result<Expr, String> {
         match self.peek().token_type {
-            TokenType::False | TokenType::True | TokenType::Nil | TokenType::Number(_) | TokenType::String(_) => {
-                self.advance();
-                Ok(Expr::Literal(self.previous()))
+            TokenType::False | TokenType::True | TokenType::Nil | TokenType::Number(_) | TokenType::Int(_) | TokenType::String(_) => {
+                let token = self.advance();
+                let value = match &token.token_type {
+                    TokenType::Number(number) => Value::Number(*number),
+                    TokenType::Int(int) => Value::Int(*int),
+                    TokenType::String(string) => Value::String(string.clone()),
+                    TokenType::True => Value::Boolean(true),
+                    TokenType::False => Value::Boolean(false),
+                    _ => Value::Nil,
+                };
+                Ok(Expr::Literal(token, value))
             }
             TokenType::Identifier(_) => {
                 self.advance();
                 Ok(Expr::Variable(self.previous()))
             }
+            TokenType::This => Ok(Expr::This(self.advance())),
+            TokenType::Super => {
+                let keyword = self.advance();
+                self.consume(TokenType::Dot, String::from("Expect '.' after 'super'."))?;
+                let method = self.identifier("Expect superclass method name.")?;
+                Ok(Expr::Super(keyword, method))
+            }
+            // print(...) inside an expression calls the print native, statements still take the keyword
+            TokenType::Print if self.tokens.get(self.current + 1).is_some_and(|next| next.token_type == TokenType::LeftParen) => {
+                let keyword = self.advance();
+                if self.strict {
+                    return Err(self.extension_error("Calling print as a function"));
+                }
+                Ok(Expr::Variable(Token::new(TokenType::Identifier(keyword.lexeme.clone()), keyword.lexeme, keyword.line)))
+            }
             TokenType::LeftParen => {
                 self.advance();
+                if self.check(TokenType::RightParen) {
+                    let message = String::from("Expected expression inside parentheses.");
+                    self.error(self.peek(), &message);
+                    return Err(message);
+                }
                 let expr = self.expression()?;
                 match self.consume(TokenType::RightParen, String::from("Expect ')' after expression.")) {
                     Ok(_) => Ok(Expr::Grouping(Box::new(expr))),
@@ -247,12 +751,63 @@ impl Parser {
                 }
 
             }
-            _ => Err(String::from("Expect expression.")),
+            TokenType::LeftBracket => {
+                self.advance();
+                self.list()
+            }
+            // Only reached inside an expression, a '{' starting a statement is a block
+            TokenType::LeftBrace => {
+                self.advance();
+                self.map()
+            }
+            _ => Err(self.peek_error("Expect expression.")),
+        }
+    }
+
+    // list -> "[" ( assignment ( "," assignment )* )? "]" ;
+    fn list(&mut self) -> Result<Expr, String> {
+        if self.strict {
+            return Err(self.extension_error("A list"));
+        }
+        let bracket = self.previous();
+        let mut elements = Vec::new();
+        if !self.check(TokenType::RightBracket) {
+            elements.push(self.assignment()?);
+            while self.match_token(vec![TokenType::Comma]) {
+                elements.push(self.assignment()?);
+            }
         }
+        self.consume(TokenType::RightBracket, String::from("Expect ']' after list elements."))?;
+        Ok(Expr::List(bracket, elements))
+    }
+
+    // map -> "{" ( entry ( "," entry )* )? "}" ;
+    // entry -> assignment ":" assignment ;
+    fn map(&mut self) -> Result<Expr, String> {
+        if self.strict {
+            return Err(self.extension_error("A map"));
+        }
+        let brace = self.previous();
+        let mut entries = Vec::new();
+        if !self.check(TokenType::RightBrace) {
+            loop {
+                let key = self.assignment()?;
+                self.consume(TokenType::Colon, String::from("Expect ':' after map key."))?;
+                entries.push((key, self.assignment()?));
+                if !self.match_token(vec![TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightBrace, String::from("Expect '}' after map entries."))?;
+        Ok(Expr::Map(brace, entries))
     }
 
     // Error handling
     pub fn error(&mut self, token: Token, message: &str) {
+        if self.quiet {
+            return;
+        }
         if token.token_type == crate::tokentype::TokenType::Eof {
             report(token.line, " at end", message);
         } else {
@@ -260,6 +815,18 @@ impl Parser {
         }
     }
 
+    // For errors found before consuming the offending token, reports it and hands back the message
+    fn peek_error(&mut self, message: &str) -> String {
+        self.error(self.peek(), message);
+        message.to_string()
+    }
+
+    fn extension_error(&mut self, extension: &str) -> String {
+        let message = format!("{} is not part of standard Lox.", extension);
+        self.error(self.previous(), &message);
+        message
+    }
+
     pub fn synchronize(&mut self) {
         self.advance();
 
@@ -285,6 +852,30 @@ impl Parser {
     }
 
     // Auxiliary functions for the parser
+    // Every recursive rule goes through here so deep nesting errors out gracefully.
+    // Statements and expressions are counted apart, so a long else-if chain doesn't
+    // use up the depth left for the expressions in it.
+    fn nested<T>(&mut self, what: Nesting, rule: fn(&mut Parser) -> Result<T, String>) -> Result<T, String> {
+        let depth = match what {
+            Nesting::Statement => self.statement_depth,
+            Nesting::Expression => self.expression_depth,
+        };
+        if depth >= self.max_depth {
+            return Err(self.peek_error(&format!("{:?} nesting too deep.", what)));
+        }
+        self.set_depth(what, depth + 1);
+        let result = rule(self);
+        self.set_depth(what, depth);
+        result
+    }
+
+    fn set_depth(&mut self, what: Nesting, depth: usize) {
+        match what {
+            Nesting::Statement => self.statement_depth = depth,
+            Nesting::Expression => self.expression_depth = depth,
+        }
+    }
+
     fn match_token(&mut self, token_types: Vec<TokenType>) -> bool {
         for token_type in token_types {
             if self.check(token_type) {
@@ -324,6 +915,18 @@ impl Parser {
         self.tokens[self.current - 1].clone()
     }
 
+    fn end_statement(&mut self, message: &str) -> Result<(), String> {
+        if self.match_token(vec![TokenType::Semicolon]) || self.inserts_semicolon() {
+            return Ok(());
+        }
+        self.consume(TokenType::Semicolon, String::from(message)).map(|_| ())
+    }
+
+    fn inserts_semicolon(&self) -> bool {
+        self.auto_semicolons
+            && (self.is_at_end() || self.check(TokenType::RightBrace) || self.peek().line > self.previous().line)
+    }
+
     fn consume(&mut self, token_type: TokenType, message: String) -> Result<Token, String> {
         if self.check(token_type) {
             Ok(self.advance())
@@ -339,15 +942,20 @@ mod tests {
     use crate::scanner::*;
     use super::*;
 
+    // Tokens compare without their line, so expected trees don't need one
+    fn token(token_type: TokenType, lexeme: &str) -> Token {
+        Token::new(token_type, String::from(lexeme), 0)
+    }
+
     #[test]
     fn test_parse() {
         let tokens = vec![
-            Token::new(TokenType::Number(1.0), String::from("1"), 1),
-            Token::new(TokenType::Plus, String::from("+"), 1),
-            Token::new(TokenType::Number(2.0), String::from("2"), 1),
-            Token::new(TokenType::Star, String::from("*"), 1),
-            Token::new(TokenType::Number(3.0), String::from("3"), 1),
-            Token::new(TokenType::Eof, String::from(""), 1),
+            token(TokenType::Int(1), "1"),
+            token(TokenType::Plus, "+"),
+            token(TokenType::Int(2), "2"),
+            token(TokenType::Star, "*"),
+            token(TokenType::Int(3), "3"),
+            token(TokenType::Eof, ""),
         ];
 
         let mut parser = Parser::new(tokens);
@@ -359,12 +967,12 @@ mod tests {
         }
 
         assert_eq!(expr, Ok(Expr::Binary(
-            Box::new(Expr::Literal(Token::new(TokenType::Number(1.0), String::from("1"), 1))),
-            Token::new(TokenType::Plus, String::from("+"), 1),
+            Box::new(Expr::Literal(token(TokenType::Int(1), "1"), Value::Int(1))),
+            token(TokenType::Plus, "+"),
             Box::new(Expr::Binary(
-                Box::new(Expr::Literal(Token::new(TokenType::Number(2.0), String::from("2"), 1))),
-                Token::new(TokenType::Star, String::from("*"), 1),
-                Box::new(Expr::Literal(Token::new(TokenType::Number(3.0), String::from("3"), 1))
+                Box::new(Expr::Literal(token(TokenType::Int(2), "2"), Value::Int(2))),
+                token(TokenType::Star, "*"),
+                Box::new(Expr::Literal(token(TokenType::Int(3), "3"), Value::Int(3))
             )),
             )))
         );
@@ -373,11 +981,11 @@ mod tests {
     #[test]
     fn test_parse_error() {
         let tokens = vec![
-            Token::new(TokenType::Number(1.0), String::from("1"), 1),
-            Token::new(TokenType::Plus, String::from("+"), 1),
-            Token::new(TokenType::Number(2.0), String::from("2"), 1),
-            Token::new(TokenType::Star, String::from("*"), 1),
-            Token::new(TokenType::Eof, String::from(""), 1),
+            token(TokenType::Int(1), "1"),
+            token(TokenType::Plus, "+"),
+            token(TokenType::Int(2), "2"),
+            token(TokenType::Star, "*"),
+            token(TokenType::Eof, ""),
         ];
 
         let mut parser = Parser::new(tokens);
@@ -398,15 +1006,15 @@ mod tests {
 
         assert_eq!(expr, Ok(Expr::Binary(
             Box::new(Expr::Binary(
-                Box::new(Expr::Literal(Token::new(TokenType::Number(1.0), String::from("1"), 1))),
-                Token::new(TokenType::Plus, String::from("+"), 1),
-                Box::new(Expr::Literal(Token::new(TokenType::Number(2.0), String::from("2"), 1)))
+                Box::new(Expr::Literal(token(TokenType::Int(1), "1"), Value::Int(1))),
+                token(TokenType::Plus, "+"),
+                Box::new(Expr::Literal(token(TokenType::Int(2), "2"), Value::Int(2)))
             )),
-            Token::new(TokenType::EqualEqual, String::from("=="), 1),
+            token(TokenType::EqualEqual, "=="),
             Box::new(Expr::Binary(
-                Box::new(Expr::Literal(Token::new(TokenType::Number(5.0), String::from("5"), 1))),
-                Token::new(TokenType::Slash, String::from("/"), 1),
-                Box::new(Expr::Literal(Token::new(TokenType::Number(2.0), String::from("2"), 1)))
+                Box::new(Expr::Literal(token(TokenType::Int(5), "5"), Value::Int(5))),
+                token(TokenType::Slash, "/"),
+                Box::new(Expr::Literal(token(TokenType::Int(2), "2"), Value::Int(2)))
             ))
         )));
     }
@@ -423,15 +1031,50 @@ mod tests {
 
         assert_eq!(expr, Ok(Expr::Binary(
             Box::new(Expr::Binary(
-                Box::new(Expr::Literal(Token::new(TokenType::Number(1.0), String::from("1"), 1))),
-                Token::new(TokenType::Plus, String::from("+"), 1),
-                Box::new(Expr::Literal(Token::new(TokenType::Number(2.0), String::from("2"), 1)))
+                Box::new(Expr::Literal(token(TokenType::Int(1), "1"), Value::Int(1))),
+                token(TokenType::Plus, "+"),
+                Box::new(Expr::Literal(token(TokenType::Int(2), "2"), Value::Int(2)))
             )),
-            Token::new(TokenType::Plus, String::from("+"), 1),
-            Box::new(Expr::Literal(Token::new(TokenType::Number(3.0), String::from("3"), 1)))
+            token(TokenType::Plus, "+"),
+            Box::new(Expr::Literal(token(TokenType::Int(3), "3"), Value::Int(3)))
         )));
     }
 
+    #[test]
+    fn test_bitwise_precedence() {
+        for (source, expected) in [
+            ("a | b ^ c & d", "(| a (^ b (& c d)))"),
+            ("a & b & c", "(& (& a b) c)"),
+            ("1 << 2 + 3", "(<< 1 (+ 2 3))"),
+            ("x & 1 == 0", "(== (& x 1) 0)"),
+            ("a < b | c", "(< a (| b c))"),
+        ] {
+            let mut parser = Parser::new(Scanner::new(String::from(source)).scan_tokens());
+            assert_eq!(parser.expression().map(|expr| expr.to_string()), Ok(String::from(expected)), "{}", source);
+        }
+
+        let mut parser = Parser::new(Scanner::new(String::from("print 1 << 2;")).scan_tokens());
+        parser.strict = true;
+        assert_eq!(parser.parse(), Err(String::from("A bitwise operator is not part of standard Lox.")));
+    }
+
+    #[test]
+    fn test_power_precedence() {
+        for (source, expected) in [
+            ("-2 ** 2", "(- (** 2 2))"),
+            ("2 ** 3 ** 2", "(** 2 (** 3 2))"),
+            ("2 ** -1 * 3", "(* (** 2 (- 1)) 3)"),
+            ("a.b(1) ** 2", "(** (call (. a b) 1) 2)"),
+        ] {
+            let mut parser = Parser::new(Scanner::new(String::from(source)).scan_tokens());
+            assert_eq!(parser.expression().map(|expr| expr.to_string()), Ok(String::from(expected)));
+        }
+
+        let mut parser = Parser::new(Scanner::new(String::from("print 2 ** 2;")).scan_tokens());
+        parser.strict = true;
+        assert_eq!(parser.parse(), Err(String::from("The power operator is not part of standard Lox.")));
+    }
+
     #[test]
     fn test_parse_and_scanned_error() {
         let source = "1 + 2 == 5 /";
@@ -445,6 +1088,221 @@ mod tests {
         assert_eq!(expr, Err(String::from("Expect expression.")));
     }
 
+    #[test]
+    fn test_strict_lox() {
+        for source in ["a = 1, 2;", "print true ? 1 : 2;"] {
+            assert!(Parser::new(Scanner::new(String::from(source)).scan_tokens()).parse().is_ok());
+        }
+
+        let mut parser = Parser::new(Scanner::new(String::from("a = 1, 2;")).scan_tokens());
+        parser.strict = true;
+        assert_eq!(parser.parse(), Err(String::from("The comma operator is not part of standard Lox.")));
+
+        let mut parser = Parser::new(Scanner::new(String::from("print true ? 1 : 2;")).scan_tokens());
+        parser.strict = true;
+        assert_eq!(parser.parse(), Err(String::from("The ternary operator is not part of standard Lox.")));
+
+        let mut parser = Parser::new(Scanner::new(String::from("print f(1, 2) == 3;")).scan_tokens());
+        parser.strict = true;
+        assert!(parser.parse().is_ok());
+
+        for (source, message) in [
+            ("assert 1 < 2;", "The assert statement is not part of standard Lox."),
+            ("var a = 1, b;", "Declaring several variables in one var is not part of standard Lox."),
+            ("print;", "A print statement without a value is not part of standard Lox."),
+            ("var a = print(1);", "Calling print as a function is not part of standard Lox."),
+            ("while (true) continue;", "The continue statement is not part of standard Lox."),
+            ("var a = [1];", "A list is not part of standard Lox."),
+            ("var m = {};", "A map is not part of standard Lox."),
+            ("print a[0];", "Indexing is not part of standard Lox."),
+        ] {
+            assert!(Parser::new(Scanner::new(String::from(source)).scan_tokens()).parse().is_ok(), "{}", source);
+            let mut parser = Parser::new(Scanner::new(String::from(source)).scan_tokens());
+            parser.strict = true;
+            assert_eq!(parser.parse(), Err(String::from(message)), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_auto_semicolons() {
+        let source = "var a = 1\nprint a\n{ assert a == 1 }\nprint\na = a +\n  2";
+        assert_eq!(Parser::new(Scanner::new(String::from(source)).scan_tokens()).parse(), Err(String::from("Expect ';' after variable declaration.")));
+
+        let mut parser = Parser::new(Scanner::new(String::from(source)).scan_tokens());
+        parser.auto_semicolons = true;
+        let explicit = Parser::new(Scanner::new(String::from("var a = 1; print a; { assert a == 1; } print; a = a + 2;")).scan_tokens()).parse();
+        assert_eq!(parser.parse(), explicit);
+
+        let mut parser = Parser::new(Scanner::new(String::from("print 1 print 2")).scan_tokens());
+        parser.auto_semicolons = true;
+        assert_eq!(parser.parse(), Err(String::from("Expect ';' after expression.")));
+    }
+
+    #[test]
+    fn test_if_statement() {
+        for (source, expected) in [
+            ("if (x) print 1; else print 2;", "(if x (print 1) (print 2))"),
+            ("if (x) print 1;", "(if x (print 1))"),
+            ("if (a) if (b) print 1; else print 2;", "(if a (if b (print 1) (print 2)))"),
+            ("if (a) { print 1; } else if (b) print 2;", "(if a (block (print 1)) (if b (print 2)))"),
+        ] {
+            let mut parser = Parser::new(Scanner::new(String::from(source)).scan_tokens());
+            assert_eq!(parser.parse().map(|statements| statements[0].to_string()), Ok(String::from(expected)));
+        }
+
+        for (source, message) in [
+            ("if x print 1;", "Expect '(' after 'if'."),
+            ("if (x print 1;", "Expect ')' after if condition."),
+            ("if (x) else print 1;", "Expect expression."),
+        ] {
+            assert_eq!(Parser::new(Scanner::new(String::from(source)).scan_tokens()).parse(), Err(String::from(message)));
+        }
+    }
+
+    #[test]
+    fn test_while_statement() {
+        let mut parser = Parser::new(Scanner::new(String::from("while (i < 3) { print i; i = i + 1; }")).scan_tokens());
+        assert_eq!(parser.parse().map(|statements| statements[0].to_string()), Ok(String::from("(while (< i 3) (block (print i) (; (assign i (+ i 1)))))")));
+
+        let mut parser = Parser::new(Scanner::new(String::from("while (true print 1;")).scan_tokens());
+        assert_eq!(parser.parse(), Err(String::from("Expect ')' after condition.")));
+
+        let mut parser = Parser::new(Scanner::new(String::from("while (a) if (b) break; else print 1; else print 2;")).scan_tokens());
+        assert_eq!(parser.parse().map(|statements| statements[0].to_string()), Ok(String::from("(while a (if b (break) (print 1)) (print 2))")));
+
+        let mut parser = Parser::new(Scanner::new(String::from("while (a) break")).scan_tokens());
+        assert_eq!(parser.parse(), Err(String::from("Expect ';' after 'break'.")));
+    }
+
+    #[test]
+    fn test_function_declaration() {
+        let statements = Parser::new(Scanner::new(String::from("fun add(a, b) { print a + b; } add(1, 2);")).scan_tokens()).parse().unwrap();
+        assert_eq!(statements.iter().map(|statement| statement.to_string()).collect::<Vec<String>>(), vec![
+            String::from("(fun add (a b) (print (+ a b)))"),
+            String::from("(; (call add 1 2))"),
+        ]);
+
+        let statements = Parser::new(Scanner::new(String::from("fun f() { return; return 1 + 2; }")).scan_tokens()).parse().unwrap();
+        assert_eq!(statements[0].to_string(), "(fun f () (return) (return (+ 1 2)))");
+
+        for (source, message) in [
+            ("fun f() { return 1 }", "Expect ';' after return value."),
+            ("fun (a) {}", "Expect function name."),
+            ("fun f(a, 1) {}", "Expect parameter name."),
+            ("fun f(a {}", "Expect ')' after parameters."),
+            ("fun f(a) print a;", "Expect '{' before function body."),
+        ] {
+            assert_eq!(Parser::new(Scanner::new(String::from(source)).scan_tokens()).parse(), Err(String::from(message)));
+        }
+    }
+
+    #[test]
+    fn test_for_desugars_to_while() {
+        for (source, equivalent) in [
+            ("for (i = 0; i < 3;) print i;", "{ i = 0; while (i < 3) print i; }"),
+            ("for (;;) print 1;", "while (true) print 1;"),
+        ] {
+            let lowered = Parser::new(Scanner::new(String::from(source)).scan_tokens()).parse();
+            let expected = Parser::new(Scanner::new(String::from(equivalent)).scan_tokens()).parse();
+            assert_eq!(lowered, expected, "{}", source);
+        }
+
+        // The increment stays apart from the body, so a continue in the body doesn't skip it
+        for (source, expected) in [
+            ("for (var i = 0; i < 3; i = i + 1) print i;", "(block (var i 0) (while (< i 3) (print i) (increment (assign i (+ i 1)))))"),
+            ("for (; i < 3; i = i + 1) { if (i == 1) continue; }", "(while (< i 3) (block (if (== i 1) (continue))) (increment (assign i (+ i 1))))"),
+        ] {
+            let lowered = Parser::new(Scanner::new(String::from(source)).scan_tokens()).parse();
+            assert_eq!(lowered.map(|statements| statements[0].to_string()), Ok(String::from(expected)), "{}", source);
+        }
+
+        // Only a loop declaring its variables asks for a fresh copy per iteration
+        for (source, keyword) in [("for (var i = 0;;) {}", TokenType::For), ("for (i = 0;;) {}", TokenType::While)] {
+            let statements = Parser::new(Scanner::new(String::from(source)).scan_tokens()).parse().unwrap();
+            let Stmt::Block(_, lowered) = &statements[0] else { panic!("{}", source) };
+            assert!(matches!(&lowered[1], Stmt::While(token, ..) if token.token_type == keyword), "{}", source);
+        }
+
+        for (source, message) in [
+            ("for i = 0; i < 3; i = i + 1) print i;", "Expect '(' after 'for'."),
+            ("for (;; print 1;", "Expect expression."),
+            ("for (; i < 3 print i;", "Expect ';' after loop condition."),
+            ("for (;; i = i + 1 print i;", "Expect ')' after for clauses."),
+            ("for (;;) continue", "Expect ';' after 'continue'."),
+        ] {
+            assert_eq!(Parser::new(Scanner::new(String::from(source)).scan_tokens()).parse(), Err(String::from(message)));
+        }
+    }
+
+    #[test]
+    fn test_logical_precedence() {
+        for (source, expected) in [
+            ("a or b and c", "(or a (and b c))"),
+            ("a and b or c and d", "(or (and a b) (and c d))"),
+            ("a == 1 and b", "(and (== a 1) b)"),
+            ("a or b ? 1 : 2", "(? (or a b) 1 : 2)"),
+            ("x = a or b", "(assign x (or a b))"),
+        ] {
+            let mut parser = Parser::new(Scanner::new(String::from(source)).scan_tokens());
+            assert_eq!(parser.expression().map(|expr| expr.to_string()), Ok(String::from(expected)));
+        }
+    }
+
+    #[test]
+    fn test_try_statement() {
+        let mut parser = Parser::new(Scanner::new(String::from("try { print 1; } catch (e) { print e; }")).scan_tokens());
+        assert_eq!(parser.parse().map(|statements| statements[0].to_string()), Ok(String::from("(try (block (print 1)) e (block (print e)))")));
+
+        for (source, message) in [
+            ("try print 1;", "Expect '{' after 'try'."),
+            ("try {} print 1;", "Expect 'catch' after try block."),
+            ("try {} catch e {}", "Expect '(' after 'catch'."),
+            ("try {} catch (1) {}", "Expect error variable name."),
+        ] {
+            assert_eq!(Parser::new(Scanner::new(String::from(source)).scan_tokens()).parse(), Err(String::from(message)));
+        }
+
+        let mut parser = Parser::new(Scanner::new(String::from("try {} catch (e) {}")).scan_tokens());
+        parser.strict = true;
+        assert_eq!(parser.parse(), Err(String::from("The try statement is not part of standard Lox.")));
+    }
+
+    #[test]
+    fn test_throw_statement() {
+        let mut parser = Parser::new(Scanner::new(String::from("throw \"bad \" + x;")).scan_tokens());
+        assert_eq!(parser.parse().map(|statements| statements[0].to_string()), Ok(String::from("(throw (+ \"bad \" x))")));
+
+        let mut parser = Parser::new(Scanner::new(String::from("throw 1")).scan_tokens());
+        assert_eq!(parser.parse(), Err(String::from("Expect ';' after thrown value.")));
+
+        let mut parser = Parser::new(Scanner::new(String::from("throw 1;")).scan_tokens());
+        parser.strict = true;
+        assert_eq!(parser.parse(), Err(String::from("The throw statement is not part of standard Lox.")));
+    }
+
+    #[test]
+    fn test_quiet_parse_reports_nothing() {
+        crate::rlox::HAD_ERROR.with(|had_error| had_error.set(false));
+        let mut parser = Parser::new(Scanner::new(String::from("1 +; var = 1;")).scan_tokens());
+        parser.quiet = true;
+        assert_eq!(parser.parse(), Err(String::from("Expect expression.")));
+        assert_eq!(parser.errors(), [String::from("Expect expression."), String::from("Expect variable name.")]);
+        assert!(!crate::rlox::HAD_ERROR.with(|had_error| had_error.get()));
+
+        let mut parser = Parser::new(Scanner::new(String::from("1 +;")).scan_tokens());
+        assert!(parser.parse().is_err());
+        assert!(crate::rlox::HAD_ERROR.with(|had_error| had_error.get()));
+    }
+
+    #[test]
+    fn test_empty_parentheses() {
+        let mut parser = Parser::new(Scanner::new(String::from("print ();")).scan_tokens());
+        assert_eq!(parser.parse(), Err(String::from("Expected expression inside parentheses.")));
+
+        let mut parser = Parser::new(Scanner::new(String::from("f()")).scan_tokens());
+        assert!(parser.expression().is_ok());
+    }
+
     #[test]
     fn test_parse_and_scanned_error_parenthesis() {
         let source = "1 + 2 == 5 / (2";
@@ -471,15 +1329,15 @@ mod tests {
         assert_eq!(expr, Ok(Expr::Binary(
             Box::new(Expr::Binary(
                 Box::new(Expr::Unary(
-                    Token::new(TokenType::Minus, String::from("-"), 1),
-                    Box::new(Expr::Literal(Token::new(TokenType::Number(1.0), String::from("1"), 1)))
+                    token(TokenType::Minus, "-"),
+                    Box::new(Expr::Literal(token(TokenType::Int(1), "1"), Value::Int(1)))
                 )),
-                Token::new(TokenType::Plus, String::from("+"), 1),
-                Box::new(Expr::Literal(Token::new(TokenType::Identifier(String::from("aux")), String::from("aux"), 1)))
-                //Box::new(Expr::Literal(Token::new(TokenType::Number(2.0), String::from("2"), 1)))
+                token(TokenType::Plus, "+"),
+                Box::new(Expr::Variable(token(TokenType::Identifier(String::from("aux")), "aux")))
+                //Box::new(Expr::Literal(token(TokenType::Int(2), "2"), Value::Int(2)))
             )),
-            Token::new(TokenType::EqualEqual, String::from("=="), 1),
-            Box::new(Expr::Literal(Token::new(TokenType::Number(5.0), String::from("5"), 1)))
+            token(TokenType::EqualEqual, "=="),
+            Box::new(Expr::Literal(token(TokenType::Int(5), "5"), Value::Int(5)))
         )));
     }
 
@@ -493,7 +1351,7 @@ mod tests {
         let mut parser = Parser::new(tokens);
         let expr = parser.expression();
 
-        assert_eq!(expr, Ok(Expr::Literal(Token::new(TokenType::Identifier(String::from("aux")), String::from("aux"), 1))));
+        assert_eq!(expr, Ok(Expr::Variable(token(TokenType::Identifier(String::from("aux")), "aux"))));
     }
 
     #[test]
@@ -504,16 +1362,16 @@ mod tests {
         let tokens = scanner.scan_tokens();
 
         let mut parser = Parser::new(tokens);
-        let expr = parser.expression();
+        let expr = parser.comma();
 
         assert_eq!(expr, Ok(Expr::Binary(
             Box::new(Expr::Binary(
-                Box::new(Expr::Literal(Token::new(TokenType::Number(1.0), String::from("1"), 1))),
-                Token::new(TokenType::Comma, String::from(","), 1),
-                Box::new(Expr::Literal(Token::new(TokenType::Number(2.0), String::from("2"), 1)))
+                Box::new(Expr::Literal(token(TokenType::Int(1), "1"), Value::Int(1))),
+                token(TokenType::Comma, ","),
+                Box::new(Expr::Literal(token(TokenType::Int(2), "2"), Value::Int(2)))
             )),
-            Token::new(TokenType::Comma, String::from(","), 1),
-            Box::new(Expr::Literal(Token::new(TokenType::Number(3.0), String::from("3"), 1)))
+            token(TokenType::Comma, ","),
+            Box::new(Expr::Literal(token(TokenType::Int(3), "3"), Value::Int(3)))
         )));
     }
 
@@ -525,7 +1383,7 @@ mod tests {
         let tokens = scanner.scan_tokens();
 
         let mut parser = Parser::new(tokens);
-        let expr = parser.expression();
+        let expr = parser.comma();
         assert_eq!(expr, Err(String::from("Expect expression.")));
     }
 
@@ -539,11 +1397,11 @@ mod tests {
         let mut parser = Parser::new(tokens);
         let expr = parser.expression();
         assert_eq!(expr, Ok(Expr::Ternary(
-            Box::new(Expr::Literal(Token::new(TokenType::Number(5.0), String::from("5"), 1))),
-            Token::new(TokenType::QuestionMark, String::from("?"), 1),
-            Box::new(Expr::Literal(Token::new(TokenType::Number(1.0), String::from("1"), 1))),
-            Token::new(TokenType::Colon, String::from(":"), 1),
-            Box::new(Expr::Literal(Token::new(TokenType::Number(2.0), String::from("2"), 1)))
+            Box::new(Expr::Literal(token(TokenType::Int(5), "5"), Value::Int(5))),
+            token(TokenType::QuestionMark, "?"),
+            Box::new(Expr::Literal(token(TokenType::Int(1), "1"), Value::Int(1))),
+            token(TokenType::Colon, ":"),
+            Box::new(Expr::Literal(token(TokenType::Int(2), "2"), Value::Int(2)))
         )));
     }
 
@@ -569,13 +1427,13 @@ mod tests {
         let mut parser = Parser::new(tokens);
         let statements = parser.parse();
         assert_eq!(statements, Ok(vec![
-            Stmt::Var(Token::new(TokenType::Identifier(String::from("a")), String::from("a"), 1), Expr::Literal(Token::new(TokenType::Number(1.0), String::from("1"), 1))),
-            Stmt::Var(Token::new(TokenType::Identifier(String::from("b")), String::from("b"), 1), Expr::Literal(Token::new(TokenType::Number(2.0), String::from("2"), 1))),
-            Stmt::Print(Expr::Binary(
-                Box::new(Expr::Variable(Token::new(TokenType::Identifier(String::from("a")), String::from("a"), 1))),
-                Token::new(TokenType::Plus, String::from("+"), 1),
-                Box::new(Expr::Variable(Token::new(TokenType::Identifier(String::from("b")), String::from("b"), 1)))
-            ))
+            Stmt::Var(token(TokenType::Identifier(String::from("a")), "a"), Expr::Literal(token(TokenType::Int(1), "1"), Value::Int(1))),
+            Stmt::Var(token(TokenType::Identifier(String::from("b")), "b"), Expr::Literal(token(TokenType::Int(2), "2"), Value::Int(2))),
+            Stmt::Print(token(TokenType::Print, "print"), Some(Expr::Binary(
+                Box::new(Expr::Variable(token(TokenType::Identifier(String::from("a")), "a"))),
+                token(TokenType::Plus, "+"),
+                Box::new(Expr::Variable(token(TokenType::Identifier(String::from("b")), "b")))
+            )))
         ]));
     }
 
@@ -590,6 +1448,17 @@ mod tests {
         assert_eq!(parser.parse(), Err(String::from("Expect ';' after expression.")));
     }
 
+    #[test]
+    fn test_unterminated_block() {
+        let source = "{\n  var a = 1;\n  print a;\n";
+
+        let mut scanner = Scanner::new(String::from(source));
+        let tokens = scanner.scan_tokens();
+
+        let mut parser = Parser::new(tokens);
+        assert_eq!(parser.parse(), Err(String::from("Expect '}' to close block opened at line 1.")));
+    }
+
     #[test]
     fn test_program_error2() {
         let source = "var a = ;";
@@ -600,4 +1469,358 @@ mod tests {
         let mut parser = Parser::new(tokens);
         assert_eq!(parser.parse(), Err(String::from("Expect expression.")));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_call() {
+        let source = "f(1, 2)";
+
+        let mut scanner = Scanner::new(String::from(source));
+        let tokens = scanner.scan_tokens();
+
+        let mut parser = Parser::new(tokens);
+        let expr = parser.expression();
+        assert_eq!(expr, Ok(Expr::Call(
+            Box::new(Expr::Variable(token(TokenType::Identifier(String::from("f")), "f"))),
+            token(TokenType::RightParen, ")"),
+            vec![
+                Expr::Literal(token(TokenType::Int(1), "1"), Value::Int(1)),
+                Expr::Literal(token(TokenType::Int(2), "2"), Value::Int(2)),
+            ]
+        )));
+    }
+
+    #[test]
+    fn test_too_many_arguments() {
+        let arguments = (0..256).map(|n| n.to_string()).collect::<Vec<String>>().join(", ");
+        let source = format!("f({}); print 1;", arguments);
+
+        let mut scanner = Scanner::new(source.clone());
+        let mut parser = Parser::new(scanner.scan_tokens());
+        match parser.expression() {
+            Ok(Expr::Call(_, _, arguments)) => assert_eq!(arguments.len(), 256),
+            expr => panic!("Expected a call, got {:?}", expr),
+        }
+        assert_eq!(parser.errors(), &[String::from("Can't have more than 255 arguments.")]);
+
+        let mut parser = Parser::new(Scanner::new(source).scan_tokens());
+        assert_eq!(parser.parse(), Err(String::from("Can't have more than 255 arguments.")));
+        assert!(parser.is_at_end());
+
+        let arguments = (0..255).map(|n| n.to_string()).collect::<Vec<String>>().join(", ");
+        let mut parser = Parser::new(Scanner::new(format!("f({});", arguments)).scan_tokens());
+        assert!(parser.parse().is_ok());
+    }
+
+    #[test]
+    fn test_ast_ignores_lines() {
+        let single = Parser::new(Scanner::new(String::from("a = 1 + b;")).scan_tokens()).parse();
+        let spread = Parser::new(Scanner::new(String::from("a\n=\n1\n+\nb\n;")).scan_tokens()).parse();
+        assert_eq!(single, spread);
+        assert_ne!(token(TokenType::Plus, "+"), token(TokenType::Minus, "+"));
+        assert_ne!(token(TokenType::Number(1.0), "1"), token(TokenType::Number(1.0), "1.0"));
+    }
+
+    #[test]
+    fn test_node_lines() {
+        let source = "var a = 1;\nprint a\n+ 2;\n{\nb = -3;\n}";
+
+        let mut scanner = Scanner::new(String::from(source));
+        let tokens = scanner.scan_tokens();
+
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse().unwrap();
+        assert_eq!(statements[0].line(), 1);
+        assert_eq!(statements[1].line(), 2);
+        assert_eq!(statements[2].line(), 4);
+        match &statements[2] {
+            Stmt::Block(_, block) => match &block[0] {
+                Stmt::Expression(Expr::Assign(_, value)) => assert_eq!(value.line(), 5),
+                statement => panic!("Unexpected statement {:?}", statement),
+            },
+            statement => panic!("Unexpected statement {:?}", statement),
+        }
+    }
+
+    #[test]
+    fn test_comma_only_at_statement_level() {
+        let source = "1, 2, 3 == 3;";
+
+        let mut scanner = Scanner::new(String::from(source));
+        let mut parser = Parser::new(scanner.scan_tokens());
+        let statements = parser.parse();
+        assert!(matches!(&statements, Ok(statements) if matches!(&statements[0], Stmt::Expression(Expr::Binary(_, operator, _)) if operator.token_type == TokenType::Comma)));
+
+        let mut scanner = Scanner::new(String::from("print 1, 2;"));
+        let mut parser = Parser::new(scanner.scan_tokens());
+        assert_eq!(parser.parse(), Err(String::from("Expect ';' after expression.")));
+
+        let mut scanner = Scanner::new(String::from("(1, 2)"));
+        let mut parser = Parser::new(scanner.scan_tokens());
+        assert_eq!(parser.expression(), Err(String::from("Expect ')' after expression.")));
+    }
+
+    #[test]
+    fn test_var_group() {
+        let source = "var a = 1, b = 2, c;";
+
+        let mut scanner = Scanner::new(String::from(source));
+        let tokens = scanner.scan_tokens();
+
+        let mut parser = Parser::new(tokens);
+        assert_eq!(parser.parse(), Ok(vec![
+            Stmt::VarGroup(vec![
+                (token(TokenType::Identifier(String::from("a")), "a"), Expr::Literal(token(TokenType::Int(1), "1"), Value::Int(1))),
+                (token(TokenType::Identifier(String::from("b")), "b"), Expr::Literal(token(TokenType::Int(2), "2"), Value::Int(2))),
+                (token(TokenType::Identifier(String::from("c")), "c"), Expr::Literal(token(TokenType::Nil, "nil"), Value::Nil)),
+            ])
+        ]));
+    }
+
+    #[test]
+    fn test_var_group_error() {
+        let source = "var a = 1, ;";
+
+        let mut scanner = Scanner::new(String::from(source));
+        let tokens = scanner.scan_tokens();
+
+        let mut parser = Parser::new(tokens);
+        assert_eq!(parser.parse(), Err(String::from("Expect variable name.")));
+    }
+
+    // Debug builds use a lot of stack per nesting level, more than the default test thread has
+    fn with_large_stack(test: fn()) {
+        std::thread::Builder::new()
+            .stack_size(64 * 1024 * 1024)
+            .spawn(test)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_nesting_too_deep() {
+        with_large_stack(|| {
+            let source = format!("{}1{}", "(".repeat(10000), ")".repeat(10000));
+
+            let mut scanner = Scanner::new(source);
+            let mut parser = Parser::new(scanner.scan_tokens());
+            assert_eq!(parser.expression(), Err(String::from("Expression nesting too deep.")));
+
+            let source = format!("{}1;", "-".repeat(10000));
+            let mut scanner = Scanner::new(source);
+            let mut parser = Parser::new(scanner.scan_tokens());
+            assert_eq!(parser.parse(), Err(String::from("Expression nesting too deep.")));
+        });
+    }
+
+    #[test]
+    fn test_statement_nesting_too_deep() {
+        with_large_stack(|| {
+            let mut parser = Parser::new(Scanner::new("{".repeat(20000)).scan_tokens());
+            assert_eq!(parser.parse(), Err(String::from("Statement nesting too deep.")));
+
+            let mut parser = Parser::new(Scanner::new(format!("{}print 1;", "if (true) ".repeat(50000))).scan_tokens());
+            assert_eq!(parser.parse(), Err(String::from("Statement nesting too deep.")));
+
+            let mut parser = Parser::new(Scanner::new("fun f() {".repeat(20000)).scan_tokens());
+            assert_eq!(parser.parse(), Err(String::from("Statement nesting too deep.")));
+        });
+    }
+
+    #[test]
+    fn test_nesting_within_limit() {
+        with_large_stack(|| {
+            let depth = DEFAULT_MAX_DEPTH - 1;
+            let source = format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+
+            let mut scanner = Scanner::new(source);
+            let mut parser = Parser::new(scanner.scan_tokens());
+            assert!(parser.expression().is_ok());
+
+            let source = format!("{}print 1;", "if (true) ".repeat(depth));
+            let mut parser = Parser::new(Scanner::new(source).scan_tokens());
+            assert!(parser.parse().is_ok());
+
+            let source = format!("{}{}", "{".repeat(depth), "}".repeat(depth));
+            let mut parser = Parser::new(Scanner::new(source).scan_tokens());
+            assert!(parser.parse().is_ok());
+
+            // The deepest expression still fits inside the deepest statement
+            let source = format!("{}print {}1{};{}", "{".repeat(depth), "(".repeat(depth - 1), ")".repeat(depth - 1), "}".repeat(depth));
+            let mut parser = Parser::new(Scanner::new(source).scan_tokens());
+            assert!(parser.parse().is_ok());
+        });
+    }
+
+    #[test]
+    fn test_long_else_if_chain() {
+        let branches = (0..60).map(|n| format!("if (x == {}) print {};", n, n)).collect::<Vec<String>>().join(" else ");
+        let mut parser = Parser::new(Scanner::new(format!("var x = 59; {}", branches)).scan_tokens());
+        assert!(parser.parse().is_ok());
+    }
+
+    #[test]
+    fn test_assignment_targets() {
+        let mut scanner = Scanner::new(String::from("a = b = 1"));
+        let mut parser = Parser::new(scanner.scan_tokens());
+        assert_eq!(parser.expression(), Ok(Expr::Assign(
+            token(TokenType::Identifier(String::from("a")), "a"),
+            Box::new(Expr::Assign(
+                token(TokenType::Identifier(String::from("b")), "b"),
+                Box::new(Expr::Literal(token(TokenType::Int(1), "1"), Value::Int(1)))
+            ))
+        )));
+
+        for source in ["1 = 2", "a + b = 3", "(a) = 3", "f() = 3"] {
+            let mut scanner = Scanner::new(String::from(source));
+            let mut parser = Parser::new(scanner.scan_tokens());
+            assert_eq!(parser.expression(), Err(String::from("Invalid assignment target.")));
+        }
+    }
+
+    #[test]
+    fn test_lists_maps_and_indexing() {
+        for (source, expected) in [
+            ("[]", "(list)"),
+            ("[1, [2], a + 1]", "(list 1 (list 2) (+ a 1))"),
+            ("{}", "(map)"),
+            ("{\"a\": 1, k: [2]}", "(map \"a\" 1 k (list 2))"),
+            ("a[0][i + 1]", "([] ([] a 0) (+ i 1))"),
+            ("m[\"k\"] = a[0] = 1", "([]= m \"k\" ([]= a 0 1))"),
+            ("f()[0].length()", "(call (. ([] (call f) 0) length))"),
+        ] {
+            let mut parser = Parser::new(Scanner::new(String::from(source)).scan_tokens());
+            assert_eq!(parser.expression().map(|expression| expression.to_string()), Ok(String::from(expected)), "{}", source);
+        }
+
+        for (source, message) in [
+            ("[1, 2", "Expect ']' after list elements."),
+            ("{\"a\" 1}", "Expect ':' after map key."),
+            ("{\"a\": 1", "Expect '}' after map entries."),
+            ("a[0", "Expect ']' after index."),
+            ("a[]", "Expect expression."),
+        ] {
+            let mut parser = Parser::new(Scanner::new(String::from(source)).scan_tokens());
+            assert_eq!(parser.expression(), Err(String::from(message)), "{}", source);
+        }
+
+        // A '{' starting a statement is still a block
+        let statements = Parser::new(Scanner::new(String::from("{ print 1; } var m = {};")).scan_tokens()).parse().unwrap();
+        assert_eq!(statements[0].to_string(), "(block (print 1))");
+        assert_eq!(statements[1].to_string(), "(var m (map))");
+    }
+
+    #[test]
+    fn test_slices() {
+        for (source, expected) in [
+            ("a[1:3]", "([:] a 1 3)"),
+            ("a[:2]", "([:] a _ 2)"),
+            ("a[1:]", "([:] a 1 _)"),
+            ("a[:]", "([:] a _ _)"),
+            ("a[i + 1:-1][0]", "([] ([:] a (+ i 1) (- 1)) 0)"),
+            // The index's own ternary takes the first ':'
+            ("a[c ? 1 : 2 : 3]", "([:] a (? c 1 : 2) 3)"),
+        ] {
+            let mut parser = Parser::new(Scanner::new(String::from(source)).scan_tokens());
+            assert_eq!(parser.expression().map(|expression| expression.to_string()), Ok(String::from(expected)), "{}", source);
+        }
+
+        for (source, message) in [
+            ("a[1:2", "Expect ']' after slice."),
+            ("a[1:2:3]", "Expect ']' after slice."),
+        ] {
+            let mut parser = Parser::new(Scanner::new(String::from(source)).scan_tokens());
+            assert_eq!(parser.expression(), Err(String::from(message)), "{}", source);
+        }
+        let mut parser = Parser::new(Scanner::new(String::from("a[1:2] = 3;")).scan_tokens());
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    fn test_classes() {
+        let source = "class B < A { init(x) { this.x = x; } get() { return super.get() + this.x; } }";
+        let statements = Parser::new(Scanner::new(String::from(source)).scan_tokens()).parse().unwrap();
+        assert_eq!(
+            statements[0].to_string(),
+            "(class B < A (fun init (x) (; (.= this x x))) (fun get () (return (+ (call (super get)) (. this x)))))"
+        );
+
+        for (source, message) in [
+            ("class {}", "Expect class name."),
+            ("class A < {}", "Expect superclass name."),
+            ("class A { f() {} ", "Expect '}' after class body."),
+            ("print super;", "Expect '.' after 'super'."),
+            ("f().x = 1 = 2;", "Invalid assignment target."),
+        ] {
+            assert_eq!(Parser::new(Scanner::new(String::from(source)).scan_tokens()).parse(), Err(String::from(message)), "{}", source);
+        }
+
+        // Calls and property accesses chain in any order, each applying to the result so far
+        let mut parser = Parser::new(Scanner::new(String::from("a.b().c(1).d = e.f()")).scan_tokens());
+        assert_eq!(parser.expression().map(|expr| expr.to_string()), Ok(String::from("(.= (call (. (call (. a b)) c) 1) d (call (. e f)))")));
+
+        // Classes are part of standard Lox
+        let mut parser = Parser::new(Scanner::new(String::from("class A { f() { return this; } }")).scan_tokens());
+        parser.strict = true;
+        assert!(parser.parse().is_ok());
+    }
+
+    #[test]
+    fn test_parse_method_call() {
+        let mut scanner = Scanner::new(String::from("\"hi\".length()"));
+        let mut parser = Parser::new(scanner.scan_tokens());
+        assert_eq!(parser.expression(), Ok(Expr::Call(
+            Box::new(Expr::Get(
+                Box::new(Expr::Literal(token(TokenType::String(String::from("hi")), "\"hi\""), Value::String(String::from("hi")))),
+                token(TokenType::Identifier(String::from("length")), "length")
+            )),
+            token(TokenType::RightParen, ")"),
+            vec![]
+        )));
+
+        let mut scanner = Scanner::new(String::from("a.1"));
+        let mut parser = Parser::new(scanner.scan_tokens());
+        assert_eq!(parser.expression(), Err(String::from("Expect property name after '.'.")));
+    }
+
+    #[test]
+    fn test_print_without_expression() {
+        let mut scanner = Scanner::new(String::from("print;"));
+        let mut parser = Parser::new(scanner.scan_tokens());
+        assert_eq!(parser.parse(), Ok(vec![Stmt::Print(token(TokenType::Print, "print"), None)]));
+    }
+
+    #[test]
+    fn test_keyword_lines() {
+        let source = "{
+}
+print
+;
+try
+{
+print 1;
+} catch (e) {}";
+        let statements = Parser::new(Scanner::new(String::from(source)).scan_tokens()).parse().unwrap();
+        let lines = statements.iter().map(|statement| statement.line()).collect::<Vec<usize>>();
+        assert_eq!(lines, vec![1, 3, 5]);
+    }
+
+    #[test]
+    fn test_synchronize_resumes_at_next_statement() {
+        let mut parser = Parser::new(Scanner::new(String::from("print 1;\nvar = 2;\nprint 3;")).scan_tokens());
+        assert_eq!(parser.parse(), Err(String::from("Expect variable name.")));
+        assert_eq!(parser.errors(), &[String::from("Expect variable name.")]);
+
+        let mut parser = Parser::new(Scanner::new(String::from("a = ;\nprint 2;\n{ var = 1; print 3; }\nprint 4;")).scan_tokens());
+        assert!(parser.parse().is_err());
+        assert_eq!(parser.errors(), &[String::from("Expect expression."), String::from("Expect variable name.")]);
+
+        // Skips past a ';' or stops before a statement keyword
+        let mut parser = Parser::new(Scanner::new(String::from("a b c; print 1; x y print 2;")).scan_tokens());
+        parser.synchronize();
+        assert_eq!(parser.peek().token_type, TokenType::Print);
+        parser.current = 8;
+        parser.synchronize();
+        assert_eq!(parser.peek().token_type, TokenType::Print);
+    }
+}
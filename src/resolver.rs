@@ -0,0 +1,444 @@
+use std::collections::HashMap;
+
+use crate::error::RloxError;
+use crate::expressions::Expr;
+use crate::statements::Stmt;
+use crate::token::Token;
+
+// Statements that passed static checking, along with any warnings found on the way
+#[derive(Debug, PartialEq)]
+pub struct ResolvedProgram {
+    pub statements: Vec<Stmt>,
+    // Always worth showing, like a variable shadowing an outer one
+    pub warnings: Vec<String>,
+    // Likely mistakes such as an assignment used as a condition, only shown under --warn
+    pub analysis: Vec<String>,
+}
+
+impl ResolvedProgram {
+    // The warnings to print, the analysis ones only when asked for
+    pub fn shown_warnings(&self, analysis: bool) -> Vec<&String> {
+        let analysis = if analysis { &self.analysis[..] } else { &[] };
+        self.warnings.iter().chain(analysis).collect()
+    }
+}
+
+// Walks the whole program once before it runs and collects every static error
+// instead of stopping at the first one
+pub fn resolve(statements: Vec<Stmt>) -> Result<ResolvedProgram, Vec<RloxError>> {
+    let mut resolver = Resolver::new();
+    for statement in &statements {
+        resolver.statement(statement);
+    }
+
+    if resolver.errors.is_empty() {
+        Ok(ResolvedProgram { statements, warnings: resolver.warnings, analysis: resolver.analysis })
+    } else {
+        Err(resolver.errors)
+    }
+}
+
+struct Resolver {
+    // Innermost scope last, the global scope is at the bottom. A name maps to
+    // false while its initializer is being resolved.
+    scopes: Vec<HashMap<String, bool>>,
+    errors: Vec<RloxError>,
+    warnings: Vec<String>,
+    analysis: Vec<String>,
+    // Loops enclosing the current statement within the current function
+    loops: usize,
+    // Function bodies enclosing the current statement
+    functions: usize,
+    // Whether the innermost function is a class's init, which can't return a value
+    initializer: bool,
+    // The innermost class declaration around the current statement, for this and super
+    class: ClassKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClassKind {
+    None,
+    Class,
+    Subclass,
+}
+
+impl Resolver {
+    fn new() -> Resolver {
+        Resolver {
+            scopes: vec![HashMap::new()],
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            analysis: Vec::new(),
+            loops: 0,
+            functions: 0,
+            initializer: false,
+            class: ClassKind::None,
+        }
+    }
+
+    fn statement(&mut self, statement: &Stmt) {
+        match statement {
+            Stmt::Expression(expression) => {
+                // Usually a forgotten print or '='
+                if !expression.has_side_effects() {
+                    self.analysis.push(format!("[line {}] Warning: expression result is unused.", expression.line()));
+                }
+                self.expression(expression);
+            }
+            Stmt::Assert(expression) | Stmt::Throw(expression) => self.expression(expression),
+            Stmt::While(_, condition, body, increment, else_branch) => {
+                self.condition(condition);
+                self.expression(condition);
+                self.loops += 1;
+                self.statement(body);
+                self.loops -= 1;
+                if let Some(increment) = increment {
+                    self.expression(increment);
+                }
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Stmt::Return(keyword, value) => {
+                if self.functions == 0 {
+                    self.errors.push(error(keyword, "Can't return from top-level code."));
+                } else if self.initializer && value.is_some() {
+                    self.errors.push(error(keyword, "Can't return a value from an initializer."));
+                }
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            Stmt::Break(keyword) => {
+                if self.loops == 0 {
+                    self.errors.push(error(keyword, "Can't use 'break' outside of a loop."));
+                }
+            }
+            Stmt::Continue(keyword) => {
+                if self.loops == 0 {
+                    self.errors.push(error(keyword, "Can't use 'continue' outside of a loop."));
+                }
+            }
+            Stmt::If(_, condition, then_branch, else_branch) => {
+                self.condition(condition);
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Stmt::Print(_, expression) => {
+                if let Some(expression) = expression {
+                    self.expression(expression);
+                }
+            }
+            Stmt::Var(name, initializer) => self.declaration(name, initializer),
+            Stmt::VarGroup(declarations) => {
+                for (name, initializer) in declarations {
+                    self.declaration(name, initializer);
+                }
+            }
+            Stmt::Block(_, statements) => {
+                self.scopes.push(HashMap::new());
+                for statement in statements {
+                    self.statement(statement);
+                }
+                self.scopes.pop();
+            }
+            Stmt::Function(name, params, body) => {
+                self.declare(name);
+                self.define(name);
+                self.function(params, body, false);
+            }
+            Stmt::Class(name, superclass, methods) => self.class(name, superclass.as_ref(), methods),
+            Stmt::Try(_, body, name, handler) => {
+                self.statement(body);
+                self.scopes.push(HashMap::new());
+                self.declare(name);
+                self.define(name);
+                self.statement(handler);
+                self.scopes.pop();
+            }
+        }
+    }
+
+    fn function(&mut self, params: &[Token], body: &[Stmt], initializer: bool) {
+        self.scopes.push(HashMap::new());
+        for param in params {
+            self.declare(param);
+            self.define(param);
+        }
+        // A break can't leave the function for a loop around the declaration
+        let loops = std::mem::replace(&mut self.loops, 0);
+        let enclosing_initializer = std::mem::replace(&mut self.initializer, initializer);
+        self.functions += 1;
+        for statement in body {
+            self.statement(statement);
+        }
+        self.functions -= 1;
+        self.initializer = enclosing_initializer;
+        self.loops = loops;
+        self.scopes.pop();
+    }
+
+    // The methods see `super` and `this` in scopes of their own, like at runtime
+    fn class(&mut self, name: &Token, superclass: Option<&Expr>, methods: &[Stmt]) {
+        self.declare(name);
+        self.define(name);
+        let enclosing = std::mem::replace(&mut self.class, ClassKind::Class);
+        if let Some(superclass) = superclass {
+            if let Expr::Variable(superclass_name) = superclass {
+                if superclass_name.lexeme == name.lexeme {
+                    self.errors.push(error(superclass_name, "A class can't inherit from itself."));
+                }
+            }
+            self.expression(superclass);
+            self.class = ClassKind::Subclass;
+            self.scopes.push(HashMap::from([(String::from("super"), true)]));
+        }
+        self.scopes.push(HashMap::from([(String::from("this"), true)]));
+        for method in methods {
+            if let Stmt::Function(method_name, params, body) = method {
+                self.function(params, body, method_name.lexeme == "init");
+            }
+        }
+        self.scopes.pop();
+        if superclass.is_some() {
+            self.scopes.pop();
+        }
+        self.class = enclosing;
+    }
+
+    fn declaration(&mut self, name: &Token, initializer: &Expr) {
+        self.declare(name);
+        self.expression(initializer);
+        self.define(name);
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), true);
+        }
+    }
+
+    fn declare(&mut self, name: &Token) {
+        let is_global = self.scopes.len() == 1;
+        let (scope, enclosing) = self.scopes.split_last_mut().unwrap();
+        if scope.contains_key(&name.lexeme) {
+            // Globals may be redefined, the REPL relies on it
+            if !is_global {
+                self.errors.push(error(name, "Already a variable with this name in this scope."));
+            }
+        } else if enclosing.iter().any(|scope| scope.contains_key(&name.lexeme)) {
+            self.warnings.push(format!("[line {}] Warning at '{}': Shadows an outer variable.", name.line, name.lexeme));
+        }
+        scope.insert(name.lexeme.clone(), false);
+    }
+
+    // Catches `(a = b) ? ...`, `if (a = b)` or `while (a = b)` where a comparison was almost certainly meant
+    fn condition(&mut self, condition: &Expr) {
+        let mut inner = condition;
+        while let Expr::Grouping(expression) = inner {
+            inner = expression;
+        }
+        if let Expr::Assign(name, _) = inner {
+            self.analysis.push(format!("[line {}] Warning: assignment used as condition; did you mean '=='?", name.line));
+        }
+    }
+
+    fn expression(&mut self, expression: &Expr) {
+        match expression {
+            Expr::Binary(left, _, right) | Expr::Logical(left, _, right) => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Ternary(condition, _, then_branch, _, else_branch) => {
+                self.condition(condition);
+                self.expression(condition);
+                self.expression(then_branch);
+                self.expression(else_branch);
+            }
+            Expr::Grouping(expression) => self.expression(expression),
+            Expr::Literal(_, _) => (),
+            Expr::Unary(_, right) => self.expression(right),
+            Expr::Assign(_, value) => self.expression(value),
+            Expr::Variable(name) => {
+                // Only locals, a global initializer may legitimately read an older global
+                let is_local = self.scopes.len() > 1;
+                if is_local && self.scopes.last().and_then(|scope| scope.get(&name.lexeme)) == Some(&false) {
+                    self.errors.push(error(name, "Can't read local variable in its own initializer."));
+                }
+            }
+            Expr::Call(callee, _, arguments) => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            Expr::Get(object, _) => self.expression(object),
+            Expr::Set(object, _, value) => {
+                self.expression(object);
+                self.expression(value);
+            }
+            Expr::This(keyword) => {
+                if self.class == ClassKind::None {
+                    self.errors.push(error(keyword, "Can't use 'this' outside of a class."));
+                }
+            }
+            Expr::Super(keyword, _) => match self.class {
+                ClassKind::None => self.errors.push(error(keyword, "Can't use 'super' outside of a class.")),
+                ClassKind::Class => self.errors.push(error(keyword, "Can't use 'super' in a class with no superclass.")),
+                ClassKind::Subclass => (),
+            },
+            Expr::List(_, elements) => {
+                for element in elements {
+                    self.expression(element);
+                }
+            }
+            Expr::Map(_, entries) => {
+                for (key, value) in entries {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            Expr::Index(collection, _, index) => {
+                self.expression(collection);
+                self.expression(index);
+            }
+            Expr::SetIndex(collection, _, index, value) => {
+                self.expression(collection);
+                self.expression(index);
+                self.expression(value);
+            }
+            Expr::Slice(collection, start, end) => {
+                self.expression(collection);
+                for bound in [start, end].into_iter().flatten() {
+                    self.expression(bound);
+                }
+            }
+        }
+    }
+}
+
+fn error(name: &Token, message: &str) -> RloxError {
+    RloxError::Resolve(name.line, name.lexeme.clone(), message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn resolve_source(source: &str) -> Result<ResolvedProgram, Vec<RloxError>> {
+        let (tokens, _) = Scanner::tokenize(source);
+        resolve(Parser::new(tokens).parse().unwrap())
+    }
+
+    #[test]
+    fn test_reports_all_errors() {
+        let errors = resolve_source("{\n var a = 1;\n var a = 2;\n}\n{\n var b = b;\n}").unwrap_err();
+        assert_eq!(errors, vec![
+            RloxError::Resolve(3, String::from("a"), String::from("Already a variable with this name in this scope.")),
+            RloxError::Resolve(6, String::from("b"), String::from("Can't read local variable in its own initializer.")),
+        ]);
+        assert_eq!(errors[1].to_string(), "[line 6] Error at 'b': Can't read local variable in its own initializer.");
+    }
+
+    #[test]
+    fn test_assignment_as_condition() {
+        let program = resolve_source("var a = 0;\nprint (a = 1) ? \"yes\" : \"no\";").unwrap();
+        assert_eq!(program.analysis, vec![String::from("[line 2] Warning: assignment used as condition; did you mean '=='?")]);
+
+        let program = resolve_source("var a = 0;\nif (a = 1) print a;").unwrap();
+        assert_eq!(program.analysis, vec![String::from("[line 2] Warning: assignment used as condition; did you mean '=='?")]);
+
+        let program = resolve_source("var a = 0; print (a == 1) ? \"yes\" : \"no\"; a = 1 ? 2 : 3;").unwrap();
+        assert!(program.analysis.is_empty());
+    }
+
+    #[test]
+    fn test_unused_expression_result() {
+        let program = resolve_source("var x = 1;\n1 + 2;\nx;\n(x == 1) ? x : -x;").unwrap();
+        assert_eq!(program.analysis, vec![
+            String::from("[line 2] Warning: expression result is unused."),
+            String::from("[line 3] Warning: expression result is unused."),
+            String::from("[line 4] Warning: expression result is unused."),
+        ]);
+
+        let program = resolve_source("var x = 1; f(); x = 1; -(x = 2); x.f(), 1; print x + 1;").unwrap();
+        assert!(program.analysis.is_empty());
+    }
+
+    #[test]
+    fn test_break_outside_loop() {
+        let errors = resolve_source("break;\nwhile (true) { fun f() { break; } break; }\nwhile (false) {} else break;").unwrap_err();
+        assert_eq!(errors, vec![
+            RloxError::Resolve(1, String::from("break"), String::from("Can't use 'break' outside of a loop.")),
+            RloxError::Resolve(2, String::from("break"), String::from("Can't use 'break' outside of a loop.")),
+            RloxError::Resolve(3, String::from("break"), String::from("Can't use 'break' outside of a loop.")),
+        ]);
+        assert!(resolve_source("for (;;) { if (true) { break; } }").is_ok());
+    }
+
+    #[test]
+    fn test_continue_outside_loop() {
+        let errors = resolve_source("continue;\nwhile (true) { fun f() { continue; } continue; }").unwrap_err();
+        assert_eq!(errors, vec![
+            RloxError::Resolve(1, String::from("continue"), String::from("Can't use 'continue' outside of a loop.")),
+            RloxError::Resolve(2, String::from("continue"), String::from("Can't use 'continue' outside of a loop.")),
+        ]);
+        assert!(resolve_source("for (var i = 0; i < 3; i = i + 1) { if (i == 1) continue; }").is_ok());
+    }
+
+    #[test]
+    fn test_return_outside_function() {
+        let errors = resolve_source("return 1;\n{ return; }\nfun f() { return 2; }\nreturn;\nwhile (true) { if (true) return; }").unwrap_err();
+        assert_eq!(errors, vec![
+            RloxError::Resolve(1, String::from("return"), String::from("Can't return from top-level code.")),
+            RloxError::Resolve(2, String::from("return"), String::from("Can't return from top-level code.")),
+            RloxError::Resolve(4, String::from("return"), String::from("Can't return from top-level code.")),
+            RloxError::Resolve(5, String::from("return"), String::from("Can't return from top-level code.")),
+        ]);
+
+        // Inside a function, however deeply nested in blocks, or a method, a return is fine
+        let source = "fun f() { { while (true) { return; } } }\nclass A { m() { fun g() { return 1; } return g(); } }";
+        assert!(resolve_source(source).is_ok());
+    }
+
+    #[test]
+    fn test_class_errors() {
+        let errors = resolve_source("class A < A {}\nprint this;\nclass B {\n init() { return 1; }\n f() { return super.f(); }\n}\nprint super.f;").unwrap_err();
+        assert_eq!(errors, vec![
+            RloxError::Resolve(1, String::from("A"), String::from("A class can't inherit from itself.")),
+            RloxError::Resolve(2, String::from("this"), String::from("Can't use 'this' outside of a class.")),
+            RloxError::Resolve(4, String::from("return"), String::from("Can't return a value from an initializer.")),
+            RloxError::Resolve(5, String::from("super"), String::from("Can't use 'super' in a class with no superclass.")),
+            RloxError::Resolve(7, String::from("super"), String::from("Can't use 'super' outside of a class.")),
+        ]);
+
+        // A bare return in init is fine, and so is returning a value from a function nested in it
+        let source = "class A { init() { fun f() { return 1; } return; } }\nclass B < A { f() { return super.f; } }";
+        assert!(resolve_source(source).is_ok());
+    }
+
+    #[test]
+    fn test_globals_and_shadowing() {
+        let errors = resolve_source("var a = 1; var a = a; { var a = a; print a; }").unwrap_err();
+        assert_eq!(errors.len(), 1);
+
+        let program = resolve_source("var a = 1; var a = a; { var b = a; { var b = 2; } }").unwrap();
+        assert_eq!(program.statements.len(), 3);
+        assert_eq!(program.warnings, vec![String::from("[line 1] Warning at 'b': Shadows an outer variable.")]);
+    }
+
+    #[test]
+    fn test_shown_warnings() {
+        let program = resolve_source("var a = 1;
+{ var a = 2; a == 1; }").unwrap();
+        let shadowing = String::from("[line 2] Warning at 'a': Shadows an outer variable.");
+        let unused = String::from("[line 2] Warning: expression result is unused.");
+        assert_eq!(program.shown_warnings(false), vec![&shadowing]);
+        assert_eq!(program.shown_warnings(true), vec![&shadowing, &unused]);
+    }
+}
@@ -1,43 +1,267 @@
+#[cfg(any(not(feature = "repl"), test))]
+use std::io::BufRead;
+use std::io::Read;
 use std::io::Write;
-use std::io::stdout;
 use std::process::exit;
-use crate::expressions;
+use crate::environment::Value;
 use crate::interpreter::Interpreter;
+use crate::natives::Rng;
 use crate::scanner::Scanner;
 use crate::parser::Parser;
-use std::sync::Mutex;
+use crate::resolver::resolve;
+use crate::statements::Stmt;
+use crate::token::Token;
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::thread::LocalKey;
 
-pub static HAD_ERROR: Mutex<bool> = Mutex::new(false);
+thread_local! {
+    pub static HAD_ERROR: Cell<bool> = const { Cell::new(false) };
+    // Set by --seed, every interpreter the CLI creates starts from it
+    pub static SEED: Cell<Option<u64>> = const { Cell::new(None) };
+    // Set by --warn, the resolver's analysis of likely mistakes is printed too
+    pub static WARN: Cell<bool> = const { Cell::new(false) };
+    // Set by --lint, style warnings about the source are printed before it runs
+    pub static LINT: Cell<bool> = const { Cell::new(false) };
+    // Set by --debug-values, print shows the type of each value
+    pub static DEBUG_VALUES: Cell<bool> = const { Cell::new(false) };
+    // Set by --hash-comments, '#' starts a line comment
+    pub static HASH_COMMENTS: Cell<bool> = const { Cell::new(false) };
+    // Set by --auto-semicolons, a line break can stand in for a ';'
+    pub static AUTO_SEMICOLONS: Cell<bool> = const { Cell::new(false) };
+    // Set by --strict-lox, turns off the extensions to the book's grammar
+    pub static STRICT: Cell<bool> = const { Cell::new(false) };
+    // Set by --watch, the variable whose every change is reported on stderr
+    pub static WATCH: RefCell<Option<String>> = const { RefCell::new(None) };
+    // The arguments after the script's path, the program sees them as the list `args`
+    pub static SCRIPT_ARGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+// How far a script goes through the pipeline, for looking at one phase at a time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    Scan,
+    Parse,
+    Run,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    // Script, how far to take it, and the arguments after it
+    RunFile(String, Stage, Vec<String>),
+    RunPrompt,
+    RunStdin,
+    Eval(String),
+    #[cfg(feature = "serde")]
+    EmitTokensJson(String),
+    // Script to compile, then where to write its AST
+    #[cfg(feature = "serde")]
+    EmitAst(String, String),
+    #[cfg(feature = "serde")]
+    RunAst(String),
+    Version,
+    Help,
+    Usage,
+}
+
+pub fn parse_args(args: &[String]) -> Command {
+    // The first argument is the program name
+    match args.get(1..).unwrap_or_default() {
+        [] => Command::RunPrompt,
+        [flag] if flag == "--version" => Command::Version,
+        [flag] if flag == "--help" || flag == "-h" => Command::Help,
+        [flag] if flag == "--stdin" || flag == "-" => Command::RunStdin,
+        [flag, source] if flag == "-e" => Command::Eval(source.clone()),
+        [flag, path] if flag == "--scan-only" || flag == "--dump-tokens" => Command::RunFile(path.clone(), Stage::Scan, Vec::new()),
+        [flag, path] if flag == "--parse-only" => Command::RunFile(path.clone(), Stage::Parse, Vec::new()),
+        #[cfg(feature = "serde")]
+        [flag, path] if flag == "--emit-tokens-json" => Command::EmitTokensJson(path.clone()),
+        #[cfg(feature = "serde")]
+        [flag, output, path] if flag == "--emit-ast" => Command::EmitAst(path.clone(), output.clone()),
+        #[cfg(feature = "serde")]
+        [flag, path] if flag == "--run-ast" => Command::RunAst(path.clone()),
+        [path, script_args @ ..] if !path.starts_with('-') => Command::RunFile(path.clone(), Stage::Run, script_args.to_vec()),
+        _ => Command::Usage,
+    }
+}
+
+// Boolean flags taken out of the arguments before the command is parsed, usable
+// with any command. run_cli sets each setting from this table and help lists it.
+static SWITCHES: &[(&str, &LocalKey<Cell<bool>>, &str)] = &[
+    ("--warn", &WARN, "Also warn about likely mistakes, e.g. an assignment as a condition"),
+    ("--lint", &LINT, "Warn about trailing whitespace and mixed indentation"),
+    ("--debug-values", &DEBUG_VALUES, "Print values with their type, e.g. number: 3"),
+    ("--hash-comments", &HASH_COMMENTS, "Treat '#' as the start of a line comment"),
+    ("--auto-semicolons", &AUTO_SEMICOLONS, "Let a line break end a statement"),
+    ("--strict-lox", &STRICT, "Reject syntax the book's Lox lacks, natives and primitive methods stay"),
+];
+
+// Every flag usable with any command, the switches and the ones taking a value
+fn options() -> Vec<(&'static str, &'static str)> {
+    let mut options = SWITCHES.iter().map(|(flag, _, description)| (*flag, *description)).collect::<Vec<_>>();
+    options.extend([
+        ("--seed N", "Seed rand() and randint() for reproducible runs"),
+        ("--watch NAME", "Report every change to a variable on stderr"),
+    ]);
+    options
+}
+
+// What parse_args understands, in the same order
+fn commands() -> Vec<(&'static str, &'static str)> {
+    let mut commands = vec![
+        ("--version", "Print the version"),
+        ("--help, -h", "Print this help"),
+        ("--stdin, -", "Run a program read from standard input"),
+        ("-e source", "Run the given source"),
+        ("--scan-only script", "Print the tokens of a script"),
+        ("--dump-tokens script", "Same as --scan-only"),
+        ("--parse-only script", "Print the syntax tree of a script"),
+    ];
+    #[cfg(feature = "serde")]
+    commands.extend([
+        ("--emit-tokens-json script", "Print the tokens of a script as JSON"),
+        ("--emit-ast out script", "Check a script and cache its syntax tree in out"),
+        ("--run-ast file", "Run a cached syntax tree"),
+    ]);
+    commands.push(("script [args...]", "Run a script, which gets the arguments after it in the list args. With no command the REPL starts"));
+    commands
+}
 
+pub fn usage() -> String {
+    let options = options().iter().map(|(flag, _)| format!("[{}]", flag)).collect::<Vec<String>>().join(" ");
+    let commands = commands().iter().map(|(command, _)| command.replace(", ", " | ")).collect::<Vec<String>>().join(" | ");
+    format!("Usage: rlox {} [{}]", options, commands)
+}
+
+pub fn help() -> String {
+    let (options, commands) = (options(), commands());
+    let width = options.iter().chain(commands.iter()).map(|(flag, _)| flag.len()).max().unwrap_or(0);
+    let describe = |entries: &[(&str, &str)]| {
+        entries.iter().map(|(flag, description)| format!("  {:width$}  {}", flag, description, width = width)).collect::<Vec<String>>().join("\n")
+    };
+    format!("{}\n\nOptions:\n{}\n\nCommands:\n{}", usage(), describe(&options), describe(&commands))
+}
+
+// Pulls "--seed N" out of the arguments wherever it appears, an invalid seed is an error
+fn take_seed(args: &mut Vec<String>) -> Result<Option<u64>, ()> {
+    match take_option(args, "--seed")? {
+        Some(seed) => seed.parse::<u64>().map(Some).map_err(|_| ()),
+        None => Ok(None),
+    }
+}
+
+// Pulls "FLAG VALUE" out of the arguments wherever it appears, a missing value is an error
+fn take_option(args: &mut Vec<String>, flag: &str) -> Result<Option<String>, ()> {
+    let Some(position) = args.iter().position(|arg| arg == flag) else {
+        return Ok(None);
+    };
+    let value = args.get(position + 1).cloned().ok_or(())?;
+    args.drain(position..position + 2);
+    Ok(Some(value))
+}
+
+// Removes a boolean flag from the arguments, returning whether it was there
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let before = args.len();
+    args.retain(|arg| arg != flag);
+    args.len() != before
+}
+
+// Runs everything on a thread with a stack big enough for MAX_CALL_DEPTH nested calls,
+// which every interpreter the CLI creates allows
 pub fn main(args: Vec<String>) {
-    match args.len().cmp(&2) { // Clippy wasn't happy with using if else :/
-        std::cmp::Ordering::Greater => {
-            println!("Usage: rlox [script]");
-            exit(64);
+    let cli = std::thread::Builder::new()
+        .stack_size(crate::interpreter::STACK_SIZE)
+        .spawn(move || run_cli(args))
+        .expect("Failed to start the interpreter thread");
+    if cli.join().is_err() {
+        exit(70);
+    }
+}
+
+fn run_cli(mut args: Vec<String>) {
+    for (flag, setting, _) in SWITCHES {
+        setting.with(|setting| setting.set(take_flag(&mut args, flag)));
+    }
+    let command = match (take_seed(&mut args), take_option(&mut args, "--watch")) {
+        (Ok(seed), Ok(watch)) => {
+            SEED.with(|cell| cell.set(seed));
+            WATCH.with(|cell| *cell.borrow_mut() = watch);
+            parse_args(&args)
+        }
+        _ => Command::Usage,
+    };
+    match command {
+        Command::RunFile(path, stage, script_args) => {
+            SCRIPT_ARGS.with(|cell| *cell.borrow_mut() = script_args);
+            exit(run_stage(read_file(&path), stage));
+        }
+        Command::RunPrompt => run_prompt(),
+        Command::RunStdin => exit(run_reader(std::io::stdin())),
+        Command::Eval(source) => exit(run_program(source)),
+        #[cfg(feature = "serde")]
+        Command::EmitTokensJson(path) => {
+            let (json, errors) = tokens_json(read_file(&path));
+            // Errors go to stderr so stdout stays valid JSON
+            for err in &errors {
+                eprintln!("{}", err);
+            }
+            println!("{}", json);
+            exit(if errors.is_empty() { 0 } else { 65 });
         }
-        std::cmp::Ordering::Equal => run_file(&args[1]),
-        std::cmp::Ordering::Less => run_prompt(),
-    }
-}
-
-fn run_file(path: &str) {
-    let bytes = std::fs::read(path).ok();
-    match bytes {
-        Some(bytes) => {
-            let source = String::from_utf8(bytes).ok();
-            match source {
-                Some(source) => {
-                    run(source);
-                    if *HAD_ERROR.lock().unwrap() {
-                        exit(65);
-                    }
-                },
-                None => {
-                    println!("Error reading file: {}", path);
-                    exit(66);
+        #[cfg(feature = "serde")]
+        Command::EmitAst(path, output) => match crate::cache::emit_ast(read_file(&path)) {
+            Ok(serialized) => {
+                if std::fs::write(&output, serialized).is_err() {
+                    println!("Error writing file: {}", output);
+                    exit(74);
                 }
+                exit(0);
+            }
+            Err(err) => {
+                println!("{}", err);
+                exit(65);
             }
         },
+        #[cfg(feature = "serde")]
+        Command::RunAst(path) => {
+            let statements = match crate::cache::load_ast(&read_file(&path)) {
+                Ok(statements) => statements,
+                Err(err) => {
+                    println!("{}", err);
+                    exit(65);
+                }
+            };
+            if let Err(err) = new_interpreter().interpret(statements) {
+                println!("{}", err);
+                exit(65);
+            }
+            exit(0);
+        }
+        Command::Version => {
+            println!("{}", version());
+            exit(0);
+        }
+        Command::Help => {
+            println!("{}", help());
+            exit(0);
+        }
+        Command::Usage => {
+            println!("{}", usage());
+            exit(64);
+        }
+    }
+}
+
+pub fn version() -> String {
+    format!("rlox {}", env!("CARGO_PKG_VERSION"))
+}
+
+fn read_file(path: &str) -> String {
+    let source = std::fs::read(path).ok().and_then(|bytes| String::from_utf8(bytes).ok());
+    match source {
+        Some(source) => source,
         None => {
             println!("Error reading file: {}", path);
             exit(66);
@@ -45,21 +269,130 @@ fn run_file(path: &str) {
     }
 }
 
+// Runs a whole program and returns the exit code, 65 if any error was reported
+fn run_program(source: String) -> i32 {
+    HAD_ERROR.with(|had_error| had_error.set(false));
+    run(&mut new_interpreter(), source);
+    if HAD_ERROR.with(|had_error| had_error.get()) {
+        65
+    } else {
+        0
+    }
+}
+
+fn run_reader<R: Read>(mut reader: R) -> i32 {
+    let mut source = String::new();
+    match reader.read_to_string(&mut source) {
+        Ok(_) => run_program(source),
+        Err(_) => {
+            println!("Error reading standard input");
+            66
+        }
+    }
+}
+
+// Stops after the given stage, printing the tokens or the AST it produced
+fn run_stage(source: String, stage: Stage) -> i32 {
+    HAD_ERROR.with(|had_error| had_error.set(false));
+    if LINT.with(|lint| lint.get()) {
+        for warning in Scanner::lint(&source) {
+            eprintln!("{}", warning);
+        }
+    }
+    match stage {
+        Stage::Scan => {
+            let mut scanner = new_scanner(source);
+            let tokens = scanner.scan_tokens();
+            report_scan_errors(&scanner);
+            for token in tokens {
+                println!("{}", token);
+            }
+        }
+        Stage::Parse => {
+            let mut scanner = new_scanner(source);
+            let tokens = scanner.scan_tokens();
+            report_scan_errors(&scanner);
+            let mut parser = new_parser(tokens);
+            // The parser reports its own errors
+            if let Ok(statements) = parser.parse() {
+                for statement in statements {
+                    println!("{}", statement);
+                }
+            }
+        }
+        Stage::Run => return run_program(source),
+    }
+    if HAD_ERROR.with(|had_error| had_error.get()) {
+        65
+    } else {
+        0
+    }
+}
+
+// One object per token with its type tag, literal value if any, lexeme, line,
+// starting column and span, for editor highlighting
+#[cfg(feature = "serde")]
+fn tokens_json(source: String) -> (String, Vec<crate::error::RloxError>) {
+    #[derive(serde::Serialize)]
+    struct TokenJson<'a> {
+        #[serde(flatten)]
+        token: &'a crate::token::Token,
+        column: usize,
+        span: crate::scanner::Span,
+    }
+
+    let mut scanner = new_scanner(source);
+    let tokens = scanner.scan_tokens();
+    let json = tokens
+        .iter()
+        .zip(scanner.spans())
+        .map(|(token, span)| TokenJson { token, column: span.start_column, span: *span })
+        .collect::<Vec<TokenJson>>();
+    (serde_json::to_string(&json).unwrap(), scanner.errors().to_vec())
+}
+
+#[cfg(not(feature = "repl"))]
 fn run_prompt() {
     let reader = std::io::stdin();
+    let mut interpreter = new_interpreter();
+    if prompt_loop(reader.lock(), |line| run(&mut interpreter, line)).is_err() {
+        println!("Error reading line");
+        exit(66);
+    }
+    println!("Bye!");
+    exit(0);
+}
+
+#[cfg(feature = "repl")]
+fn run_prompt() {
+    use rustyline::error::ReadlineError;
+
+    let mut editor = match rustyline::DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(_) => {
+            println!("Error starting line editor");
+            exit(66);
+        }
+    };
+    let history = history_path();
+    if let Some(path) = &history {
+        // A missing history file just means a first session
+        editor.load_history(path).ok();
+    }
+
+    // One interpreter for the whole session, so globals and the --seed sequence carry over
+    let mut interpreter = new_interpreter();
     loop {
-        print!("> ");
-        stdout().flush().ok();
-        let mut line = String::new();
-        let res = reader.read_line(&mut line);
-        match res {
-            Ok(_) => {
+        match editor.readline("> ") {
+            Ok(line) => {
                 if line.trim() == "quit" {
                     break;
                 }
-                run(line);
-                *HAD_ERROR.lock().unwrap() = false;
-            },
+                record_history(&mut editor, history.as_deref(), &line);
+                run(&mut interpreter, line);
+                HAD_ERROR.with(|had_error| had_error.set(false));
+            }
+            Err(ReadlineError::Eof | ReadlineError::Interrupted) => break,
             Err(_) => {
                 println!("Error reading line");
                 exit(66);
@@ -70,51 +403,467 @@ fn run_prompt() {
     exit(0);
 }
 
-fn run(source: String) {
-    let mut scanner = Scanner::new(source);
-    let tokens = scanner.scan_tokens();
-    let mut parser = Parser::new(tokens.clone());
-    let statements = parser.parse();
-    
-    match statements {
-        Ok(statements) => {
-            let mut interpreter = Interpreter::new();
-            match interpreter.interpret(statements) {
-                Ok(_) => {},
-                Err(err) => {
-                    *HAD_ERROR.lock().unwrap() = true;
-                    println!("{}", err);
+#[cfg(feature = "repl")]
+fn history_path() -> Option<std::path::PathBuf> {
+    std::env::var_os("HOME").map(|home| std::path::Path::new(&home).join(".rlox_history"))
+}
+
+// Appends each line as it's entered so history survives a crashed session
+#[cfg(feature = "repl")]
+fn record_history(editor: &mut rustyline::DefaultEditor, path: Option<&std::path::Path>, line: &str) {
+    if line.trim().is_empty() {
+        return;
+    }
+    editor.add_history_entry(line).ok();
+    if let Some(path) = path {
+        editor.append_history(path).ok();
+    }
+}
+
+// Runs each line read until "quit" or end of input. Lines with invalid UTF-8
+// are converted lossily so one bad paste doesn't end the session.
+#[cfg(any(not(feature = "repl"), test))]
+fn prompt_loop<R: BufRead>(mut reader: R, mut run_line: impl FnMut(String)) -> std::io::Result<()> {
+    loop {
+        print!("> ");
+        std::io::stdout().flush().ok();
+        let mut bytes = Vec::new();
+        if reader.read_until(b'\n', &mut bytes)? == 0 {
+            return Ok(());
+        }
+        let line = match String::from_utf8(bytes) {
+            Ok(line) => line,
+            Err(err) => {
+                println!("Warning: line contained invalid UTF-8");
+                String::from_utf8_lossy(err.as_bytes()).into_owned()
+            }
+        };
+        if line.trim() == "quit" {
+            return Ok(());
+        }
+        run_line(line);
+        HAD_ERROR.with(|had_error| had_error.set(false));
+    }
+}
+
+fn run(interpreter: &mut Interpreter, source: String) {
+    let options = Options::from_cli();
+    let mut report_error = |message: String| {
+        println!("{}", message);
+        HAD_ERROR.with(|had_error| had_error.set(true));
+    };
+    match compile(interpreter, source, &options, false, &mut report_error) {
+        Compiled::Program(statements) => {
+            if let Err(err) = interpreter.interpret(statements) {
+                report_error(err.to_string());
+                if let Some(context) = interpreter.error_context() {
+                    println!("{}", context);
                 }
             }
-        },
-        Err(err) => {
-            let mut parser = Parser::new(tokens);
-            let expression = parser.expression();
-            match expression {
-                Ok(expression) => {
-                    let mut interpreter = Interpreter::new();
-                    match interpreter.evaluate_expression(expression) {
-                        Ok(val) => println!("{}", val),
-                        Err(err) => {
-                            *HAD_ERROR.lock().unwrap() = true;
-                            println!("{}", err);
-                        }
-                    }
-                },
-                Err(_) => {
-                    *HAD_ERROR.lock().unwrap() = true;
-                    println!("{}", err);
+        }
+        // The parser has reported its errors, the retry as an expression stays quiet
+        Compiled::Unparsed(tokens) => {
+            let mut parser = options.parser(tokens);
+            parser.quiet = true;
+            if let Ok(expression) = parser.comma() {
+                match interpreter.evaluate_expression(expression) {
+                    Ok(val) => println!("{}", interpreter.display(&val)),
+                    Err(err) => report_error(err.to_string()),
                 }
             }
         }
+        Compiled::Invalid => {}
     }
 }
 
-pub fn error(line: usize, message: &str) {
-    report(line, "", message);
+// What compile made of a source
+enum Compiled {
+    Program(Vec<Stmt>),
+    // The tokens of a source that didn't parse, so the REPL can retry it as an expression
+    Unparsed(Vec<Token>),
+    // It parsed but had scan or resolve errors
+    Invalid,
+}
+
+// Scans, parses, resolves and folds a program, the pipeline the CLI and run_source
+// share. Scan and resolve errors go to `error`, parse errors too when `quiet`,
+// otherwise the parser reports them with their location. Warnings are written to
+// the interpreter's error output.
+fn compile(interpreter: &mut Interpreter, source: String, options: &Options, quiet: bool, error: &mut dyn FnMut(String)) -> Compiled {
+    let mut scanner = options.scanner(source);
+    let tokens = scanner.scan_tokens();
+    for err in scanner.errors() {
+        error(err.to_string());
+    }
+
+    // Parsed even after a scan error so its errors are reported in the same run
+    let mut parser = options.parser(tokens.clone());
+    parser.quiet = quiet;
+    let Ok(statements) = parser.parse() else {
+        if quiet {
+            for err in parser.errors() {
+                error(err.clone());
+            }
+        }
+        return Compiled::Unparsed(tokens);
+    };
+    if !scanner.errors().is_empty() {
+        return Compiled::Invalid;
+    }
+
+    let program = match resolve(statements) {
+        Ok(program) => program,
+        Err(errors) => {
+            for err in errors {
+                error(err.to_string());
+            }
+            return Compiled::Invalid;
+        }
+    };
+    for warning in program.shown_warnings(options.warn) {
+        // A warning that can't be written isn't worth stopping the program for
+        writeln!(interpreter.error_output.borrow_mut(), "{}", warning).ok();
+    }
+    Compiled::Program(interpreter.fold_constants(program.statements))
+}
+
+// The scanner only collects its errors, printing them is up to the CLI
+fn report_scan_errors(scanner: &Scanner) {
+    for err in scanner.errors() {
+        println!("{}", err);
+        HAD_ERROR.with(|had_error| had_error.set(true));
+    }
+}
+
+// What the flags change about running a program. The CLI builds one from its
+// settings, library callers of run_source pass their own.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub strict: bool,
+    pub hash_comments: bool,
+    pub auto_semicolons: bool,
+    // Show the resolver's analysis of likely mistakes along with the other warnings
+    pub warn: bool,
+    pub debug_values: bool,
+    pub seed: Option<u64>,
+    // The variable whose every change is reported on stderr
+    pub watch: Option<String>,
+    // Bound to the global `args` as a list of strings, empty unless given
+    pub args: Vec<String>,
+}
+
+impl Options {
+    // The settings run_cli took from the arguments
+    fn from_cli() -> Options {
+        Options {
+            strict: STRICT.with(|strict| strict.get()),
+            hash_comments: HASH_COMMENTS.with(|hash_comments| hash_comments.get()),
+            auto_semicolons: AUTO_SEMICOLONS.with(|auto_semicolons| auto_semicolons.get()),
+            warn: WARN.with(|warn| warn.get()),
+            debug_values: DEBUG_VALUES.with(|debug_values| debug_values.get()),
+            seed: SEED.with(|seed| seed.get()),
+            watch: WATCH.with(|watch| watch.borrow().clone()),
+            args: SCRIPT_ARGS.with(|args| args.borrow().clone()),
+        }
+    }
+
+    pub fn scanner(&self, source: String) -> Scanner {
+        let mut scanner = Scanner::new(source);
+        scanner.strict = self.strict;
+        scanner.hash_comments = self.hash_comments;
+        scanner
+    }
+
+    pub fn parser(&self, tokens: Vec<Token>) -> Parser {
+        let mut parser = Parser::new(tokens);
+        parser.strict = self.strict;
+        parser.auto_semicolons = self.auto_semicolons;
+        parser
+    }
+
+    pub fn interpreter(&self) -> Interpreter {
+        let mut interpreter = Interpreter::new();
+        if let Some(seed) = self.seed {
+            interpreter.rng = Rng::new(seed);
+        }
+        interpreter.annotate_types = self.debug_values;
+        let args = self.args.iter().map(|arg| Value::String(arg.clone())).collect();
+        interpreter.environment.define(String::from("args"), Value::list(args));
+        if let Some(watched) = self.watch.clone() {
+            interpreter.watcher = Some(Box::new(move |name, value| {
+                if name == watched {
+                    eprintln!("[watch] {} = {}", name, value);
+                }
+            }));
+        }
+        interpreter
+    }
+}
+
+fn new_scanner(source: String) -> Scanner {
+    Options::from_cli().scanner(source)
+}
+
+fn new_parser(tokens: Vec<Token>) -> Parser {
+    Options::from_cli().parser(tokens)
+}
+
+// The CLI runs on a STACK_SIZE thread, so its interpreters allow MAX_CALL_DEPTH calls
+fn new_interpreter() -> Interpreter {
+    let mut interpreter = Options::from_cli().interpreter();
+    interpreter.max_call_depth = crate::interpreter::MAX_CALL_DEPTH;
+    interpreter
+}
+
+// Library entry point: runs a whole program with print output sent to `output` and
+// returns its scan, parse or resolve errors, one per line, or its runtime error. It
+// runs on the caller's stack, so calls nest up to DEFAULT_MAX_CALL_DEPTH.
+pub fn run_source(source: String, options: &Options, output: Rc<RefCell<dyn Write>>) -> Result<(), String> {
+    let mut interpreter = options.interpreter();
+    interpreter.output = output;
+    let mut errors = Vec::new();
+    match compile(&mut interpreter, source, options, true, &mut |message| errors.push(message)) {
+        Compiled::Program(statements) => interpreter.interpret(statements).map_err(|err| err.to_string()),
+        Compiled::Unparsed(_) | Compiled::Invalid => Err(errors.join("\n")),
+    }
 }
 
 pub fn report(line: usize, location: &str, message: &str) {
     println!("[line {}] Error {}: {}", line, location, message);
-    *HAD_ERROR.lock().unwrap() = true;
+    HAD_ERROR.with(|had_error| had_error.set(true));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(parse_args(&args(&["rlox"])), Command::RunPrompt);
+        assert_eq!(parse_args(&args(&["rlox", "script.lox"])), Command::RunFile(String::from("script.lox"), Stage::Run, Vec::new()));
+        assert_eq!(parse_args(&args(&["rlox", "--version"])), Command::Version);
+        assert_eq!(parse_args(&args(&["rlox", "--stdin"])), Command::RunStdin);
+        assert_eq!(parse_args(&args(&["rlox", "-"])), Command::RunStdin);
+        assert_eq!(parse_args(&args(&["rlox", "-e", "print 1;"])), Command::Eval(String::from("print 1;")));
+        assert_eq!(parse_args(&args(&["rlox", "--dump-tokens", "script.lox"])), Command::RunFile(String::from("script.lox"), Stage::Scan, Vec::new()));
+        assert_eq!(parse_args(&args(&["rlox", "--scan-only", "script.lox"])), Command::RunFile(String::from("script.lox"), Stage::Scan, Vec::new()));
+        assert_eq!(parse_args(&args(&["rlox", "--parse-only", "script.lox"])), Command::RunFile(String::from("script.lox"), Stage::Parse, Vec::new()));
+    }
+
+    #[test]
+    fn test_help() {
+        assert_eq!(parse_args(&args(&["rlox", "--help"])), Command::Help);
+        assert_eq!(parse_args(&args(&["rlox", "-h"])), Command::Help);
+
+        let help = help();
+        assert!(help.starts_with(&usage()));
+        for flag in ["--warn", "--strict-lox", "--seed N", "--watch NAME", "--version", "-e source", "--scan-only", "--parse-only", "script"] {
+            assert!(help.contains(flag), "{} missing from help", flag);
+        }
+    }
+
+    #[test]
+    fn test_help_covers_every_parsed_flag() {
+        let help = help();
+        for (flag, _, _) in SWITCHES {
+            assert!(help.contains(&format!("[{}]", flag)), "{} missing from help", flag);
+        }
+        for flag in ["--seed", "--watch"] {
+            let mut arguments = args(&["rlox", flag, "x"]);
+            assert!(take_option(&mut arguments, flag).unwrap().is_some());
+            assert!(help.contains(flag), "{} missing from help", flag);
+        }
+
+        let mut commands = vec![
+            args(&["rlox", "--version"]),
+            args(&["rlox", "--help"]),
+            args(&["rlox", "-h"]),
+            args(&["rlox", "--stdin"]),
+            args(&["rlox", "-"]),
+            args(&["rlox", "-e", "print 1;"]),
+            args(&["rlox", "--scan-only", "a.lox"]),
+            args(&["rlox", "--dump-tokens", "a.lox"]),
+            args(&["rlox", "--parse-only", "a.lox"]),
+        ];
+        if cfg!(feature = "serde") {
+            commands.push(args(&["rlox", "--emit-tokens-json", "a.lox"]));
+            commands.push(args(&["rlox", "--emit-ast", "out.ast", "a.lox"]));
+            commands.push(args(&["rlox", "--run-ast", "out.ast"]));
+        }
+        for arguments in commands {
+            assert_ne!(parse_args(&arguments), Command::Usage, "{:?}", arguments);
+            assert!(help.contains(&format!("{} ", arguments[1])), "{} missing from help", arguments[1]);
+        }
+    }
+
+    #[test]
+    fn test_parse_args_usage() {
+        assert_eq!(parse_args(&args(&[])), Command::RunPrompt);
+        assert_eq!(parse_args(&args(&["rlox", "-e"])), Command::Usage);
+        assert_eq!(parse_args(&args(&["rlox", "--dump-tokens"])), Command::Usage);
+        assert_eq!(parse_args(&args(&["rlox", "--unknown"])), Command::Usage);
+    }
+
+    #[test]
+    fn test_take_seed() {
+        let mut arguments = args(&["rlox", "--seed", "7", "script.lox"]);
+        assert_eq!(take_seed(&mut arguments), Ok(Some(7)));
+        assert_eq!(arguments, args(&["rlox", "script.lox"]));
+
+        let mut arguments = args(&["rlox", "script.lox"]);
+        assert_eq!(take_seed(&mut arguments), Ok(None));
+        assert_eq!(take_seed(&mut args(&["rlox", "--seed", "x"])), Err(()));
+        assert_eq!(take_seed(&mut args(&["rlox", "--seed"])), Err(()));
+    }
+
+    #[test]
+    fn test_take_flag() {
+        let mut arguments = args(&["rlox", "--warn", "script.lox"]);
+        assert!(take_flag(&mut arguments, "--warn"));
+        assert_eq!(arguments, args(&["rlox", "script.lox"]));
+        assert!(!take_flag(&mut arguments, "--warn"));
+    }
+
+    #[test]
+    fn test_run_reader_exit_codes() {
+        assert_eq!(run_reader(&b"var a = 1; print a;"[..]), 0);
+        assert_eq!(run_reader(&b"print 1 / 0;"[..]), 65);
+        assert_eq!(run_reader(&b"print 1"[..]), 65);
+        assert_eq!(run_reader(&b"print 2;"[..]), 0);
+        assert_eq!(run_reader(&b"{ var a = a; }"[..]), 65);
+        assert_eq!(run_reader(&b"\xff"[..]), 66);
+    }
+
+    #[test]
+    fn test_run_stage_stops_early() {
+        let runtime_error = String::from("print 1 / 0;");
+        assert_eq!(run_stage(runtime_error.clone(), Stage::Scan), 0);
+        assert_eq!(run_stage(runtime_error.clone(), Stage::Parse), 0);
+        assert_eq!(run_stage(runtime_error, Stage::Run), 65);
+
+        assert_eq!(run_stage(String::from("print @;"), Stage::Scan), 65);
+        assert_eq!(run_stage(String::from("print 1"), Stage::Scan), 0);
+        assert_eq!(run_stage(String::from("print 1"), Stage::Parse), 65);
+    }
+
+    #[test]
+    fn test_prompt_loop_survives_invalid_utf8() {
+        let input: &[u8] = b"print 1;\n\xff\xfe;\nprint 2;\nquit\nprint 3;\n";
+        let mut lines = Vec::new();
+        assert!(prompt_loop(input, |line| lines.push(line)).is_ok());
+        assert_eq!(lines, vec![
+            String::from("print 1;\n"),
+            String::from("\u{FFFD}\u{FFFD};\n"),
+            String::from("print 2;\n"),
+        ]);
+    }
+
+    #[test]
+    fn test_prompt_session_keeps_seeded_rng() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = new_interpreter();
+        interpreter.rng = Rng::new(42);
+        interpreter.output = output.clone();
+        let input: &[u8] = b"print rand();\nprint rand();\n";
+        assert!(prompt_loop(input, |line| run(&mut interpreter, line)).is_ok());
+
+        let output = String::from_utf8(output.borrow().clone()).unwrap();
+        let values = output.lines().collect::<Vec<&str>>();
+        assert_eq!(values.len(), 2);
+        assert_ne!(values[0], values[1]);
+    }
+
+    #[test]
+    fn test_run_source_options() {
+        let run = |source: &str, options: &Options| {
+            let output = Rc::new(RefCell::new(Vec::new()));
+            let result = run_source(String::from(source), options, output.clone());
+            result.map(|_| String::from_utf8(output.borrow().clone()).unwrap())
+        };
+        let defaults = Options::default();
+        assert_eq!(run("var a; a = 1, a = 2; print a;", &defaults), Ok(String::from("2\n")));
+        assert_eq!(run("var a; a = 1, a = 2; print a;", &Options { strict: true, ..Options::default() }), Err(String::from("The comma operator is not part of standard Lox.")));
+        assert_eq!(run("# note\nprint 1;", &Options { hash_comments: true, ..Options::default() }), Ok(String::from("1\n")));
+        assert_eq!(run("print 1\nprint 2", &Options { auto_semicolons: true, ..Options::default() }), Ok(String::from("1\n2\n")));
+        assert_eq!(run("print 3;", &Options { debug_values: true, ..Options::default() }), Ok(String::from("number: 3\n")));
+
+        let seeded = Options { seed: Some(7), ..Options::default() };
+        assert_eq!(run("print rand();", &seeded), run("print rand();", &seeded));
+
+        // Every error of the failing stage, not just the first
+        assert_eq!(run("var = 1; print;\n1 +;", &defaults), Err(String::from("Expect variable name.\nExpect expression.")));
+        assert_eq!(run("print @;", &defaults), Err(String::from("[line 1] Error: Unexpected character: @")));
+    }
+
+    #[test]
+    fn test_script_args() {
+        let command = parse_args(&args(&["rlox", "script.lox", "foo", "bar"]));
+        assert_eq!(command, Command::RunFile(String::from("script.lox"), Stage::Run, args(&["foo", "bar"])));
+        let Command::RunFile(_, _, script_args) = command else { unreachable!() };
+
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let options = Options { args: script_args, ..Options::default() };
+        assert_eq!(run_source(String::from("print args; print args[1];"), &options, output.clone()), Ok(()));
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "[\"foo\", \"bar\"]\nbar\n");
+
+        // Always defined, so a script can check for arguments without failing
+        let output = Rc::new(RefCell::new(Vec::new()));
+        assert_eq!(run_source(String::from("print args.length();"), &Options::default(), output.clone()), Ok(()));
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "0\n");
+        // Only flags may follow a flag's value, script arguments need a script
+        assert_eq!(parse_args(&args(&["rlox", "--parse-only", "a.lox", "b.lox"])), Command::Usage);
+    }
+
+    #[test]
+    fn test_prompt_loop_stops_at_end_of_input() {
+        let input: &[u8] = b"print 1;";
+        let mut lines = Vec::new();
+        assert!(prompt_loop(input, |line| lines.push(line)).is_ok());
+        assert_eq!(lines, vec![String::from("print 1;")]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_parse_ast_args() {
+        assert_eq!(parse_args(&args(&["rlox", "--emit-ast", "out.ast", "script.lox"])), Command::EmitAst(String::from("script.lox"), String::from("out.ast")));
+        assert_eq!(parse_args(&args(&["rlox", "--run-ast", "out.ast"])), Command::RunAst(String::from("out.ast")));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_tokens_json() {
+        let (json, errors) = tokens_json(String::from("var x = 1.5;"));
+        assert!(errors.is_empty());
+        let tokens: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(tokens[0], serde_json::json!({"type": "Var", "lexeme": "var", "line": 1, "column": 1, "span": {"start_line": 1, "start_column": 1, "end_line": 1, "end_column": 3}}));
+        assert_eq!(tokens[3], serde_json::json!({"type": "Number", "value": 1.5, "lexeme": "1.5", "line": 1, "column": 9, "span": {"start_line": 1, "start_column": 9, "end_line": 1, "end_column": 11}}));
+        assert_eq!(tokens.as_array().unwrap().len(), 6);
+    }
+
+    #[cfg(feature = "repl")]
+    #[test]
+    fn test_record_history_appends() {
+        let path = std::env::temp_dir().join(format!("rlox_history_{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut editor = rustyline::DefaultEditor::new().unwrap();
+        record_history(&mut editor, Some(&path), "var a = 1;");
+        record_history(&mut editor, Some(&path), "   ");
+        record_history(&mut editor, Some(&path), "print a;");
+
+        let mut reloaded = rustyline::DefaultEditor::new().unwrap();
+        reloaded.load_history(&path).unwrap();
+        let entries = reloaded.history().iter().cloned().collect::<Vec<String>>();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(entries, vec![String::from("var a = 1;"), String::from("print a;")]);
+    }
+
+    #[test]
+    fn test_version() {
+        assert_eq!(parse_args(&args(&["rlox", "--version"])), Command::Version);
+        assert_eq!(version(), concat!("rlox ", env!("CARGO_PKG_VERSION")));
+    }
 }
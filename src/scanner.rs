@@ -1,35 +1,226 @@
 use crate::token::Token;
 use crate::tokentype::TokenType;
-use crate::rlox;
+use crate::error::RloxError;
+
+pub const DEFAULT_MAX_TOKEN_LENGTH: usize = 1024;
+
+// Where a token sits in the source, from its first character to its last one,
+// all 1-based and inclusive. A string can start and end on different lines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Span {
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Span {
+    // A span within a single line
+    pub fn on_line(line: usize, start_column: usize, end_column: usize) -> Span {
+        Span { start_line: line, start_column, end_line: line, end_column }
+    }
+
+    fn shifted(self, lines: isize) -> Span {
+        Span {
+            start_line: (self.start_line as isize + lines) as usize,
+            end_line: (self.end_line as isize + lines) as usize,
+            ..self
+        }
+    }
+}
+
+// Replaces the characters in start..end (character offsets) with `text`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
 
 pub struct Scanner {
-    source: String,
+    // Indexed by character so non-ASCII input can't split a UTF-8 sequence
+    source: Vec<char>,
     tokens: Vec<Token>,
+    // Span of each token, kept alongside so tokens still compare by content
+    spans: Vec<Span>,
+    // Character offset where each token starts, for splicing in rescan
+    offsets: Vec<usize>,
     start: usize,
     current: usize,
     line: usize,
+    // Offset of the first character of the current line, for columns
+    line_start: usize,
+    start_line: usize,
+    start_column: usize,
+    errors: Vec<RloxError>,
+    // Longest identifier or number literal accepted, guards against pathological input
+    pub max_token_length: usize,
+    // Only the book's lexical grammar, /* */ comments scan as '/' and '*', .5 as '.'
+    // and 5, and 0o17 as 0 and the identifier o17
+    pub strict: bool,
+    // '#' starts a line comment like '//', otherwise it is an unexpected character
+    pub hash_comments: bool,
 }
 
 impl Scanner {
     pub fn new(source: String) -> Scanner {
-        Scanner {
-            source,
+        let mut scanner = Scanner {
+            source: source.chars().collect(),
             tokens: Vec::new(),
+            spans: Vec::new(),
+            offsets: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            start_line: 1,
+            start_column: 1,
+            errors: Vec::new(),
+            max_token_length: DEFAULT_MAX_TOKEN_LENGTH,
+            strict: false,
+            hash_comments: false,
+        };
+        scanner.current = scanner.shebang_length();
+        scanner
+    }
+
+    // A leading "#!" line lets scripts run as executables, it is skipped up to its newline
+    fn shebang_length(&self) -> usize {
+        if self.source.starts_with(&['#', '!']) {
+            self.source.iter().position(|c| *c == '\n').unwrap_or(self.source.len())
+        } else {
+            0
         }
     }
 
+    // One-shot scan that hands back the errors instead of relying on the global error flag
+    pub fn tokenize(source: &str) -> (Vec<Token>, Vec<RloxError>) {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens();
+        (tokens, scanner.errors)
+    }
+
+    // Style warnings for a linter, found from the raw lines so tokenizing is unaffected
+    pub fn lint(source: &str) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            if line.ends_with([' ', '\t']) {
+                warnings.push(format!("[line {}] Warning: trailing whitespace.", index + 1));
+            }
+            let indentation = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+            if indentation.contains(' ') && indentation.contains('\t') {
+                warnings.push(format!("[line {}] Warning: indentation mixes tabs and spaces.", index + 1));
+            }
+        }
+        warnings
+    }
+
+    pub fn errors(&self) -> &[RloxError] {
+        &self.errors
+    }
+
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
     pub fn scan_tokens(&mut self) -> Vec<Token> {
         while !self.is_at_end() {
-            self.start = self.current;
+            self.start_token();
             self.scan_token();
         }
-        self.tokens.push(Token::new(TokenType::Eof, String::from(""), self.line));
+        self.add_eof();
+        self.tokens.clone()
+    }
+
+    // Applies an edit to an already scanned source and re-tokenizes from the token
+    // before it, reusing the old tokens again once scanning is back in step with them.
+    // Falls back to a full scan if the old tokens came with errors.
+    pub fn rescan(&mut self, edit: TextEdit) -> Vec<Token> {
+        let end = edit.end.min(self.source.len());
+        let start = edit.start.min(end);
+        let old_length = self.source.len();
+        self.source.splice(start..end, edit.text.chars());
+        let edit_end = start + edit.text.chars().count();
+        let delta = self.source.len() as isize - old_length as isize;
+
+        if self.tokens.is_empty() || !self.errors.is_empty() {
+            return self.full_rescan();
+        }
+
+        // The token ending where the edit starts may grow, as in `ab` -> `abc`, so it
+        // is scanned again too. The Eof token always qualifies.
+        let first = (0..self.tokens.len())
+            .find(|&index| self.token_end(index) >= start)
+            .unwrap_or(self.tokens.len() - 1);
+        let restart = if first == 0 { self.shebang_length() } else { self.token_end(first - 1) };
+        let old_tokens = self.tokens.split_off(first);
+        let old_spans = self.spans.split_off(first);
+        let old_offsets = self.offsets.split_off(first);
+
+        self.current = restart;
+        self.line = self.tokens.last().map_or(1, |token| token.line);
+        self.line_start = self.source[..restart].iter().rposition(|c| *c == '\n').map_or(0, |index| index + 1);
+
+        while !self.is_at_end() {
+            self.start_token();
+            let count = self.tokens.len();
+            self.scan_token();
+            if self.tokens.len() == count {
+                continue;
+            }
+
+            // Past the edit on a line it didn't touch, scanning goes exactly as before,
+            // so the old tokens from here on only need their lines and offsets moved
+            let offset = self.offsets[count];
+            if self.line_start < edit_end || offset < self.line_start {
+                continue;
+            }
+            let Some(old) = old_offsets.iter().position(|old_offset| *old_offset as isize + delta == offset as isize) else {
+                continue;
+            };
+            let line_delta = self.tokens[count].line as isize - old_tokens[old].line as isize;
+            if old_tokens[old] != self.tokens[count] || old_spans[old].shifted(line_delta) != self.spans[count] {
+                continue;
+            }
+            self.tokens.truncate(count);
+            self.spans.truncate(count);
+            self.offsets.truncate(count);
+            for (index, token) in old_tokens.into_iter().enumerate().skip(old) {
+                self.tokens.push(Token::new(token.token_type, token.lexeme, (token.line as isize + line_delta) as usize));
+                self.spans.push(old_spans[index].shifted(line_delta));
+                self.offsets.push((old_offsets[index] as isize + delta) as usize);
+            }
+            return self.tokens.clone();
+        }
+
+        self.add_eof();
         self.tokens.clone()
     }
 
+    fn add_eof(&mut self) {
+        let column = self.current - self.line_start + 1;
+        self.tokens.push(Token::new(TokenType::Eof, String::from(""), self.line));
+        self.spans.push(Span::on_line(self.line, column, column));
+        self.offsets.push(self.current);
+    }
+
+    fn full_rescan(&mut self) -> Vec<Token> {
+        self.tokens.clear();
+        self.spans.clear();
+        self.offsets.clear();
+        self.errors.clear();
+        self.start = 0;
+        self.current = self.shebang_length();
+        self.line = 1;
+        self.line_start = 0;
+        self.scan_tokens()
+    }
+
+    fn token_end(&self, index: usize) -> usize {
+        self.offsets[index] + self.tokens[index].lexeme.chars().count()
+    }
+
     pub fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
@@ -42,16 +233,31 @@ impl Scanner {
             ')' => self.add_token(TokenType::RightParen),
             '{' => self.add_token(TokenType::LeftBrace),
             '}' => self.add_token(TokenType::RightBrace),
+            '[' => self.add_token(TokenType::LeftBracket),
+            ']' => self.add_token(TokenType::RightBracket),
             ',' => self.add_token(TokenType::Comma),
+            // .5 is a number unless it follows something with properties, as in a.b
+            '.' if !self.strict && self.peek().is_ascii_digit() && !self.follows_operand() => self.fraction(),
             '.' => self.add_token(TokenType::Dot),
             '-' => self.add_token(TokenType::Minus),
             '+' => self.add_token(TokenType::Plus),
             ';' => self.add_token(TokenType::Semicolon),
-            '*' => self.add_token(TokenType::Star),
             ':' => self.add_token(TokenType::Colon),
             '?' => self.add_token(TokenType::QuestionMark),
+            '&' => self.add_token(TokenType::Ampersand),
+            '|' => self.add_token(TokenType::Pipe),
+            '^' => self.add_token(TokenType::Caret),
 
             // One or two character tokens
+            '*' => {
+                let token_type = if self.match_char('*') {
+                    TokenType::StarStar
+                } else {
+                    TokenType::Star
+                };
+                self.add_token(token_type);
+            },
+
             '!' => {
                 let token_type = if self.match_char('=') {
                     TokenType::BangEqual
@@ -73,6 +279,8 @@ impl Scanner {
             '>' => {
                 let token_type = if self.match_char('=') {
                     TokenType::GreaterEqual
+                } else if self.match_char('>') {
+                    TokenType::GreaterGreater
                 } else {
                     TokenType::Greater
                 };
@@ -82,6 +290,8 @@ impl Scanner {
             '<' => {
                 let token_type = if self.match_char('=') {
                     TokenType::LessEqual
+                } else if self.match_char('<') {
+                    TokenType::LessLess
                 } else {
                     TokenType::Less
                 };
@@ -93,15 +303,14 @@ impl Scanner {
                     while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
-                } else if self.match_char('*') {
+                } else if !self.strict && self.match_char('*') {
                     while self.peek() != '*' && self.peek_next() != '/' && !self.is_at_end() {
-                        if self.peek() == '\n' {
-                            self.line += 1;
+                        if self.advance() == '\n' {
+                            self.new_line();
                         }
-                        self.advance();
                     }
                     if self.is_at_end() {
-                        rlox::error(self.line, "Unterminated block comment");
+                        self.error("Unterminated block comment");
                         return;
                     }
                     self.advance();
@@ -111,11 +320,17 @@ impl Scanner {
                 }
             }
 
-            // Ignore whitespace
-            ' ' | '\r' | '\t' => (),
+            '#' if self.hash_comments => {
+                while self.peek() != '\n' && !self.is_at_end() {
+                    self.advance();
+                }
+            }
 
             // Newline increases line number and is ignored
-            '\n' => self.line += 1,
+            '\n' => self.new_line(),
+
+            // Ignore any other whitespace, form feeds and non-breaking spaces included
+            c if c.is_whitespace() => (),
 
             // String literals
             '"' => self.string(),
@@ -126,28 +341,74 @@ impl Scanner {
                 } else if c.is_ascii_alphabetic() || c == '_' {
                     self.identifier();
                 } else {
-                    rlox::error(self.line, format!("Unexpected character: {}", c).as_str())
+                    self.unexpected_characters();
                 }
             }
         }
     }
 
+    // A run of garbage is reported once instead of once per character
+    fn unexpected_characters(&mut self) {
+        while !self.is_at_end() && !self.starts_token(self.peek()) {
+            self.advance();
+        }
+
+        let text = self.lexeme();
+        if text.chars().count() == 1 {
+            self.error(format!("Unexpected character: {}", text).as_str());
+        } else {
+            self.error(format!("Unexpected characters: {}", text).as_str());
+        }
+    }
+
+    // Characters scan_token knows how to handle, keep in sync with its match arms
+    fn starts_token(&self, c: char) -> bool {
+        matches!(c,
+            '(' | ')' | '{' | '}' | ',' | '.' | '-' | '+' | ';' | '*' | ':' | '?'
+            | '!' | '=' | '>' | '<' | '/' | '"' | '_')
+            || c.is_whitespace()
+            || c.is_ascii_alphanumeric()
+            || (c == '#' && self.hash_comments)
+    }
+
     fn advance(&mut self) -> char {
-        let c = self.source.chars().nth(self.current).unwrap();
+        let c = self.source[self.current];
         self.current += 1;
         c
     }
 
     fn add_token(&mut self, token_type: TokenType) {
-        let text = self.source[self.start..self.current].to_string();
+        let text = self.lexeme();
         self.tokens.push(Token::new(token_type, text, self.line));
+        self.spans.push(self.span());
+        self.offsets.push(self.start);
+    }
+
+    fn start_token(&mut self) {
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.start - self.line_start + 1;
+    }
+
+    // From the token's first character to the last one consumed
+    fn span(&self) -> Span {
+        Span {
+            start_line: self.start_line,
+            start_column: self.start_column,
+            end_line: self.line,
+            end_column: self.current - self.line_start,
+        }
+    }
+
+    fn lexeme(&self) -> String {
+        self.source[self.start..self.current].iter().collect()
     }
 
     fn match_char(&mut self, expected: char) -> bool {
         if self.is_at_end() {
             return false;
         }
-        if self.source.chars().nth(self.current).unwrap() != expected {
+        if self.source[self.current] != expected {
             return false;
         }
         self.current += 1;
@@ -158,28 +419,43 @@ impl Scanner {
         if self.is_at_end() {
             return '\0';
         }
-        self.source.chars().nth(self.current).unwrap()
+        self.source[self.current]
     }
 
     fn string(&mut self) {
+        let mut value = String::new();
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+            // A backslash right before a newline continues the string without embedding the newline
+            if self.peek() == '\\' && self.peek_next() == '\n' {
+                self.advance();
+                self.advance();
+                self.new_line();
+                continue;
             }
-            self.advance();
+            let c = self.advance();
+            if c == '\n' {
+                self.new_line();
+            }
+            value.push(c);
         }
 
         if self.is_at_end() {
-            rlox::error(self.line, "Unterminated string");
+            self.error("Unterminated string");
             return;
         }
 
         self.advance();
-        let value = self.source[self.start + 1..self.current - 1].to_string();
         self.add_token(TokenType::String(value));
     }
 
     fn number(&mut self) {
+        // Radix prefixes, only octal for now
+        if !self.strict && self.source[self.start] == '0' && self.current == self.start + 1 && self.peek() == 'o' {
+            self.advance();
+            self.radix_number(8, "octal");
+            return;
+        }
+
         while self.peek().is_ascii_digit() {
             self.advance();
         }
@@ -191,15 +467,104 @@ impl Scanner {
             }
         }
 
-        let value = self.source[self.start..self.current].parse::<f64>().unwrap();
-        self.add_token(TokenType::Number(value));
+        if self.malformed_number() {
+            return;
+        }
+        self.add_number();
+    }
+
+    // A dot right after a number only makes sense before a method name, as in 1.5.floor(),
+    // so 1., 1..2 and 1.2.3 are reported as one bad literal
+    fn malformed_number(&mut self) -> bool {
+        if self.peek() != '.' || self.peek_next().is_ascii_alphabetic() || self.peek_next() == '_' {
+            return false;
+        }
+        while self.peek() == '.' || self.peek().is_ascii_digit() {
+            self.advance();
+        }
+        self.error("Malformed number literal.");
+        true
+    }
+
+    // Integer literal after a prefix like 0o, already consumed
+    fn radix_number(&mut self, radix: u32, name: &str) {
+        let digits_start = self.current;
+        // Take every alphanumeric so 0o19 is one bad literal rather than 0o1 followed by 9
+        while self.peek().is_ascii_alphanumeric() || self.peek() == '_' {
+            self.advance();
+        }
+
+        if self.token_too_long() {
+            return;
+        }
+
+        let digits = self.source[digits_start..self.current].iter().collect::<String>();
+        match u64::from_str_radix(&digits, radix) {
+            Ok(value) => match i64::try_from(value) {
+                Ok(int) => self.add_token(TokenType::Int(int)),
+                _ => self.add_token(TokenType::Number(value as f64)),
+            },
+            _ => self.error(format!("Invalid {} literal: {}", name, self.lexeme()).as_str()),
+        }
+    }
+
+    // Leading-dot literal, the '.' is already consumed
+    fn fraction(&mut self) {
+        while self.peek().is_ascii_digit() {
+            self.advance();
+        }
+
+        if self.malformed_number() {
+            return;
+        }
+        self.add_number();
+    }
+
+    fn add_number(&mut self) {
+        if self.token_too_long() {
+            return;
+        }
+
+        // Without a dot it's an Int, unless it's too big for one
+        let lexeme = self.lexeme();
+        match lexeme.parse::<i64>() {
+            Ok(int) => self.add_token(TokenType::Int(int)),
+            _ => self.add_token(TokenType::Number(lexeme.parse::<f64>().unwrap())),
+        }
+    }
+
+    fn follows_operand(&self) -> bool {
+        matches!(
+            self.tokens.last().map(|token| &token.token_type),
+            Some(TokenType::Identifier(_) | TokenType::RightParen | TokenType::RightBracket | TokenType::This | TokenType::Super
+                | TokenType::String(_) | TokenType::Number(_) | TokenType::Int(_))
+        )
     }
 
     fn peek_next(&self) -> char {
         if self.current + 1 >= self.source.len() {
             return '\0';
         }
-        self.source.chars().nth(self.current + 1).unwrap()
+        self.source[self.current + 1]
+    }
+
+    // Call right after consuming a newline
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
+    }
+
+    // The span covers the whole token, as far as it was consumed
+    fn error(&mut self, message: &str) {
+        self.errors.push(RloxError::Scan(self.line, self.span(), message.to_string()));
+    }
+
+    fn token_too_long(&mut self) -> bool {
+        if self.current - self.start > self.max_token_length {
+            self.error("Token too long.");
+            return true;
+        }
+        false
     }
 
     fn identifier(&mut self) {
@@ -207,11 +572,19 @@ impl Scanner {
             self.advance();
         }
 
-        let text = self.source[self.start..self.current].to_string();
+        if self.token_too_long() {
+            return;
+        }
+
+        let text = self.lexeme();
         // Here we match the identifier against the reserved words
         let token_type = match text.as_str() {
             "and" => TokenType::And,
+            "assert" => TokenType::Assert,
+            "break" => TokenType::Break,
+            "catch" => TokenType::Catch,
             "class" => TokenType::Class,
+            "continue" => TokenType::Continue,
             "else" => TokenType::Else,
             "false" => TokenType::False,
             "for" => TokenType::For,
@@ -223,7 +596,9 @@ impl Scanner {
             "return" => TokenType::Return,
             "super" => TokenType::Super,
             "this" => TokenType::This,
+            "throw" => TokenType::Throw,
             "true" => TokenType::True,
+            "try" => TokenType::Try,
             "var" => TokenType::Var,
             "while" => TokenType::While,
             _ => TokenType::Identifier(text),
@@ -236,6 +611,54 @@ impl Scanner {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_rescan_matches_full_scan() {
+        let source = "var a = 1;\nprint a + 2; // two\n\nprint \"x\ny\" + .5;\n{ print a; }";
+        let edits = [
+            TextEdit { start: 4, end: 5, text: String::from("abc") },
+            TextEdit { start: 5, end: 5, text: String::from("c") },
+            TextEdit { start: 12, end: 12, text: String::from("\n\n") },
+            TextEdit { start: 0, end: 0, text: String::from("/* ") },
+            TextEdit { start: 20, end: 22, text: String::new() },
+            TextEdit { start: 33, end: 33, text: String::from("\"") },
+            TextEdit { start: 52, end: 53, text: String::from("b") },
+        ];
+        for edit in edits {
+            let mut edited = source.chars().collect::<Vec<char>>();
+            edited.splice(edit.start..edit.end, edit.text.chars());
+            let mut full = Scanner::new(edited.into_iter().collect());
+            let expected = full.scan_tokens();
+
+            let mut scanner = Scanner::new(String::from(source));
+            scanner.scan_tokens();
+            let tokens = scanner.rescan(edit.clone());
+            assert_eq!(tokens, expected, "{:?}", edit);
+            assert_eq!(tokens.iter().map(|token| token.line).collect::<Vec<usize>>(), expected.iter().map(|token| token.line).collect::<Vec<usize>>(), "{:?}", edit);
+            assert_eq!(scanner.spans(), full.spans(), "{:?}", edit);
+            assert_eq!(scanner.errors(), full.errors(), "{:?}", edit);
+        }
+
+        // Offsets stay right for the next edit
+        let mut scanner = Scanner::new(String::from("var a = 1;\nprint a;"));
+        scanner.scan_tokens();
+        scanner.rescan(TextEdit { start: 0, end: 0, text: String::from("print 0;\n") });
+        let tokens = scanner.rescan(TextEdit { start: 26, end: 27, text: String::from("bc") });
+        assert_eq!(tokens, Scanner::new(String::from("print 0;\nvar a = 1;\nprint bc;")).scan_tokens());
+        assert_eq!(tokens[8].line, 3);
+    }
+
+    #[test]
+    fn test_lint() {
+        let source = "var a = 1;  \n{\n \tprint a;\n\tprint a;\n}\t";
+        assert_eq!(Scanner::lint(source), vec![
+            String::from("[line 1] Warning: trailing whitespace."),
+            String::from("[line 3] Warning: indentation mixes tabs and spaces."),
+            String::from("[line 5] Warning: trailing whitespace."),
+        ]);
+        assert!(Scanner::lint("print 1;\n\tprint 2;\n").is_empty());
+        assert_eq!(Scanner::tokenize(source).0.len(), Scanner::tokenize("var a = 1;{print a;print a;}").0.len());
+    }
+
     #[test]
     fn test_single_char_tokens() {
         let mut scanner = Scanner::new(String::from("(){},.-+;*/:?"));
@@ -257,6 +680,25 @@ mod tests {
         assert_eq!(tokens[13].token_type, TokenType::Eof);
     }
 
+    #[test]
+    fn test_ternary_tokens() {
+        let (tokens, errors) = Scanner::tokenize("5 ? 1 : 2");
+        assert!(errors.is_empty());
+        let types = tokens.into_iter().map(|token| token.token_type).collect::<Vec<TokenType>>();
+        assert_eq!(types, vec![
+            TokenType::Int(5),
+            TokenType::QuestionMark,
+            TokenType::Int(1),
+            TokenType::Colon,
+            TokenType::Int(2),
+            TokenType::Eof,
+        ]);
+
+        let (tokens, _) = Scanner::tokenize("a?b:c");
+        assert_eq!(tokens[1].lexeme, "?");
+        assert_eq!(tokens[3].lexeme, ":");
+    }
+
     #[test]
     fn test_one_or_two_char_tokens() {
         let mut scanner = Scanner::new(String::from("!= == >= <= < >"));
@@ -269,6 +711,12 @@ mod tests {
         assert_eq!(tokens[4].token_type, TokenType::Less);
         assert_eq!(tokens[5].token_type, TokenType::Greater);
         assert_eq!(tokens[6].token_type, TokenType::Eof);
+
+        let types = Scanner::new(String::from("& | ^ << >> <<= >>=")).scan_tokens().into_iter().map(|token| token.token_type).collect::<Vec<_>>();
+        assert_eq!(types, vec![
+            TokenType::Ampersand, TokenType::Pipe, TokenType::Caret, TokenType::LessLess, TokenType::GreaterGreater,
+            TokenType::LessLess, TokenType::Equal, TokenType::GreaterGreater, TokenType::Equal, TokenType::Eof,
+        ]);
     }
 
     #[test]
@@ -288,6 +736,34 @@ mod tests {
         assert_eq!(tokens[1].token_type, TokenType::Eof);
     }
 
+    #[test]
+    fn test_string_line_continuation() {
+        let mut scanner = Scanner::new(String::from("\"a\\\nb\""));
+        let tokens = scanner.scan_tokens();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[0].token_type, TokenType::String(String::from("ab")));
+        assert_eq!(tokens[0].line, 2);
+        assert_eq!(tokens[1].token_type, TokenType::Eof);
+    }
+
+    #[test]
+    fn test_multiline_string_literal() {
+        let mut scanner = Scanner::new(String::from("\"a\nb\""));
+        let tokens = scanner.scan_tokens();
+        assert_eq!(tokens[0].token_type, TokenType::String(String::from("a\nb")));
+        assert_eq!(tokens[0].line, 2);
+    }
+
+    #[test]
+    fn test_non_ascii_characters() {
+        let mut scanner = Scanner::new(String::from("\"héllo\" \u{FFFD}; 1"));
+        let tokens = scanner.scan_tokens();
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(tokens[0].token_type, TokenType::String(String::from("héllo")));
+        assert_eq!(tokens[1].token_type, TokenType::Semicolon);
+        assert_eq!(tokens[2].token_type, TokenType::Int(1));
+    }
+
     #[test]
     fn test_identifier() {
         let mut scanner = Scanner::new(String::from("identifier"));
@@ -299,9 +775,9 @@ mod tests {
 
     #[test]
     fn test_keywords() {
-        let mut scanner = Scanner::new(String::from("and class else false for fun if nil or print return super this true var while"));
+        let mut scanner = Scanner::new(String::from("and class else false for fun if nil or print return super this true var while continue"));
         let tokens = scanner.scan_tokens();
-        assert_eq!(tokens.len(), 17);
+        assert_eq!(tokens.len(), 18);
         assert_eq!(tokens[0].token_type, TokenType::And);
         assert_eq!(tokens[1].token_type, TokenType::Class);
         assert_eq!(tokens[2].token_type, TokenType::Else);
@@ -318,7 +794,8 @@ mod tests {
         assert_eq!(tokens[13].token_type, TokenType::True);
         assert_eq!(tokens[14].token_type, TokenType::Var);
         assert_eq!(tokens[15].token_type, TokenType::While);
-        assert_eq!(tokens[16].token_type, TokenType::Eof);
+        assert_eq!(tokens[16].token_type, TokenType::Continue);
+        assert_eq!(tokens[17].token_type, TokenType::Eof);
     }
 
     #[test]
@@ -326,12 +803,102 @@ mod tests {
         let mut scanner = Scanner::new(String::from("1 2 3.15"));
         let tokens = scanner.scan_tokens();
         assert_eq!(tokens.len(), 4);
-        assert_eq!(tokens[0].token_type, TokenType::Number(1.0));
-        assert_eq!(tokens[1].token_type, TokenType::Number(2.0));
+        assert_eq!(tokens[0].token_type, TokenType::Int(1));
+        assert_eq!(tokens[1].token_type, TokenType::Int(2));
         assert_eq!(tokens[2].token_type, TokenType::Number(3.15)); // Clippy pls stop complaining about 3.14 and PI...
         assert_eq!(tokens[3].token_type, TokenType::Eof);
     }
 
+    #[test]
+    fn test_leading_dot_numbers() {
+        let (tokens, errors) = Scanner::tokenize(".5 == 0.5");
+        assert!(errors.is_empty());
+        assert_eq!(tokens[0].token_type, TokenType::Number(0.5));
+        assert_eq!(tokens[0].lexeme, ".5");
+        assert_eq!(tokens[2].token_type, TokenType::Number(0.5));
+
+        let (tokens, _) = Scanner::tokenize("a.b (x).c 3.field -.25");
+        let types = tokens.into_iter().map(|token| token.token_type).collect::<Vec<TokenType>>();
+        assert_eq!(types, vec![
+            TokenType::Identifier(String::from("a")),
+            TokenType::Dot,
+            TokenType::Identifier(String::from("b")),
+            TokenType::LeftParen,
+            TokenType::Identifier(String::from("x")),
+            TokenType::RightParen,
+            TokenType::Dot,
+            TokenType::Identifier(String::from("c")),
+            TokenType::Int(3),
+            TokenType::Dot,
+            TokenType::Identifier(String::from("field")),
+            TokenType::Minus,
+            TokenType::Number(0.25),
+            TokenType::Eof,
+        ]);
+    }
+
+    #[test]
+    fn test_octal_numbers() {
+        let (tokens, errors) = Scanner::tokenize("0o17 0o0 0o755 017");
+        assert!(errors.is_empty());
+        assert_eq!(tokens[0].token_type, TokenType::Int(15));
+        assert_eq!(tokens[0].lexeme, "0o17");
+        assert_eq!(tokens[1].token_type, TokenType::Int(0));
+        assert_eq!(tokens[2].token_type, TokenType::Int(493));
+        assert_eq!(tokens[3].token_type, TokenType::Int(17));
+
+        let (tokens, errors) = Scanner::tokenize("0o19; 0o;");
+        assert_eq!(errors, vec![
+            RloxError::Scan(1, Span::on_line(1, 1, 4), String::from("Invalid octal literal: 0o19")),
+            RloxError::Scan(1, Span::on_line(1, 7, 8), String::from("Invalid octal literal: 0o")),
+        ]);
+        assert_eq!(tokens.len(), 3);
+    }
+
+    #[test]
+    fn test_token_too_long() {
+        let mut scanner = Scanner::new("a".repeat(DEFAULT_MAX_TOKEN_LENGTH + 1));
+        let tokens = scanner.scan_tokens();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token_type, TokenType::Eof);
+        assert_eq!(scanner.errors().len(), 1);
+    }
+
+    #[test]
+    fn test_custom_max_token_length() {
+        let mut scanner = Scanner::new(String::from("abc 12345 ab"));
+        scanner.max_token_length = 3;
+        let tokens = scanner.scan_tokens();
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[0].token_type, TokenType::Identifier(String::from("abc")));
+        assert_eq!(tokens[1].token_type, TokenType::Identifier(String::from("ab")));
+        assert_eq!(scanner.errors().len(), 1);
+    }
+
+    #[test]
+    fn test_strict_block_comment() {
+        let mut scanner = Scanner::new(String::from("/* c */"));
+        scanner.strict = true;
+        let tokens = scanner.scan_tokens();
+        assert_eq!(tokens[0].token_type, TokenType::Slash);
+        assert_eq!(tokens[1].token_type, TokenType::Star);
+        assert_eq!(tokens[2].token_type, TokenType::Identifier(String::from("c")));
+    }
+
+    #[test]
+    fn test_strict_number_literals() {
+        let mut scanner = Scanner::new(String::from(".5 0o17"));
+        scanner.strict = true;
+        let tokens = scanner.scan_tokens().into_iter().map(|token| token.token_type).collect::<Vec<TokenType>>();
+        assert_eq!(tokens, vec![
+            TokenType::Dot,
+            TokenType::Int(5),
+            TokenType::Int(0),
+            TokenType::Identifier(String::from("o17")),
+            TokenType::Eof,
+        ]);
+    }
+
     #[test]
     fn test_comments() {
         let mut scanner = Scanner::new(String::from("// This is a comment\n// This is another comment"));
@@ -356,16 +923,135 @@ mod tests {
         assert_eq!(tokens[0].token_type, TokenType::Var);
         assert_eq!(tokens[1].token_type, TokenType::Identifier(String::from("a")));
         assert_eq!(tokens[2].token_type, TokenType::Equal);
-        assert_eq!(tokens[3].token_type, TokenType::Number(1.0));
+        assert_eq!(tokens[3].token_type, TokenType::Int(1));
         assert_eq!(tokens[4].token_type, TokenType::Semicolon);
         assert_eq!(tokens[5].token_type, TokenType::Eof);
-        assert!(!*rlox::HAD_ERROR.lock().unwrap());
+        assert!(scanner.errors().is_empty());
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let (tokens, errors) = Scanner::tokenize("1 @\n2");
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[0].token_type, TokenType::Int(1));
+        assert_eq!(tokens[1].token_type, TokenType::Int(2));
+        assert_eq!(tokens[2].token_type, TokenType::Eof);
+        assert_eq!(errors, vec![RloxError::Scan(1, Span::on_line(1, 3, 3), String::from("Unexpected character: @"))]);
+        // Errors are only returned, nothing is printed or flagged globally
+        assert!(!crate::rlox::HAD_ERROR.with(|had_error| had_error.get()));
+    }
+
+    #[test]
+    fn test_unexpected_characters_coalesced() {
+        let (tokens, errors) = Scanner::tokenize("@@@@ 1 # 2");
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(errors, vec![
+            RloxError::Scan(1, Span::on_line(1, 1, 4), String::from("Unexpected characters: @@@@")),
+            RloxError::Scan(1, Span::on_line(1, 8, 8), String::from("Unexpected character: #")),
+        ]);
+
+        let (tokens, errors) = Scanner::tokenize("a@#b");
+        assert_eq!(tokens[0].token_type, TokenType::Identifier(String::from("a")));
+        assert_eq!(tokens[1].token_type, TokenType::Identifier(String::from("b")));
+        assert_eq!(errors, vec![RloxError::Scan(1, Span::on_line(1, 2, 3), String::from("Unexpected characters: @#"))]);
+    }
+
+    #[test]
+    fn test_malformed_numbers() {
+        for (source, span) in [("1.", (1, 2)), ("1..2", (1, 4)), ("1.2.3;", (1, 5)), (".5.", (1, 3)), ("x = 12. + 1", (5, 7))] {
+            let (_, errors) = Scanner::tokenize(source);
+            assert_eq!(errors, vec![RloxError::Scan(1, Span::on_line(1, span.0, span.1), String::from("Malformed number literal."))], "{}", source);
+        }
+
+        let (tokens, errors) = Scanner::tokenize("1.0 1.5.floor; .5");
+        assert!(errors.is_empty());
+        assert_eq!(tokens[0].token_type, TokenType::Number(1.0));
+        assert_eq!(tokens[1].token_type, TokenType::Number(1.5));
+        assert_eq!(tokens[2].token_type, TokenType::Dot);
+        assert_eq!(tokens[5].token_type, TokenType::Number(0.5));
+    }
+
+    #[test]
+    fn test_unusual_whitespace() {
+        let (tokens, errors) = Scanner::tokenize("1\x0c+\x0b2\u{a0}*\u{2003}3\r\n;");
+        assert!(errors.is_empty());
+        assert_eq!(tokens.iter().map(|token| token.lexeme.as_str()).collect::<Vec<&str>>(), vec!["1", "+", "2", "*", "3", ";", ""]);
+        assert_eq!(tokens[5].line, 2);
+
+        let (_, errors) = Scanner::tokenize("@@\x0c@");
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn test_shebang() {
+        let (tokens, errors) = Scanner::tokenize("#!/usr/bin/env rlox\nprint 1;");
+        assert!(errors.is_empty());
+        assert_eq!(tokens.len(), 4);
+        assert_eq!((tokens[0].token_type.clone(), tokens[0].line), (TokenType::Print, 2));
+
+        let (tokens, errors) = Scanner::tokenize("#!rlox");
+        assert!(errors.is_empty());
+        assert_eq!(tokens.len(), 1);
+
+        // Only the very first line
+        let (_, errors) = Scanner::tokenize("\n#!/usr/bin/env rlox");
+        assert_eq!(errors.len(), 1);
+
+        let mut scanner = Scanner::new(String::from("#!/usr/bin/env rlox\nprint 1;"));
+        scanner.scan_tokens();
+        let tokens = scanner.rescan(TextEdit { start: 20, end: 20, text: String::from("x = ") });
+        assert_eq!(tokens, Scanner::tokenize("#!/usr/bin/env rlox\nx = print 1;").0);
+    }
+
+    #[test]
+    fn test_hash_comments() {
+        let mut scanner = Scanner::new(String::from("# comment\n1;"));
+        scanner.hash_comments = true;
+        let tokens = scanner.scan_tokens();
+        assert_eq!(tokens.iter().map(|token| token.token_type.clone()).collect::<Vec<TokenType>>(), vec![TokenType::Int(1), TokenType::Semicolon, TokenType::Eof]);
+        assert_eq!(tokens[0].line, 2);
+
+        let mut scanner = Scanner::new(String::from("a@# b"));
+        scanner.hash_comments = true;
+        scanner.scan_tokens();
+        assert_eq!(scanner.errors(), &[RloxError::Scan(1, Span::on_line(1, 2, 2), String::from("Unexpected character: @"))]);
+    }
+
+    #[test]
+    fn test_error_span_covers_whole_token() {
+        let mut scanner = Scanner::new(String::from("var x =\n  12345;"));
+        scanner.max_token_length = 3;
+        scanner.scan_tokens();
+        assert_eq!(scanner.errors(), &[RloxError::Scan(2, Span::on_line(2, 3, 7), String::from("Token too long."))]);
+
+        let (_, errors) = Scanner::tokenize("print \"abc");
+        assert_eq!(errors, vec![RloxError::Scan(1, Span::on_line(1, 7, 10), String::from("Unterminated string"))]);
+    }
+
+    #[test]
+    fn test_spans() {
+        let mut scanner = Scanner::new(String::from("var ab = \n  >= 1.5;"));
+        scanner.scan_tokens();
+        let spans = [(1, 1, 3), (1, 5, 6), (1, 8, 8), (2, 3, 4), (2, 6, 8), (2, 9, 9), (2, 10, 10)]
+            .map(|(line, start, end)| Span::on_line(line, start, end));
+        assert_eq!(scanner.spans(), &spans);
+    }
+
+    #[test]
+    fn test_multi_line_string_span() {
+        let mut scanner = Scanner::new(String::from("print \"ab\ncd\" ;"));
+        scanner.scan_tokens();
+        assert_eq!(scanner.spans()[1], Span { start_line: 1, start_column: 7, end_line: 2, end_column: 3 });
+        assert_eq!(scanner.spans()[2], Span::on_line(2, 5, 5));
+
+        let (_, errors) = Scanner::tokenize("x = \"ab\ncd");
+        assert_eq!(errors, vec![RloxError::Scan(2, Span { start_line: 1, start_column: 5, end_line: 2, end_column: 2 }, String::from("Unterminated string"))]);
     }
 
     #[test]
     fn test_error() {
         let mut scanner = Scanner::new(String::from("/* This is a \n unfinished block comment"));
         scanner.scan_tokens();
-        assert!(*rlox::HAD_ERROR.lock().unwrap());
+        assert_eq!(scanner.errors().len(), 1);
     }
 }
\ No newline at end of file
@@ -2,13 +2,126 @@ use crate::expressions::Expr;
 use crate::token::Token;
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Stmt {
     Expression(Expr),
-    Print(Expr),
+    // The print keyword, for its line, and the value
+    Print(Token, Option<Expr>),
     Var(Token, Expr),
-    Block(Vec<Stmt>),
-    //If(Expr, Box<Stmt>, Option<Box<Stmt>>),
-    //While(Expr, Box<Stmt>),
-    //Function(Token, Vec<Token>, Vec<Stmt>),
-    //Return(Token, Option<Expr>),
-}
\ No newline at end of file
+    VarGroup(Vec<(Token, Expr)>),
+    // The opening brace, for its line, and the statements
+    Block(Token, Vec<Stmt>),
+    Assert(Expr),
+    // try keyword, body, the name bound to the error message, and the catch block
+    Try(Token, Box<Stmt>, Token, Box<Stmt>),
+    Throw(Expr),
+    // The keyword, for its line, the condition and the branches
+    If(Token, Expr, Box<Stmt>, Option<Box<Stmt>>),
+    // Like If, the else branch runs when the loop ends without a break. The keyword is
+    // `for` when it comes from a for loop declaring its variables, each iteration then
+    // gets its own copy of them. The expression
+    // after the body is a for loop's increment, which runs even when a continue cuts
+    // the body short.
+    While(Token, Expr, Box<Stmt>, Option<Expr>, Option<Box<Stmt>>),
+    Break(Token),
+    Continue(Token),
+    // Name, parameters and body
+    Function(Token, Vec<Token>, Vec<Stmt>),
+    // Name, the superclass as a Variable and the methods, each a Function
+    Class(Token, Option<Expr>, Vec<Stmt>),
+    // The keyword, for its line, and the value, nil when left out
+    Return(Token, Option<Expr>),
+}
+
+impl Stmt {
+    // Line of the statement's principal token, the opening brace for blocks
+    pub fn line(&self) -> usize {
+        match self {
+            Stmt::Expression(expression) => expression.line(),
+            Stmt::Print(keyword, _) | Stmt::Block(keyword, _) | Stmt::Try(keyword, _, _, _) => keyword.line,
+            Stmt::Var(name, _) | Stmt::Function(name, _, _) | Stmt::Class(name, _, _) => name.line,
+            Stmt::VarGroup(declarations) => declarations.first().map_or(0, |(name, _)| name.line),
+            Stmt::Assert(expression) => expression.line(),
+            Stmt::Throw(expression) => expression.line(),
+            Stmt::If(keyword, _, _, _) | Stmt::While(keyword, _, _, _, _) => keyword.line,
+            Stmt::Break(keyword) | Stmt::Continue(keyword) | Stmt::Return(keyword, _) => keyword.line,
+        }
+    }
+
+    // Whether a function or class is declared anywhere inside, the only way a closure
+    // can capture the scopes the statement runs in
+    pub fn declares_function(&self) -> bool {
+        match self {
+            Stmt::Function(_, _, _) | Stmt::Class(_, _, _) => true,
+            Stmt::Block(_, statements) => statements.iter().any(Stmt::declares_function),
+            Stmt::Try(_, body, _, handler) => body.declares_function() || handler.declares_function(),
+            Stmt::If(_, _, body, else_branch) | Stmt::While(_, _, body, _, else_branch) => {
+                body.declares_function() || else_branch.as_ref().is_some_and(|else_branch| else_branch.declares_function())
+            }
+            _ => false,
+        }
+    }
+}
+
+// Lisp-style rendering in the same shape as Expr's, used by --parse-only
+impl std::fmt::Display for Stmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Stmt::Expression(expression) => write!(f, "(; {})", expression),
+            Stmt::Print(_, Some(expression)) => write!(f, "(print {})", expression),
+            Stmt::Print(_, None) => write!(f, "(print)"),
+            Stmt::Var(name, initializer) => write!(f, "(var {} {})", name.lexeme, initializer),
+            Stmt::VarGroup(declarations) => {
+                write!(f, "(var")?;
+                for (name, initializer) in declarations {
+                    write!(f, " {} {}", name.lexeme, initializer)?;
+                }
+                write!(f, ")")
+            }
+            Stmt::Block(_, statements) => {
+                write!(f, "(block")?;
+                for statement in statements {
+                    write!(f, " {}", statement)?;
+                }
+                write!(f, ")")
+            }
+            Stmt::Assert(condition) => write!(f, "(assert {})", condition),
+            Stmt::Try(_, body, name, handler) => write!(f, "(try {} {} {})", body, name.lexeme, handler),
+            Stmt::Throw(value) => write!(f, "(throw {})", value),
+            Stmt::If(_, condition, then_branch, Some(else_branch)) => write!(f, "(if {} {} {})", condition, then_branch, else_branch),
+            Stmt::If(_, condition, then_branch, None) => write!(f, "(if {} {})", condition, then_branch),
+            Stmt::While(_, condition, body, increment, else_branch) => {
+                write!(f, "(while {} {}", condition, body)?;
+                if let Some(increment) = increment {
+                    write!(f, " (increment {})", increment)?;
+                }
+                if let Some(else_branch) = else_branch {
+                    write!(f, " {}", else_branch)?;
+                }
+                write!(f, ")")
+            }
+            Stmt::Break(_) => write!(f, "(break)"),
+            Stmt::Continue(_) => write!(f, "(continue)"),
+            Stmt::Return(_, Some(value)) => write!(f, "(return {})", value),
+            Stmt::Return(_, None) => write!(f, "(return)"),
+            Stmt::Function(name, params, body) => {
+                let params = params.iter().map(|param| param.lexeme.as_str()).collect::<Vec<&str>>();
+                write!(f, "(fun {} ({})", name.lexeme, params.join(" "))?;
+                for statement in body {
+                    write!(f, " {}", statement)?;
+                }
+                write!(f, ")")
+            }
+            Stmt::Class(name, superclass, methods) => {
+                write!(f, "(class {}", name.lexeme)?;
+                if let Some(superclass) = superclass {
+                    write!(f, " < {}", superclass)?;
+                }
+                for method in methods {
+                    write!(f, " {}", method)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
@@ -2,8 +2,10 @@ use core::fmt;
 
 use crate::tokentype::TokenType;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Token {
+    #[cfg_attr(feature = "serde", serde(flatten))]
     pub token_type: TokenType,
     pub lexeme: String,
     pub line: usize,
@@ -19,6 +21,14 @@ impl Token {
     }
 }
 
+// Tokens are equal when they read the same, wherever they sit in the source, so
+// AST comparisons don't depend on line tracking. Check `line` explicitly when it matters.
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        self.token_type == other.token_type && self.lexeme == other.lexeme
+    }
+}
+
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?} {} {}", self.token_type, self.lexeme, self.line)
@@ -1,9 +1,10 @@
 use std::fmt;
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(tag = "type", content = "value"))]
 pub enum TokenType {
     // Single-character tokens.
-    LeftParen, RightParen, LeftBrace, RightBrace,
+    LeftParen, RightParen, LeftBrace, RightBrace, LeftBracket, RightBracket,
     Comma, Dot, Minus, Plus, Semicolon, Slash, Star,
     Colon, QuestionMark,
   
@@ -12,13 +13,16 @@ pub enum TokenType {
     Equal, EqualEqual,
     Greater, GreaterEqual,
     Less, LessEqual,
+    StarStar,
+    Ampersand, Pipe, Caret, LessLess, GreaterGreater,
   
     // Literals.
-    Identifier(String), String(String), Number(f64),
+    Identifier(String), String(String), Number(f64), Int(i64),
   
     // Keywords.
     And, Class, Else, False, For, Fun, If, Nil, Or,
     Print, Return, Super, This, True, Var, While,
+    Assert, Try, Catch, Throw, Break, Continue,
   
     Eof
 }
@@ -30,6 +34,8 @@ impl fmt::Display for TokenType {
             TokenType::RightParen => write!(f, ")"),
             TokenType::LeftBrace => write!(f, "{{"),
             TokenType::RightBrace => write!(f, "}}"),
+            TokenType::LeftBracket => write!(f, "["),
+            TokenType::RightBracket => write!(f, "]"),
             TokenType::Comma => write!(f, ","),
             TokenType::Dot => write!(f, "."),
             TokenType::Minus => write!(f, "-"),
@@ -37,6 +43,12 @@ impl fmt::Display for TokenType {
             TokenType::Semicolon => write!(f, ";"),
             TokenType::Slash => write!(f, "/"),
             TokenType::Star => write!(f, "*"),
+            TokenType::StarStar => write!(f, "**"),
+            TokenType::Ampersand => write!(f, "&"),
+            TokenType::Pipe => write!(f, "|"),
+            TokenType::Caret => write!(f, "^"),
+            TokenType::LessLess => write!(f, "<<"),
+            TokenType::GreaterGreater => write!(f, ">>"),
             TokenType::Colon => write!(f, ":"),
             TokenType::QuestionMark => write!(f, "?"),
             TokenType::Bang => write!(f, "!"),
@@ -50,6 +62,7 @@ impl fmt::Display for TokenType {
             TokenType::Identifier(identifier) => write!(f, "{}", identifier),
             TokenType::String(string) => write!(f, "{}", string),
             TokenType::Number(number) => write!(f, "{}", number),
+            TokenType::Int(int) => write!(f, "{}", int),
             TokenType::And => write!(f, "and"),
             TokenType::Class => write!(f, "class"),
             TokenType::Else => write!(f, "else"),
@@ -66,6 +79,12 @@ impl fmt::Display for TokenType {
             TokenType::True => write!(f, "true"),
             TokenType::Var => write!(f, "var"),
             TokenType::While => write!(f, "while"),
+            TokenType::Assert => write!(f, "assert"),
+            TokenType::Try => write!(f, "try"),
+            TokenType::Catch => write!(f, "catch"),
+            TokenType::Throw => write!(f, "throw"),
+            TokenType::Break => write!(f, "break"),
+            TokenType::Continue => write!(f, "continue"),
             TokenType::Eof => write!(f, "EOF"),
         }
     }
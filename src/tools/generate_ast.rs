@@ -19,5 +19,17 @@ fn define_ast(output_dir: String, base_name: String, types: Vec<String>) {
 
     let mut file = fs::File::create(path).unwrap();
 
-    file.write_all(b"use crate::token::Token;\n").unwrap();
+    file.write_all(b"use crate::token::Token;\n\n").unwrap();
+    file.write_all(b"#[derive(Debug, Clone, PartialEq)]\n").unwrap();
+    file.write_all(format!("pub enum {} {{\n", base_name).as_bytes()).unwrap();
+    for expr_type in types {
+        let (name, fields) = expr_type.split_once(':').unwrap();
+        let field_types = fields
+            .split(',')
+            .map(|field| field.split_whitespace().next().unwrap())
+            .collect::<Vec<&str>>()
+            .join(", ");
+        file.write_all(format!("    {}({}),\n", name.trim(), field_types).as_bytes()).unwrap();
+    }
+    file.write_all(b"}\n").unwrap();
 }
\ No newline at end of file
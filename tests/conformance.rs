@@ -0,0 +1,59 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use rlox::rlox::{run_source, Options};
+
+// Each tests/lox/NAME.lox runs through the library and its printed output,
+// followed by the error message if one was raised, must match NAME.expected
+fn fixtures() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("lox");
+    let mut fixtures = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "lox"))
+        .collect::<Vec<PathBuf>>();
+    fixtures.sort();
+    fixtures
+}
+
+fn run_fixture(path: &Path) -> String {
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let result = run_source(fs::read_to_string(path).unwrap(), &Options::default(), output.clone());
+    let mut printed = String::from_utf8(output.borrow().clone()).unwrap();
+    if let Err(err) = result {
+        printed.push_str(&format!("{}\n", err));
+    }
+    printed
+}
+
+// run_source runs on the caller's stack, and the default call depth is sized for a
+// main thread, bigger than the one the test harness gives each test
+const MAIN_THREAD_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+#[test]
+fn conformance() {
+    std::thread::Builder::new()
+        .stack_size(MAIN_THREAD_STACK_SIZE)
+        .spawn(run_fixtures)
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+fn run_fixtures() {
+    let fixtures = fixtures();
+    assert!(!fixtures.is_empty());
+
+    let mut failures = Vec::new();
+    for fixture in fixtures {
+        let expected = fs::read_to_string(fixture.with_extension("expected")).unwrap();
+        let actual = run_fixture(&fixture);
+        if actual != expected {
+            failures.push(format!("{}\n--- expected\n{}--- actual\n{}", fixture.display(), expected, actual));
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}